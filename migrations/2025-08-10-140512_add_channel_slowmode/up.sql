@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE channels ADD COLUMN slowmode INT4 NOT NULL DEFAULT 0;
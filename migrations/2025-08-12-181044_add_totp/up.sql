@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN totp_secret VARCHAR(64) DEFAULT NULL;
+
+CREATE TABLE recovery_codes (
+    uuid uuid PRIMARY KEY NOT NULL,
+    user_uuid uuid NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+    code_hash VARCHAR(512) NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE passkeys (
+    uuid uuid PRIMARY KEY NOT NULL,
+    user_uuid uuid NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+    credential_id VARCHAR(1024) NOT NULL UNIQUE,
+    name VARCHAR(32) NOT NULL,
+    credential TEXT NOT NULL,
+    created_at int8 NOT NULL,
+    last_used int8 DEFAULT NULL
+);
+
+CREATE INDEX idx_passkeys_user_uuid ON passkeys(user_uuid);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE admin_actions;
+DROP TABLE instance_settings;
+ALTER TABLE users DROP COLUMN disabled;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE instance_settings (
+    key VARCHAR(64) PRIMARY KEY NOT NULL,
+    value BOOLEAN NOT NULL
+);
+
+CREATE TABLE admin_actions (
+    uuid uuid PRIMARY KEY NOT NULL,
+    admin_uuid uuid REFERENCES users(uuid) ON DELETE SET NULL,
+    action VARCHAR(64) NOT NULL,
+    target_uuid uuid,
+    details VARCHAR(1024),
+    created_at int8 NOT NULL
+);
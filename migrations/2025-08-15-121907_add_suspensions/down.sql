@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE registration_blocks;
+ALTER TABLE users DROP COLUMN suspension_reason;
+ALTER TABLE users DROP COLUMN suspended_until;
+ALTER TABLE users DROP COLUMN suspended_at;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN suspended_at int8 DEFAULT NULL;
+ALTER TABLE users ADD COLUMN suspended_until int8 DEFAULT NULL;
+ALTER TABLE users ADD COLUMN suspension_reason VARCHAR(512) DEFAULT NULL;
+
+CREATE TABLE registration_blocks (
+    uuid uuid PRIMARY KEY NOT NULL,
+    kind VARCHAR(16) NOT NULL,
+    value VARCHAR(256) NOT NULL,
+    reason VARCHAR(512),
+    created_at int8 NOT NULL,
+    UNIQUE (kind, value)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE refresh_tokens DROP COLUMN last_used;
+ALTER TABLE refresh_tokens DROP COLUMN ip_address;
+ALTER TABLE refresh_tokens DROP COLUMN user_agent;
+ALTER TABLE refresh_tokens DROP COLUMN session_uuid;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE refresh_tokens ADD COLUMN session_uuid uuid NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE refresh_tokens ADD COLUMN user_agent VARCHAR(256);
+ALTER TABLE refresh_tokens ADD COLUMN ip_address VARCHAR(45);
+ALTER TABLE refresh_tokens ADD COLUMN last_used int8;
+UPDATE refresh_tokens SET last_used = created_at;
+ALTER TABLE refresh_tokens ALTER COLUMN last_used SET NOT NULL;
+ALTER TABLE refresh_tokens ALTER COLUMN session_uuid DROP DEFAULT;
+ALTER TABLE refresh_tokens ADD CONSTRAINT refresh_tokens_session_uuid_key UNIQUE (session_uuid);
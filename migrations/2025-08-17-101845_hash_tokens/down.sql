@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM refresh_tokens;
+ALTER TABLE access_tokens ALTER COLUMN token TYPE varchar(32);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Existing tokens are stored in plaintext and can't be rehashed without the pepper, everyone has to log in again
+DELETE FROM refresh_tokens;
+ALTER TABLE access_tokens ALTER COLUMN token TYPE varchar(64);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE used_refresh_tokens;
+ALTER TABLE refresh_tokens DROP COLUMN issued_at;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE refresh_tokens ADD COLUMN issued_at int8;
+UPDATE refresh_tokens SET issued_at = created_at;
+ALTER TABLE refresh_tokens ALTER COLUMN issued_at SET NOT NULL;
+CREATE TABLE used_refresh_tokens (
+    token VARCHAR(64) PRIMARY KEY NOT NULL,
+    session_uuid uuid NOT NULL REFERENCES refresh_tokens(session_uuid) ON DELETE CASCADE,
+    rotated_at int8 NOT NULL
+);
+CREATE INDEX used_refresh_tokens_session_uuid_idx ON used_refresh_tokens(session_uuid);
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE email_queue (
+    uuid uuid PRIMARY KEY NOT NULL,
+    recipient VARCHAR(100) NOT NULL,
+    subject VARCHAR(256) NOT NULL,
+    text_body TEXT NOT NULL,
+    html_body TEXT NOT NULL,
+    attempts int4 NOT NULL DEFAULT 0,
+    next_attempt_at int8 NOT NULL,
+    last_error VARCHAR(1024),
+    failed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at int8 NOT NULL
+);
+CREATE INDEX email_queue_next_attempt_at_idx ON email_queue(next_attempt_at) WHERE NOT failed;
+ALTER TABLE users ADD COLUMN locale VARCHAR(16);
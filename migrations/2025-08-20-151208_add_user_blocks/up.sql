@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE user_blocks (
+    blocker UUID NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+    blocked UUID NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+    blocked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (blocker, blocked),
+    CHECK (blocker <> blocked)
+);
+CREATE INDEX user_blocks_blocked_idx ON user_blocks(blocked);
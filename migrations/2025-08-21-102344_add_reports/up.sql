@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE reports (
+    uuid uuid PRIMARY KEY NOT NULL,
+    reporter_uuid uuid NOT NULL REFERENCES users(uuid),
+    kind VARCHAR(16) NOT NULL,
+    target_uuid uuid NOT NULL,
+    guild_uuid uuid REFERENCES guilds(uuid) ON DELETE CASCADE,
+    category VARCHAR(32) NOT NULL,
+    reason VARCHAR(1000),
+    message_author_uuid uuid REFERENCES users(uuid),
+    message_content VARCHAR(4000),
+    status VARCHAR(16) NOT NULL DEFAULT 'open',
+    resolver_uuid uuid REFERENCES users(uuid),
+    resolution_note VARCHAR(1000),
+    created_at int8 NOT NULL,
+    resolved_at int8
+);
+CREATE INDEX reports_guild_uuid_idx ON reports(guild_uuid, status);
+CREATE INDEX reports_status_idx ON reports(status);
+CREATE UNIQUE INDEX reports_open_unique_idx ON reports(reporter_uuid, target_uuid) WHERE status = 'open';
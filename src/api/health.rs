@@ -0,0 +1,200 @@
+//! `/api/health` Liveness and readiness probes for orchestrators
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use diesel_async::RunQueryDsl;
+use log::warn;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::{AppState, error::Error};
+
+/// A dependency that takes longer than this to respond counts as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Storage and mail checks call external services, their results are reused for this long
+const OPTIONAL_CHECK_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Last storage and mail results, the lock also makes concurrent probes wait for one check instead of each running their own
+static OPTIONAL_CHECKS: Mutex<Option<(Instant, Check, Check)>> = Mutex::const_new(None);
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Unavailable,
+    ShuttingDown,
+}
+
+#[derive(Serialize, Clone)]
+struct Check {
+    status: Status,
+    /// Unavailable dependencies that aren't required are reported without failing readiness
+    required: bool,
+    latency_ms: u64,
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, Check>,
+}
+
+impl IntoResponse for Health {
+    fn into_response(self) -> Response {
+        let code = match self.status {
+            Status::Ok => StatusCode::OK,
+            _ => StatusCode::SERVICE_UNAVAILABLE,
+        };
+
+        (code, Json(self)).into_response()
+    }
+}
+
+pub fn router() -> Router<&'static AppState> {
+    Router::new()
+        .route("/live", get(live))
+        .route("/ready", get(ready))
+}
+
+/// `GET /api/health/live` Responds as long as the server is able to handle requests
+///
+/// requires auth: no
+///
+/// ### Responses
+/// 200 Alive
+pub async fn live() -> impl IntoResponse {
+    Health {
+        status: Status::Ok,
+        checks: BTreeMap::new(),
+    }
+}
+
+/// `GET /api/health/ready` Checks the dependencies of the server, only route traffic here while it's ready
+///
+/// Storage and mail are reported but not required, uploads and emails fail or retry on their own without affecting the rest of the API.
+/// Their results are reused for 30 seconds and errors are only logged, so probing can't be used to hammer those services
+///
+/// requires auth: no
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "status": "ok",
+///     "checks": {
+///         "cache_database": { "status": "ok", "required": true, "latency_ms": 1 },
+///         "database": { "status": "ok", "required": true, "latency_ms": 2 },
+///         "mail": { "status": "ok", "required": false, "latency_ms": 0 },
+///         "storage": { "status": "unavailable", "required": false, "latency_ms": 2000 }
+///     }
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Ready
+///
+/// 503 A required dependency is unavailable or the server is shutting down
+pub async fn ready(State(app_state): State<&'static AppState>) -> impl IntoResponse {
+    if app_state.draining.is_cancelled() {
+        return Health {
+            status: Status::ShuttingDown,
+            checks: BTreeMap::new(),
+        };
+    }
+
+    let (database, cache_database, (storage, mail)) = tokio::join!(
+        check("database", true, async {
+            let mut conn = app_state.pool.get().await?;
+
+            diesel::sql_query("SELECT 1").execute(&mut conn).await?;
+
+            Ok(())
+        }),
+        check("cache_database", true, app_state.cache_pool.ping()),
+        optional_checks(app_state),
+    );
+
+    let checks = BTreeMap::from([
+        ("database", database),
+        ("cache_database", cache_database),
+        ("storage", storage),
+        ("mail", mail),
+    ]);
+
+    let status = match checks
+        .values()
+        .all(|check| !check.required || check.status == Status::Ok)
+    {
+        true => Status::Ok,
+        false => Status::Unavailable,
+    };
+
+    Health { status, checks }
+}
+
+/// Storage and mail results, checked again once they are older than `OPTIONAL_CHECK_LIFETIME`
+async fn optional_checks(app_state: &AppState) -> (Check, Check) {
+    let mut checks = OPTIONAL_CHECKS.lock().await;
+
+    if let Some((checked_at, storage, mail)) = &*checks
+        && checked_at.elapsed() < OPTIONAL_CHECK_LIFETIME
+    {
+        return (storage.clone(), mail.clone());
+    }
+
+    let (storage, mail) = tokio::join!(
+        check("storage", false, async {
+            app_state.bunny_storage.list("/").await?;
+
+            Ok(())
+        }),
+        check("mail", false, app_state.mail_client.check()),
+    );
+
+    *checks = Some((Instant::now(), storage.clone(), mail.clone()));
+
+    (storage, mail)
+}
+
+/// Errors are logged instead of returned, the endpoint is public
+async fn check(
+    name: &str,
+    required: bool,
+    check: impl Future<Output = Result<(), Error>>,
+) -> Check {
+    let start = Instant::now();
+
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    let status = match result {
+        Ok(Ok(())) => Status::Ok,
+        Ok(Err(error)) => {
+            warn!("readiness check {name} failed: {error}");
+            Status::Unavailable
+        }
+        Err(_) => {
+            warn!("readiness check {name} timed out");
+            Status::Unavailable
+        }
+    };
+
+    Check {
+        status,
+        required,
+        latency_ms,
+    }
+}
@@ -1,9 +1,15 @@
 //! `/api` Contains the entire API
 
-use axum::{Router, routing::get};
+use axum::{
+    Router,
+    middleware::{from_fn, from_fn_with_state},
+    routing::get,
+};
 
-use crate::AppState;
+use crate::{AppState, logging, telemetry};
 
+mod health;
+pub mod rate_limit;
 mod v1;
 mod versions;
 
@@ -11,4 +17,9 @@ pub fn router(path: &str, app_state: &'static AppState) -> Router<&'static AppSt
     Router::new()
         .route(&format!("{path}/versions"), get(versions::versions))
         .nest(&format!("{path}/v1"), v1::router(app_state))
+        .route_layer(from_fn_with_state(app_state, rate_limit::rate_limit_layer))
+        .route_layer(from_fn(telemetry::track_requests))
+        .route_layer(from_fn(logging::trace_requests))
+        // Added after the layers, probes aren't rate limited and don't flood the logs
+        .nest(&format!("{path}/health"), health::router())
 }
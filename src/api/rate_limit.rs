@@ -0,0 +1,191 @@
+//! Token bucket rate limiting backed by the cache database
+
+use std::{sync::LazyLock, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
+use log::error;
+use redis::Script;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::{CurrentUser, check_access_token},
+    cache::{Cache, CacheKey},
+    config::Bucket,
+    error::Error,
+    telemetry,
+    utils::ClientIp,
+};
+
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Refills the bucket based on the time since the last request and takes a token if one is available.
+///
+/// Uses the cache database clock so every backend instance agrees on the bucket state.
+static TOKEN_BUCKET: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+local capacity = tonumber(ARGV[1])
+local refill_rate = tonumber(ARGV[2])
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) / 1000 * refill_rate)
+
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+
+local reset = math.ceil((capacity - tokens) / refill_rate)
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', tostring(now))
+redis.call('EXPIRE', KEYS[1], reset + 1)
+
+local retry_after = 0
+if allowed == 0 then
+    retry_after = math.ceil((1 - tokens) / refill_rate)
+end
+
+return {allowed, math.floor(tokens), reset, retry_after}
+"#,
+    )
+});
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset: u64,
+    /// Seconds until the next request is allowed, 0 if `allowed` is true
+    pub retry_after: u64,
+}
+
+impl RateLimitStatus {
+    fn append_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(X_RATELIMIT_RESET, HeaderValue::from(self.reset));
+
+        if !self.allowed {
+            headers.insert(RETRY_AFTER, HeaderValue::from(self.retry_after));
+        }
+    }
+}
+
+/// Takes a token from the bucket stored at `key`
+pub async fn take_token(
+    cache_pool: &Cache,
+    key: CacheKey,
+    bucket: Bucket,
+) -> Result<RateLimitStatus, Error> {
+    let start = Instant::now();
+
+    let (allowed, remaining, reset, retry_after): (u8, u32, u64, u64) = TOKEN_BUCKET
+        .key(key.to_string())
+        .arg(bucket.capacity)
+        .arg(bucket.refill_rate)
+        .invoke_async(&mut cache_pool.connection())
+        .await?;
+
+    telemetry::record_cache_command("EVALSHA", start);
+
+    Ok(RateLimitStatus {
+        allowed: allowed == 1,
+        limit: bucket.capacity,
+        remaining,
+        reset,
+        retry_after,
+    })
+}
+
+/// Rate limits requests per route, authenticated requests are limited per user and anonymous requests per IP address
+pub async fn rate_limit_layer(
+    State(app_state): State<&'static AppState>,
+    ClientIp(ip): ClientIp,
+    matched_path: Option<MatchedPath>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    if !app_state.config.rate_limit.enabled {
+        return Ok(next.run(req).await);
+    }
+
+    let base_path = app_state
+        .config
+        .web
+        .backend_url
+        .path()
+        .trim_end_matches('/');
+
+    let route = matched_path
+        .as_ref()
+        .map(|path| path.as_str())
+        .map(|path| path.strip_prefix(base_path).unwrap_or(path))
+        .unwrap_or("/")
+        .to_string();
+
+    let bucket = app_state.config.rate_limit.bucket(&route);
+
+    let mut user_uuid: Option<Uuid> = None;
+
+    if let Some(TypedHeader(auth)) = auth {
+        let mut conn = app_state.pool.get().await?;
+
+        user_uuid = check_access_token(app_state, auth.token(), &mut conn)
+            .await
+            .ok();
+    }
+
+    let key = match user_uuid {
+        Some(uuid) => CacheKey::RateLimit(format!("user:{uuid}:{route}")),
+        None => CacheKey::RateLimit(format!("ip:{ip}:{route}")),
+    };
+
+    let status = match take_token(&app_state.cache_pool, key, bucket).await {
+        Ok(status) => status,
+        Err(e) => {
+            error!("rate limiter unavailable: {e}");
+            return Ok(next.run(req).await);
+        }
+    };
+
+    let mut response = if status.allowed {
+        if let Some(uuid) = user_uuid {
+            // Saves the auth layer from checking the access token again
+            req.extensions_mut().insert(CurrentUser(uuid));
+        }
+
+        next.run(req).await
+    } else {
+        Error::TooManyRequests(format!(
+            "Too many requests, try again in {} seconds",
+            status.retry_after
+        ))
+        .into_response()
+    };
+
+    status.append_headers(response.headers_mut());
+
+    Ok(response)
+}
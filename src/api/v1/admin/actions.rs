@@ -0,0 +1,54 @@
+//! `/api/v1/admin/actions` Audit log of administrator actions
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+
+use crate::{
+    AppState,
+    error::Error,
+    objects::{AdminAction, StartAmountQuery},
+};
+
+/// `GET /api/v1/admin/actions` Returns actions taken by administrators, newest first
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Query Parameters
+/// start, amount
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "uuid": "0198a7f2-3c1d-7e4b-a2f6-9d8c7b6a5e4f",
+///         "admin_uuid": "155d2291-fb23-46bd-a656-ae7c5d8218e6",
+///         "action": "disable_user",
+///         "target_uuid": "d48a3317-7b4d-443f-a250-ea9ab2bb8661",
+///         "details": null,
+///         "created_at": 1755163912
+///     }
+/// ]);
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Query(request_query): Query<StartAmountQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = request_query.start.unwrap_or(0);
+
+    let amount = request_query.amount.unwrap_or(50);
+
+    if amount > 100 {
+        return Err(Error::BadRequest("Amount can't exceed 100".to_string()));
+    }
+
+    let admin_actions =
+        AdminAction::fetch_amount(&mut app_state.pool.get().await?, start, amount).await?;
+
+    Ok((StatusCode::OK, Json(admin_actions)))
+}
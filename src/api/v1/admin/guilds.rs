@@ -0,0 +1,52 @@
+//! `/api/v1/admin/guilds` Guild moderation endpoints
+
+use axum::{
+    Extension,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AdminAction, Guild},
+};
+
+/// `DELETE /api/v1/admin/guilds/{uuid}` Deletes a guild
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found
+///
+pub async fn delete(
+    State(app_state): State<&'static AppState>,
+    Path(guild_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let guild = Guild::fetch_one(&mut conn, guild_uuid).await?;
+
+    let name = guild.name.clone();
+
+    guild.delete(&mut conn, app_state).await?;
+
+    AdminAction::record(
+        &mut conn,
+        uuid,
+        "delete_guild",
+        Some(guild_uuid),
+        Some(name),
+    )
+    .await?;
+
+    Ok(StatusCode::OK)
+}
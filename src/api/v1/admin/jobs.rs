@@ -0,0 +1,58 @@
+//! `/api/v1/admin/jobs` Status of periodic maintenance jobs
+
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+
+use crate::{
+    AppState,
+    error::Error,
+    jobs::{JOBS, JobStatus},
+};
+
+#[derive(Serialize)]
+struct JobInformation {
+    name: &'static str,
+    interval: u64,
+    last_run: Option<JobStatus>,
+}
+
+/// `GET /api/v1/admin/jobs` Returns every job with the result of its last run
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "name": "prune_sessions",
+///         "interval": 600,
+///         "last_run": {
+///             "started_at": 1755504000,
+///             "duration_ms": 12,
+///             "success": true,
+///             "summary": "logged out 3 expired or idle sessions",
+///             "runner": "9f2c4e1a7b3d5f60"
+///         }
+///     },
+///     {
+///         "name": "cleanup_uploads",
+///         "interval": 86400,
+///         "last_run": null
+///     }
+/// ]);
+/// ```
+pub async fn get(State(app_state): State<&'static AppState>) -> Result<impl IntoResponse, Error> {
+    let mut jobs = vec![];
+
+    for job in JOBS {
+        jobs.push(JobInformation {
+            name: job.name,
+            interval: job.interval,
+            last_run: job.status(&app_state.cache_pool).await,
+        });
+    }
+
+    Ok((StatusCode::OK, Json(jobs)))
+}
@@ -0,0 +1,61 @@
+//! `/api/v1/admin` Instance administration endpoints
+
+use axum::{
+    Extension, Router,
+    extract::{Request, State},
+    middleware::{Next, from_fn_with_state},
+    response::IntoResponse,
+    routing::{delete, get, patch, post},
+};
+use uuid::Uuid;
+
+use crate::{AppState, api::v1::auth::CurrentUser, error::Error, objects::InstancePermissions};
+
+mod actions;
+mod guilds;
+mod jobs;
+mod registration_blocks;
+mod reports;
+mod settings;
+mod users;
+
+pub fn router(app_state: &'static AppState) -> Router<&'static AppState> {
+    Router::new()
+        .route("/users", get(users::get))
+        .route("/users/{uuid}", get(users::uuid::get))
+        .route(
+            "/users/{uuid}/verify-email",
+            post(users::uuid::verify_email),
+        )
+        .route("/users/{uuid}/disable", post(users::uuid::disable))
+        .route("/users/{uuid}/enable", post(users::uuid::enable))
+        .route("/users/{uuid}/logout", post(users::uuid::logout))
+        .route("/users/{uuid}/suspend", post(users::uuid::suspend))
+        .route("/users/{uuid}/suspend", delete(users::uuid::unsuspend))
+        .route("/registration-blocks", get(registration_blocks::get))
+        .route("/registration-blocks", post(registration_blocks::post))
+        .route(
+            "/registration-blocks/{uuid}",
+            delete(registration_blocks::delete),
+        )
+        .route("/guilds/{uuid}", delete(guilds::delete))
+        .route("/reports", get(reports::get))
+        .route("/reports/{uuid}", patch(reports::patch))
+        .route("/settings", get(settings::get))
+        .route("/settings", patch(settings::patch))
+        .route("/actions", get(actions::get))
+        .route("/jobs", get(jobs::get))
+        .layer(from_fn_with_state(app_state, check_admin_layer))
+}
+
+/// Only lets instance administrators through, has to run after the auth layer
+pub async fn check_admin_layer(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, Error> {
+    InstancePermissions::check_admin(&mut app_state.pool.get().await?, uuid).await?;
+
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,125 @@
+//! `/api/v1/admin/registration-blocks` Email and IP address registration blocks
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AdminAction, RegistrationBlock},
+};
+
+#[derive(Deserialize)]
+pub struct NewRegistrationBlock {
+    kind: String,
+    value: String,
+    reason: Option<String>,
+}
+
+/// `GET /api/v1/admin/registration-blocks` Returns all registration blocks
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "uuid": "0198b0d1-8e2f-7a3c-b4d5-e6f708192a3b",
+///         "kind": "email",
+///         "value": "@spam.example",
+///         "reason": "Disposable email provider",
+///         "created_at": 1755163912
+///     }
+/// ]);
+/// ```
+pub async fn get(State(app_state): State<&'static AppState>) -> Result<impl IntoResponse, Error> {
+    let registration_blocks =
+        RegistrationBlock::fetch_all(&mut app_state.pool.get().await?).await?;
+
+    Ok((StatusCode::OK, Json(registration_blocks)))
+}
+
+/// `POST /api/v1/admin/registration-blocks` Blocks registrations from an email address or IP address
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// `kind` is either `email` or `ip`, email values starting with `@` block the whole domain.
+/// Blocking a value that is already blocked returns the existing block
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "kind": "ip",
+///     "value": "203.0.113.7",
+///     "reason": "Ban evasion"
+/// });
+/// ```
+pub async fn post(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(new_block): Json<NewRegistrationBlock>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let registration_block = RegistrationBlock::new(
+        &mut conn,
+        new_block.kind.clone(),
+        new_block.value.clone(),
+        new_block.reason,
+    )
+    .await?;
+
+    AdminAction::record(
+        &mut conn,
+        uuid,
+        "block_registration",
+        None,
+        Some(format!("{}={}", new_block.kind, new_block.value)),
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(registration_block)))
+}
+
+/// `DELETE /api/v1/admin/registration-blocks/{uuid}` Removes a registration block
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found
+///
+pub async fn delete(
+    State(app_state): State<&'static AppState>,
+    Path(block_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    RegistrationBlock::delete(&mut conn, block_uuid).await?;
+
+    AdminAction::record(
+        &mut conn,
+        uuid,
+        "unblock_registration",
+        Some(block_uuid),
+        None,
+    )
+    .await?;
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,113 @@
+//! `/api/v1/admin/reports` Instance report queue
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AdminAction, Report, ReportQuery, ReportResolution},
+};
+
+/// `GET /api/v1/admin/reports` Returns every report on the instance, newest first
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Query Parameters
+/// status: `open`, `actioned` or `dismissed`
+///
+/// start, amount
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "uuid": "0198c3a1-5b2e-7f40-9c1d-2e3f4a5b6c7d",
+///         "reporter_uuid": "155d2291-fb23-46bd-a656-ae7c5d8218e6",
+///         "kind": "message",
+///         "target_uuid": "01971976-8618-74c0-b040-7ffbc44823f6",
+///         "guild_uuid": "5ba61ec7-5f97-43e1-89a5-d4693c155612",
+///         "category": "harassment",
+///         "reason": "Keeps insulting people",
+///         "message_author_uuid": "d48a3317-7b4d-443f-a250-ea9ab2bb8661",
+///         "message_content": "reported message",
+///         "status": "open",
+///         "resolver_uuid": null,
+///         "resolution_note": null,
+///         "created_at": 1755771824,
+///         "resolved_at": null
+///     }
+/// ]);
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Query(report_query): Query<ReportQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = report_query.start.unwrap_or(0);
+
+    let amount = report_query.amount.unwrap_or(50);
+
+    if amount > 100 {
+        return Err(Error::BadRequest("Amount can't exceed 100".to_string()));
+    }
+
+    let reports = Report::fetch_amount(
+        &mut app_state.pool.get().await?,
+        None,
+        report_query.status,
+        start,
+        amount,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(reports)))
+}
+
+/// `PATCH /api/v1/admin/reports/{uuid}` Changes the status of a report
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "status": "actioned",
+///     "note": "Member was banned"
+/// });
+/// ```
+///
+/// `status` is one of `open`, `actioned` or `dismissed`, reopening a report clears its resolution
+pub async fn patch(
+    State(app_state): State<&'static AppState>,
+    Path(report_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(resolution): Json<ReportResolution>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let mut report = Report::fetch_one(&mut conn, report_uuid).await?;
+
+    let status = resolution.status.clone();
+
+    report.resolve(&mut conn, uuid, resolution).await?;
+
+    AdminAction::record(
+        &mut conn,
+        uuid,
+        "resolve_report",
+        Some(report_uuid),
+        Some(status),
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
@@ -0,0 +1,98 @@
+//! `/api/v1/admin/settings` Runtime instance settings
+
+use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AdminAction, InstanceSettings},
+};
+
+#[derive(Deserialize)]
+pub struct NewSettings {
+    registration: Option<bool>,
+    require_email_verification: Option<bool>,
+}
+
+/// `GET /api/v1/admin/settings` Returns instance settings
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "registration": true,
+///     "require_email_verification": false
+/// });
+/// ```
+pub async fn get(State(app_state): State<&'static AppState>) -> Result<impl IntoResponse, Error> {
+    let instance_settings = InstanceSettings::get(
+        &mut app_state.pool.get().await?,
+        &app_state.cache_pool,
+        &app_state.config,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(instance_settings)))
+}
+
+/// `PATCH /api/v1/admin/settings` Changes instance settings, overrides the values from the config
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "registration": false
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "registration": false,
+///     "require_email_verification": false
+/// });
+/// ```
+pub async fn patch(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(new_settings): Json<NewSettings>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let changes = [
+        ("registration", new_settings.registration),
+        (
+            "require_email_verification",
+            new_settings.require_email_verification,
+        ),
+    ];
+
+    for (key, value) in changes {
+        if let Some(value) = value {
+            InstanceSettings::set(&mut conn, &app_state.cache_pool, key, value).await?;
+
+            AdminAction::record(
+                &mut conn,
+                uuid,
+                "update_setting",
+                None,
+                Some(format!("{key}={value}")),
+            )
+            .await?;
+        }
+    }
+
+    let instance_settings =
+        InstanceSettings::get(&mut conn, &app_state.cache_pool, &app_state.config).await?;
+
+    Ok((StatusCode::OK, Json(instance_settings)))
+}
@@ -0,0 +1,69 @@
+//! `/api/v1/admin/users` User administration endpoints
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+use crate::{AppState, error::Error, objects::AdminUser};
+
+pub mod uuid;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    query: Option<String>,
+    start: Option<i64>,
+    amount: Option<i64>,
+}
+
+/// `GET /api/v1/admin/users` Lists and searches users on this instance
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Query Parameters
+/// query: matches usernames and email addresses
+///
+/// start, amount
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "uuid": "155d2291-fb23-46bd-a656-ae7c5d8218e6",
+///         "username": "user1",
+///         "display_name": "Nullable Name",
+///         "email": "user1@example.com",
+///         "email_verified": true,
+///         "disabled": false,
+///         "suspension": null,
+///         "administrator": true
+///     }
+/// ]);
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Query(search_query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = search_query.start.unwrap_or(0);
+
+    let amount = search_query.amount.unwrap_or(50);
+
+    if amount > 100 {
+        return Err(Error::BadRequest("Amount can't exceed 100".to_string()));
+    }
+
+    let users = AdminUser::fetch_amount(
+        &mut app_state.pool.get().await?,
+        search_query.query.filter(|query| !query.is_empty()),
+        start,
+        amount,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(users)))
+}
@@ -0,0 +1,273 @@
+//! `/api/v1/admin/users/{uuid}` Specific user administration endpoints
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AdminAction, AdminUser, RegistrationBlock, Suspension},
+};
+
+#[derive(Deserialize)]
+pub struct SuspendInformation {
+    reason: Option<String>,
+    /// Unix timestamp the suspension ends at, suspends indefinitely if not set
+    until: Option<i64>,
+    /// Also blocks the email address of the user from registering again
+    #[serde(default)]
+    block_email: bool,
+}
+
+/// `GET /api/v1/admin/users/{uuid}` Returns a user with account state
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "uuid": "155d2291-fb23-46bd-a656-ae7c5d8218e6",
+///     "username": "user1",
+///     "display_name": "Nullable Name",
+///     "email": "user1@example.com",
+///     "email_verified": true,
+///     "disabled": false,
+///     "suspension": null,
+///     "administrator": true
+/// });
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+) -> Result<impl IntoResponse, Error> {
+    let user = AdminUser::fetch_one(&mut app_state.pool.get().await?, user_uuid).await?;
+
+    Ok((StatusCode::OK, Json(user)))
+}
+
+/// `POST /api/v1/admin/users/{uuid}/verify-email` Marks the email address of a user as verified
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success, returns the updated user
+///
+/// 404 Not Found
+///
+pub async fn verify_email(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let mut user = AdminUser::fetch_one(&mut conn, user_uuid).await?;
+
+    user.verify_email(&mut conn, &app_state.cache_pool).await?;
+
+    AdminAction::record(&mut conn, uuid, "verify_email", Some(user_uuid), None).await?;
+
+    Ok((StatusCode::OK, Json(user)))
+}
+
+/// `POST /api/v1/admin/users/{uuid}/disable` Disables an account and logs it out everywhere
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success, returns the updated user
+///
+/// 400 Can't disable your own account
+///
+/// 404 Not Found
+///
+pub async fn disable(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    if user_uuid == uuid {
+        return Err(Error::BadRequest(
+            "You can't disable your own account".to_string(),
+        ));
+    }
+
+    let mut conn = app_state.pool.get().await?;
+
+    let mut user = AdminUser::fetch_one(&mut conn, user_uuid).await?;
+
+    user.set_disabled(&mut conn, &app_state.cache_pool, true)
+        .await?;
+
+    AdminAction::record(&mut conn, uuid, "disable_user", Some(user_uuid), None).await?;
+
+    Ok((StatusCode::OK, Json(user)))
+}
+
+/// `POST /api/v1/admin/users/{uuid}/enable` Enables a disabled account
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success, returns the updated user
+///
+/// 404 Not Found
+///
+pub async fn enable(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let mut user = AdminUser::fetch_one(&mut conn, user_uuid).await?;
+
+    user.set_disabled(&mut conn, &app_state.cache_pool, false)
+        .await?;
+
+    AdminAction::record(&mut conn, uuid, "enable_user", Some(user_uuid), None).await?;
+
+    Ok((StatusCode::OK, Json(user)))
+}
+
+/// `POST /api/v1/admin/users/{uuid}/logout` Logs a user out on all devices
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found
+///
+pub async fn logout(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let user = AdminUser::fetch_one(&mut conn, user_uuid).await?;
+
+    user.logout_everywhere(&mut conn, &app_state.cache_pool)
+        .await?;
+
+    AdminAction::record(&mut conn, uuid, "force_logout", Some(user_uuid), None).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// `POST /api/v1/admin/users/{uuid}/suspend` Suspends a user from the instance
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// Revokes all sessions of the user and disconnects their sockets
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "reason": "Spam",
+///     "until": 1755768712,
+///     "block_email": true
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "suspended_at": 1755163912,
+///     "suspended_until": 1755768712,
+///     "reason": "Spam"
+/// });
+/// ```
+pub async fn suspend(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(suspend_information): Json<SuspendInformation>,
+) -> Result<impl IntoResponse, Error> {
+    if user_uuid == uuid {
+        return Err(Error::BadRequest(
+            "You can't suspend your own account".to_string(),
+        ));
+    }
+
+    let mut conn = app_state.pool.get().await?;
+
+    let user = AdminUser::fetch_one(&mut conn, user_uuid).await?;
+
+    let suspension = Suspension::suspend(
+        &mut conn,
+        &app_state.cache_pool,
+        user_uuid,
+        suspend_information.reason.clone(),
+        suspend_information.until,
+    )
+    .await?;
+
+    if suspend_information.block_email {
+        RegistrationBlock::new(
+            &mut conn,
+            "email".to_string(),
+            user.email,
+            suspend_information.reason.clone(),
+        )
+        .await?;
+    }
+
+    AdminAction::record(
+        &mut conn,
+        uuid,
+        "suspend_user",
+        Some(user_uuid),
+        suspend_information.reason,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(suspension)))
+}
+
+/// `DELETE /api/v1/admin/users/{uuid}/suspend` Lifts the suspension of a user
+///
+/// requires auth: yes
+///
+/// requires admin: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found
+///
+pub async fn unsuspend(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    AdminUser::fetch_one(&mut conn, user_uuid).await?;
+
+    Suspension::lift(&mut conn, user_uuid).await?;
+
+    AdminAction::record(&mut conn, uuid, "unsuspend_user", Some(user_uuid), None).await?;
+
+    Ok(StatusCode::OK)
+}
@@ -1,49 +1,90 @@
-//! `/api/v1/auth/devices` Returns list of logged in devices
+//! `/api/v1/auth/devices` Endpoints for listing and renaming logged in devices
 
-use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
-use diesel::{ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper};
-use diesel_async::RunQueryDsl;
-use serde::Serialize;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
+use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::{
-    AppState,
-    api::v1::auth::CurrentUser,
-    error::Error,
-    schema::refresh_tokens::{self, dsl},
-};
-
-#[derive(Serialize, Selectable, Queryable)]
-#[diesel(table_name = refresh_tokens)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
-struct Device {
-    device_name: String,
-    created_at: i64,
-}
+use crate::{AppState, api::v1::auth::CurrentUser, error::Error, objects::Session};
 
 /// `GET /api/v1/auth/devices` Returns list of logged in devices
 ///
-/// requires auth: no
+/// requires auth: yes
 ///
 /// ### Response Example
 /// ```
 /// json!([
 ///     {
-///         "device_name": "My Device!"
-///         "created_at": "1752418856"
+///         "uuid": "0198a7e2-3c4b-7d1e-9f20-5b6c7d8e9f01",
+///         "device_name": "My Device!",
+///         "user_agent": "Mozilla/5.0 (X11; Linux x86_64; rv:141.0) Gecko/20100101 Firefox/141.0",
+///         "ip_address": "203.0.113.7",
+///         "created_at": 1752418856,
+///         "last_used": 1752422456,
+///         "current": true
 ///     }
-///     
 /// ]);
 /// ```
 pub async fn get(
     State(app_state): State<&'static AppState>,
     Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let current_session = Session::current_uuid(&mut conn, app_state, auth.token()).await?;
+
+    let sessions = Session::fetch_all(&mut conn, uuid, Some(current_session)).await?;
+
+    Ok((StatusCode::OK, Json(sessions)))
+}
+
+#[derive(Deserialize)]
+pub struct DeviceInformation {
+    device_name: String,
+}
+
+/// `PATCH /api/v1/auth/devices/{uuid}` Renames a logged in device
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "device_name": "Work laptop"
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Success
+///
+/// 400 Bad Request
+///
+/// 404 Not Found
+///
+pub async fn patch(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Path(session_uuid): Path<Uuid>,
+    Json(device_information): Json<DeviceInformation>,
 ) -> Result<impl IntoResponse, Error> {
-    let devices: Vec<Device> = dsl::refresh_tokens
-        .filter(dsl::uuid.eq(uuid))
-        .select(Device::as_select())
-        .get_results(&mut app_state.pool.get().await?)
-        .await?;
+    let mut conn = app_state.pool.get().await?;
+
+    Session::rename(
+        &mut conn,
+        uuid,
+        session_uuid,
+        device_information.device_name,
+    )
+    .await?;
 
-    Ok((StatusCode::OK, Json(devices)))
+    Ok(StatusCode::OK)
 }
@@ -1,25 +1,22 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
 use argon2::{PasswordHash, PasswordVerifier};
 use axum::{
     Json,
     extract::State,
-    http::{HeaderValue, StatusCode},
-    response::IntoResponse,
+    http::StatusCode,
+    response::{IntoResponse, Response},
 };
-use diesel::{ExpressionMethods, QueryDsl, dsl::insert_into};
+use diesel::{ExpressionMethods, QueryDsl};
 use diesel_async::RunQueryDsl;
-use serde::Deserialize;
+use log::error;
+use serde::{Deserialize, Serialize};
 
-use super::Response;
+use super::new_session;
 use crate::{
     AppState,
     error::Error,
+    objects::{AttemptSubject, LoginAttempts, LoginChallenge, Passkey, Totp},
     schema::*,
-    utils::{
-        PASSWORD_REGEX, generate_device_name, generate_token, new_refresh_token_cookie,
-        user_uuid_from_identifier,
-    },
+    utils::{ClientInfo, PASSWORD_REGEX, user_uuid_from_identifier},
 };
 
 #[derive(Deserialize)]
@@ -28,19 +25,77 @@ pub struct LoginInformation {
     password: String,
 }
 
+/// Returned instead of a session when the account requires a second factor
+#[derive(Serialize)]
+pub struct ChallengeResponse {
+    pub challenge_token: String,
+    pub methods: Vec<String>,
+}
+
+/// `POST /api/v1/auth/login` Logs in with username or email and password
+///
+/// requires auth: no
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "username": "user1",
+///     "password": "1608c17a27f6ae3891c23d680c73ae91528f20a54dcf4973e2c3126b9734f48b7253047f2395b51bb8a44a6daa188003"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "access_token": "c3b9a7e1f2d5c6b8a0d3e7f4a2b1c9d8",
+///     "device_name": "Clever Fox"
+/// });
+/// ```
+///
+/// If the account has TOTP enabled or passkeys registered a challenge is returned instead,
+/// exchange it for a session with `POST /api/v1/auth/login/totp` or `POST /api/v1/auth/login/passkey`
+/// ```
+/// json!({
+///     "challenge_token": "a3f7e29c1b8d0456e2c9f83b7a1d6e4f5028c3b9a7e1f2d5c6b8a0d3e7f4a2b",
+///     "methods": ["totp", "recovery_code", "passkey"]
+/// });
+/// ```
 pub async fn response(
     State(app_state): State<&'static AppState>,
+    client: ClientInfo,
     Json(login_information): Json<LoginInformation>,
-) -> Result<impl IntoResponse, Error> {
+) -> Result<Response, Error> {
     if !PASSWORD_REGEX.is_match(&login_information.password) {
         return Err(Error::BadRequest("Bad password".to_string()));
     }
 
+    let login_protection = &app_state.config.login_protection;
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Ip(client.ip))
+        .await?
+        .check(login_protection)?;
+
     use users::dsl;
 
     let mut conn = app_state.pool.get().await?;
 
-    let uuid = user_uuid_from_identifier(&mut conn, &login_information.username).await?;
+    let uuid = match user_uuid_from_identifier(&mut conn, &login_information.username).await {
+        Ok(uuid) => uuid,
+        Err(error) => {
+            LoginAttempts::register_failure(
+                &app_state.cache_pool,
+                login_protection,
+                AttemptSubject::Ip(client.ip),
+            )
+            .await?;
+
+            return Err(error);
+        }
+    };
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Account(uuid))
+        .await?
+        .check(login_protection)?;
 
     let database_password: String = dsl::users
         .filter(dsl::uuid.eq(uuid))
@@ -56,57 +111,57 @@ pub async fn response(
         .verify_password(login_information.password.as_bytes(), &parsed_hash)
         .is_err()
     {
+        LoginAttempts::register_failure(
+            &app_state.cache_pool,
+            login_protection,
+            AttemptSubject::Ip(client.ip),
+        )
+        .await?;
+
+        let locked = LoginAttempts::register_failure(
+            &app_state.cache_pool,
+            login_protection,
+            AttemptSubject::Account(uuid),
+        )
+        .await?;
+
+        if locked
+            && let Err(error) = LoginAttempts::send_lockout_email(&mut conn, app_state, uuid).await
+        {
+            error!("failed to send lockout email: {error}");
+        }
+
         return Err(Error::Unauthorized(
             "Wrong username or password".to_string(),
         ));
     }
 
-    let refresh_token = generate_token::<32>()?;
-    let access_token = generate_token::<16>()?;
-
-    let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    LoginAttempts::clear(&app_state.cache_pool, AttemptSubject::Account(uuid)).await?;
 
-    use refresh_tokens::dsl as rdsl;
+    let mut methods = vec![];
 
-    let device_name = generate_device_name();
-
-    insert_into(refresh_tokens::table)
-        .values((
-            rdsl::token.eq(&refresh_token),
-            rdsl::uuid.eq(uuid),
-            rdsl::created_at.eq(current_time),
-            rdsl::device_name.eq(&device_name),
-        ))
-        .execute(&mut conn)
-        .await?;
+    if Totp::is_enabled(&mut conn, uuid).await? {
+        methods.push("totp".to_string());
+        methods.push("recovery_code".to_string());
+    }
 
-    use access_tokens::dsl as adsl;
+    // Registered passkeys act as a second factor on their own, TOTP isn't required for them
+    if Passkey::has_any(&mut conn, uuid).await? {
+        methods.push("passkey".to_string());
+    }
 
-    insert_into(access_tokens::table)
-        .values((
-            adsl::token.eq(&access_token),
-            adsl::refresh_token.eq(&refresh_token),
-            adsl::uuid.eq(uuid),
-            adsl::created_at.eq(current_time),
-        ))
-        .execute(&mut conn)
-        .await?;
+    if !methods.is_empty() {
+        let login_challenge = LoginChallenge::new(&app_state.cache_pool, uuid).await?;
+
+        return Ok((
+            StatusCode::OK,
+            Json(ChallengeResponse {
+                challenge_token: login_challenge.token,
+                methods,
+            }),
+        )
+            .into_response());
+    }
 
-    let mut response = (
-        StatusCode::OK,
-        Json(Response {
-            access_token,
-            device_name,
-        }),
-    )
-        .into_response();
-
-    response.headers_mut().append(
-        "Set-Cookie",
-        HeaderValue::from_str(
-            &new_refresh_token_cookie(&app_state.config, refresh_token).to_string(),
-        )?,
-    );
-
-    Ok(response)
+    new_session(&mut conn, app_state, uuid, &client).await
 }
@@ -11,6 +11,7 @@ use crate::{
     AppState,
     error::Error,
     schema::refresh_tokens::{self, dsl},
+    utils::hash_token,
 };
 
 /// `GET /api/v1/logout`
@@ -41,7 +42,7 @@ pub async fn res(
     let mut conn = app_state.pool.get().await?;
 
     let deleted = delete(refresh_tokens::table)
-        .filter(dsl::token.eq(refresh_token))
+        .filter(dsl::token.eq(hash_token(&app_state.token_pepper, &refresh_token)))
         .execute(&mut conn)
         .await?;
 
@@ -1,30 +1,44 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use axum::{
-    Router,
+    Json, Router,
     extract::{Request, State},
+    http::{HeaderValue, StatusCode},
     middleware::{Next, from_fn_with_state},
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post},
 };
 use axum_extra::{
     TypedHeader,
     headers::{Authorization, authorization::Bearer},
 };
-use diesel::{ExpressionMethods, QueryDsl};
+use diesel::{ExpressionMethods, QueryDsl, insert_into};
 use diesel_async::RunQueryDsl;
 use serde::Serialize;
 use uuid::Uuid;
 
-use crate::{AppState, Conn, error::Error, schema::access_tokens::dsl};
+use crate::{
+    AppState, Conn,
+    error::Error,
+    objects::{AccountDeletion, Session, Suspension},
+    schema::{
+        access_tokens::{self, dsl},
+        refresh_tokens, users,
+    },
+    utils::{
+        ClientInfo, generate_device_name, generate_token, hash_token, new_refresh_token_cookie,
+    },
+};
 
 mod devices;
 mod login;
 mod logout;
+mod passkeys;
 mod refresh;
 mod register;
 mod reset_password;
 mod revoke;
+mod totp;
 mod verify_email;
 
 #[derive(Serialize)]
@@ -38,12 +52,26 @@ pub fn router(app_state: &'static AppState) -> Router<&'static AppState> {
         .route("/verify-email", get(verify_email::get))
         .route("/verify-email", post(verify_email::post))
         .route("/revoke", post(revoke::post))
+        .route("/revoke-others", post(revoke::others))
         .route("/devices", get(devices::get))
+        .route("/devices/{uuid}", patch(devices::patch))
+        .route("/totp", get(totp::get))
+        .route("/totp", post(totp::post))
+        .route("/totp", delete(totp::delete))
+        .route("/totp/confirm", post(totp::confirm))
+        .route("/totp/recovery-codes", post(totp::recovery_codes))
+        .route("/passkeys", get(passkeys::get))
+        .route("/passkeys", post(passkeys::post))
+        .route("/passkeys/confirm", post(passkeys::confirm))
+        .route("/passkeys/{uuid}", delete(passkeys::delete))
         .layer(from_fn_with_state(app_state, CurrentUser::check_auth_layer));
 
     Router::new()
         .route("/register", post(register::post))
         .route("/login", post(login::response))
+        .route("/login/totp", post(totp::login))
+        .route("/login/passkey", post(passkeys::login))
+        .route("/login/passkey/options", post(passkeys::login_options))
         .route("/logout", delete(logout::res))
         .route("/refresh", post(refresh::post))
         .route("/reset-password", get(reset_password::get))
@@ -51,10 +79,114 @@ pub fn router(app_state: &'static AppState) -> Router<&'static AppState> {
         .merge(router_with_auth)
 }
 
-pub async fn check_access_token(access_token: &str, conn: &mut Conn) -> Result<Uuid, Error> {
-    let (uuid, created_at): (Uuid, i64) = dsl::access_tokens
-        .filter(dsl::token.eq(access_token))
-        .select((dsl::uuid, dsl::created_at))
+/// Creates a new session for the user, responds with the access token and sets the refresh token cookie
+pub async fn new_session(
+    conn: &mut Conn,
+    app_state: &'static AppState,
+    uuid: Uuid,
+    client: &ClientInfo,
+) -> Result<axum::response::Response, Error> {
+    let (disabled, is_deleted): (bool, bool) = users::table
+        .filter(users::uuid.eq(uuid))
+        .select((users::disabled, users::is_deleted))
+        .get_result(conn)
+        .await?;
+
+    if is_deleted {
+        return Err(Error::Unauthorized(
+            "Invalid username or password".to_string(),
+        ));
+    }
+
+    if disabled {
+        return Err(Error::Forbidden("Account is disabled".to_string()));
+    }
+
+    if let Some(suspension) = Suspension::fetch(conn, uuid).await? {
+        return Err(suspension.to_error());
+    }
+
+    // Logging in during the grace period keeps the account
+    AccountDeletion::cancel(conn, uuid).await?;
+
+    let refresh_token = generate_token::<32>()?;
+    let access_token = generate_token::<16>()?;
+
+    let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    let device_name = generate_device_name();
+
+    use refresh_tokens::dsl as rdsl;
+    insert_into(refresh_tokens::table)
+        .values((
+            rdsl::token.eq(hash_token(&app_state.token_pepper, &refresh_token)),
+            rdsl::uuid.eq(uuid),
+            rdsl::created_at.eq(current_time),
+            rdsl::device_name.eq(&device_name),
+            rdsl::session_uuid.eq(Uuid::now_v7()),
+            rdsl::user_agent.eq(&client.user_agent),
+            rdsl::ip_address.eq(client.ip.to_string()),
+            rdsl::last_used.eq(current_time),
+            rdsl::issued_at.eq(current_time),
+        ))
+        .execute(conn)
+        .await?;
+
+    if let Some(max_sessions) = app_state.config.auth.max_sessions {
+        Session::enforce_limit(conn, uuid, max_sessions).await?;
+    }
+
+    insert_into(access_tokens::table)
+        .values((
+            dsl::token.eq(hash_token(&app_state.token_pepper, &access_token)),
+            dsl::refresh_token.eq(hash_token(&app_state.token_pepper, &refresh_token)),
+            dsl::uuid.eq(uuid),
+            dsl::created_at.eq(current_time),
+        ))
+        .execute(conn)
+        .await?;
+
+    let mut response = (
+        StatusCode::OK,
+        Json(Response {
+            access_token,
+            device_name,
+        }),
+    )
+        .into_response();
+
+    response.headers_mut().append(
+        "Set-Cookie",
+        HeaderValue::from_str(
+            &new_refresh_token_cookie(&app_state.config, refresh_token).to_string(),
+        )?,
+    );
+
+    Ok(response)
+}
+
+pub async fn check_access_token(
+    app_state: &AppState,
+    access_token: &str,
+    conn: &mut Conn,
+) -> Result<Uuid, Error> {
+    #[allow(clippy::type_complexity)]
+    let (uuid, created_at, suspended_at, suspended_until, suspension_reason): (
+        Uuid,
+        i64,
+        Option<i64>,
+        Option<i64>,
+        Option<String>,
+    ) = dsl::access_tokens
+        .inner_join(users::table)
+        .filter(dsl::token.eq(hash_token(&app_state.token_pepper, access_token)))
+        .select((
+            dsl::uuid,
+            dsl::created_at,
+            users::suspended_at,
+            users::suspended_until,
+            users::suspension_reason,
+        ))
         .get_result(conn)
         .await
         .map_err(|error| {
@@ -69,10 +201,18 @@ pub async fn check_access_token(access_token: &str, conn: &mut Conn) -> Result<U
 
     let lifetime = current_time - created_at;
 
-    if lifetime > 3600 {
+    if lifetime > app_state.config.auth.access_token_lifetime {
         return Err(Error::Unauthorized("Invalid access token".to_string()));
     }
 
+    if let Some(suspension) =
+        Suspension::from_columns(suspended_at, suspended_until, suspension_reason)?
+    {
+        return Err(suspension.to_error());
+    }
+
+    tracing::Span::current().record("user_uuid", tracing::field::display(uuid));
+
     Ok(uuid)
 }
 
@@ -86,8 +226,14 @@ impl CurrentUser<Uuid> {
         mut req: Request,
         next: Next,
     ) -> Result<impl IntoResponse, Error> {
-        let current_user =
-            CurrentUser(check_access_token(auth.token(), &mut app_state.pool.get().await?).await?);
+        // Already checked by the rate limiter
+        if req.extensions().get::<CurrentUser<Uuid>>().is_some() {
+            return Ok(next.run(req).await);
+        }
+
+        let current_user = CurrentUser(
+            check_access_token(app_state, auth.token(), &mut app_state.pool.get().await?).await?,
+        );
 
         req.extensions_mut().insert(current_user);
         Ok(next.run(req).await)
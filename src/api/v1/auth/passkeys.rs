@@ -0,0 +1,332 @@
+//! `/api/v1/auth/passkeys` Endpoints for managing and logging in with passkeys
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use uuid::Uuid;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential};
+
+use super::new_session;
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AttemptSubject, LoginAttempts, LoginChallenge, Me, Passkey, Totp},
+    utils::ClientInfo,
+};
+
+#[derive(Deserialize)]
+pub struct PasswordConfirmation {
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyRemoval {
+    password: String,
+    totp_code: Option<String>,
+    /// Token from `POST /api/v1/auth/login/passkey/options`, sent with `credential` to confirm with another passkey
+    challenge_token: Option<String>,
+    credential: Option<PublicKeyCredential>,
+}
+
+#[derive(Deserialize)]
+pub struct NewPasskey {
+    name: String,
+    credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginOptions {
+    challenge_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLogin {
+    challenge_token: String,
+    credential: PublicKeyCredential,
+}
+
+/// `GET /api/v1/auth/passkeys` Returns list of registered passkeys
+///
+/// requires auth: yes
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "uuid": "0198a2c4-5f3e-7b21-9d8c-4e6f1a2b3c4d",
+///         "name": "My Phone",
+///         "created_at": 1755016947,
+///         "last_used": 1755103347
+///     }
+/// ]);
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let passkeys = Passkey::fetch_all(&mut app_state.pool.get().await?, uuid).await?;
+
+    Ok((StatusCode::OK, Json(passkeys)))
+}
+
+/// `POST /api/v1/auth/passkeys` Starts registering a passkey
+///
+/// requires auth: yes
+///
+/// Returns options for `navigator.credentials.create()`, the result has to be sent to
+/// `POST /api/v1/auth/passkeys/confirm` within 5 minutes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "1608c17a27f6ae3891c23d680c73ae91528f20a54dcf4973e2c3126b9734f48b7253047f2395b51bb8a44a6daa188003"
+/// });
+/// ```
+pub async fn post(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(confirmation): Json<PasswordConfirmation>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.verify_password(&mut conn, app_state, &confirmation.password)
+        .await?;
+
+    let options = Passkey::start_registration(&mut conn, app_state, &me).await?;
+
+    Ok((StatusCode::OK, Json(options)))
+}
+
+/// `POST /api/v1/auth/passkeys/confirm` Finishes registering a passkey
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "name": "My Phone",
+///     "credential": { /* result of navigator.credentials.create() */ }
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "uuid": "0198a2c4-5f3e-7b21-9d8c-4e6f1a2b3c4d",
+///     "name": "My Phone",
+///     "created_at": 1755016947,
+///     "last_used": null
+/// });
+/// ```
+pub async fn confirm(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(new_passkey): Json<NewPasskey>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    let passkey = Passkey::finish_registration(
+        &mut conn,
+        app_state,
+        &me,
+        new_passkey.name,
+        &new_passkey.credential,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(passkey)))
+}
+
+/// `DELETE /api/v1/auth/passkeys/{uuid}` Removes a passkey
+///
+/// requires auth: yes
+///
+/// While TOTP or another passkey remains, the removal also has to be confirmed with a `totp_code`,
+/// or with a `challenge_token` and `credential` from a passkey login started without a `challenge_token`
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "sha512hashedpassword",
+///     "totp_code": "123456"
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Success
+///
+/// 401 Wrong password or second factor
+///
+/// 404 Not Found
+///
+pub async fn delete(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Path(passkey_uuid): Path<Uuid>,
+    Json(removal): Json<PasskeyRemoval>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    Me::get(&mut conn, uuid)
+        .await?
+        .verify_password(&mut conn, app_state, &removal.password)
+        .await?;
+
+    let passkeys = Passkey::fetch_all(&mut conn, uuid).await?;
+
+    if !passkeys.iter().any(|passkey| passkey.uuid == passkey_uuid) {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    let totp_enabled = Totp::is_enabled(&mut conn, uuid).await?;
+
+    // Otherwise a stolen access token could remove the second factor of the account
+    if totp_enabled || passkeys.len() > 1 {
+        match (
+            removal.totp_code,
+            removal.challenge_token,
+            removal.credential,
+        ) {
+            (Some(code), _, _) if totp_enabled => {
+                Totp::verify(&mut conn, app_state, uuid, &code).await?
+            }
+            (_, Some(challenge_token), Some(credential)) => {
+                let asserted_uuid = Passkey::finish_authentication(
+                    &mut conn,
+                    app_state,
+                    &challenge_token,
+                    &credential,
+                )
+                .await?;
+
+                if asserted_uuid != uuid {
+                    return Err(Error::Unauthorized("Unknown passkey".to_string()));
+                }
+            }
+            _ => {
+                return Err(Error::Unauthorized(
+                    "Two-factor authentication required".to_string(),
+                ));
+            }
+        }
+    }
+
+    Passkey::delete(&mut conn, uuid, passkey_uuid).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// `POST /api/v1/auth/login/passkey/options` Starts a passkey login
+///
+/// requires auth: no
+///
+/// Pass the `challenge_token` returned by `POST /api/v1/auth/login` to use a passkey as the second factor,
+/// leave it out to log in without a password
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "challenge_token": "a3f7e29c1b8d0456e2c9f83b7a1d6e4f5028c3b9a7e1f2d5c6b8a0d3e7f4a2b"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "challenge_token": "a3f7e29c1b8d0456e2c9f83b7a1d6e4f5028c3b9a7e1f2d5c6b8a0d3e7f4a2b",
+///     "public_key": { /* options for navigator.credentials.get() */ }
+/// });
+/// ```
+pub async fn login_options(
+    State(app_state): State<&'static AppState>,
+    Json(options): Json<PasskeyLoginOptions>,
+) -> Result<impl IntoResponse, Error> {
+    let login_challenge = match options.challenge_token {
+        Some(token) => Some(LoginChallenge::get(&app_state.cache_pool, &token).await?),
+        None => None,
+    };
+
+    let options =
+        Passkey::start_authentication(&mut app_state.pool.get().await?, app_state, login_challenge)
+            .await?;
+
+    Ok((StatusCode::OK, Json(options)))
+}
+
+/// `POST /api/v1/auth/login/passkey` Logs in with a passkey
+///
+/// requires auth: no
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "challenge_token": "a3f7e29c1b8d0456e2c9f83b7a1d6e4f5028c3b9a7e1f2d5c6b8a0d3e7f4a2b",
+///     "credential": { /* result of navigator.credentials.get() */ }
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "access_token": "c3b9a7e1f2d5c6b8a0d3e7f4a2b1c9d8",
+///     "device_name": "Clever Fox"
+/// });
+/// ```
+pub async fn login(
+    State(app_state): State<&'static AppState>,
+    client: ClientInfo,
+    Json(passkey_login): Json<PasskeyLogin>,
+) -> Result<Response, Error> {
+    let login_protection = &app_state.config.login_protection;
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Ip(client.ip))
+        .await?
+        .check(login_protection)?;
+
+    let mut conn = app_state.pool.get().await?;
+
+    let uuid = match Passkey::finish_authentication(
+        &mut conn,
+        app_state,
+        &passkey_login.challenge_token,
+        &passkey_login.credential,
+    )
+    .await
+    {
+        Ok(uuid) => uuid,
+        Err(error) => {
+            LoginAttempts::register_failure(
+                &app_state.cache_pool,
+                login_protection,
+                AttemptSubject::Ip(client.ip),
+            )
+            .await?;
+
+            return Err(error);
+        }
+    };
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Account(uuid))
+        .await?
+        .check(login_protection)?;
+
+    // Used as a second factor, the password step is complete now
+    if let Ok(login_challenge) =
+        LoginChallenge::get(&app_state.cache_pool, &passkey_login.challenge_token).await
+    {
+        login_challenge.delete(&app_state.cache_pool).await?;
+    }
+
+    LoginAttempts::clear(&app_state.cache_pool, AttemptSubject::Account(uuid)).await?;
+
+    new_session(&mut conn, app_state, uuid, &client).await
+}
@@ -5,24 +5,42 @@ use axum::{
     response::IntoResponse,
 };
 use axum_extra::extract::CookieJar;
-use diesel::{ExpressionMethods, QueryDsl, delete, update};
-use diesel_async::RunQueryDsl;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, delete, insert_into, update};
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use log::error;
 use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 use super::Response;
 use crate::{
     AppState,
     error::Error,
+    objects::Session,
     schema::{
         access_tokens::{self, dsl},
         refresh_tokens::{self, dsl as rdsl},
+        used_refresh_tokens,
     },
-    utils::{generate_token, new_refresh_token_cookie},
+    utils::{ClientInfo, generate_token, hash_token, new_refresh_token_cookie},
 };
 
+/// `POST /api/v1/auth/refresh` Exchanges the refresh token for a new access token
+///
+/// The refresh token is rotated on every refresh, presenting a token that has already been
+/// rotated logs the session out and notifies the user by email
+///
+/// requires auth: kinda, needs refresh token set but no access token is technically required
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "access_token": "f8b1a9c6d0e2f4a7b3c5d9e1f0a2b4c6",
+///     "device_name": "Clever Otter"
+/// });
+/// ```
 pub async fn post(
     State(app_state): State<&'static AppState>,
+    client: ClientInfo,
     jar: CookieJar,
 ) -> Result<impl IntoResponse, Error> {
     let mut refresh_token_cookie = jar
@@ -32,95 +50,115 @@ pub async fn post(
         ))?
         .to_owned();
 
-    let mut refresh_token = String::from(refresh_token_cookie.value_trimmed());
+    let refresh_token_hash = hash_token(
+        &app_state.token_pepper,
+        refresh_token_cookie.value_trimmed(),
+    );
 
     let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
 
     let mut conn = app_state.pool.get().await?;
 
-    if let Ok(created_at) = rdsl::refresh_tokens
-        .filter(rdsl::token.eq(&refresh_token))
-        .select(rdsl::created_at)
-        .get_result::<i64>(&mut conn)
+    let session: Option<(Uuid, i64, String)> = rdsl::refresh_tokens
+        .filter(rdsl::token.eq(&refresh_token_hash))
+        .select((rdsl::session_uuid, rdsl::issued_at, rdsl::device_name))
+        .get_result(&mut conn)
         .await
-    {
-        let lifetime = current_time - created_at;
+        .optional()?;
+
+    if let Some((session_uuid, issued_at, device_name)) = session {
+        let lifetime = current_time - issued_at;
 
-        if lifetime > 2592000 {
+        if lifetime > app_state.config.auth.refresh_token_lifetime {
             if let Err(error) = delete(refresh_tokens::table)
-                .filter(rdsl::token.eq(&refresh_token))
+                .filter(rdsl::token.eq(&refresh_token_hash))
                 .execute(&mut conn)
                 .await
             {
                 error!("{error}");
             }
-
-            let mut response = StatusCode::UNAUTHORIZED.into_response();
-
-            refresh_token_cookie.make_removal();
-            response.headers_mut().append(
-                "Set-Cookie",
-                HeaderValue::from_str(&refresh_token_cookie.to_string())?,
-            );
-
-            return Ok(response);
-        }
-
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-        let mut device_name: String = String::new();
-
-        if lifetime > 1987200 {
-            let new_refresh_token = generate_token::<32>()?;
-
-            match update(refresh_tokens::table)
-                .filter(rdsl::token.eq(&refresh_token))
-                .set((
-                    rdsl::token.eq(&new_refresh_token),
-                    rdsl::created_at.eq(current_time),
-                ))
-                .returning(rdsl::device_name)
-                .get_result::<String>(&mut conn)
-                .await
-            {
-                Ok(existing_device_name) => {
-                    refresh_token = new_refresh_token;
-                    device_name = existing_device_name;
-                }
-                Err(error) => {
-                    error!("{error}");
-                }
+        } else {
+            let refresh_token = generate_token::<32>()?;
+            let new_refresh_token_hash = hash_token(&app_state.token_pepper, &refresh_token);
+
+            let access_token = generate_token::<16>()?;
+            let access_token_hash = hash_token(&app_state.token_pepper, &access_token);
+
+            let refresh_token_hash = &refresh_token_hash;
+            let client = &client;
+
+            // Rotated tokens always have a reuse record, otherwise reusing them wouldn't be detected
+            let rotated = conn
+                .transaction::<_, Error, _>(|conn| {
+                    async move {
+                        // Filtering on the old hash makes concurrent refreshes with the same token rotate it only once
+                        let rotated = update(refresh_tokens::table)
+                            .filter(rdsl::token.eq(refresh_token_hash))
+                            .set((
+                                rdsl::token.eq(&new_refresh_token_hash),
+                                rdsl::issued_at.eq(current_time),
+                                rdsl::last_used.eq(current_time),
+                                rdsl::user_agent.eq(&client.user_agent),
+                                rdsl::ip_address.eq(client.ip.to_string()),
+                            ))
+                            .execute(conn)
+                            .await?;
+
+                        if rotated != 1 {
+                            return Ok(false);
+                        }
+
+                        insert_into(used_refresh_tokens::table)
+                            .values((
+                                used_refresh_tokens::token.eq(refresh_token_hash),
+                                used_refresh_tokens::session_uuid.eq(session_uuid),
+                                used_refresh_tokens::rotated_at.eq(current_time),
+                            ))
+                            .on_conflict_do_nothing()
+                            .execute(conn)
+                            .await?;
+
+                        update(access_tokens::table)
+                            .filter(dsl::refresh_token.eq(&new_refresh_token_hash))
+                            .set((
+                                dsl::token.eq(access_token_hash),
+                                dsl::created_at.eq(current_time),
+                            ))
+                            .execute(conn)
+                            .await?;
+
+                        Ok(true)
+                    }
+                    .scope_boxed()
+                })
+                .await?;
+
+            if rotated {
+                let mut response = (
+                    StatusCode::OK,
+                    Json(Response {
+                        access_token,
+                        device_name,
+                    }),
+                )
+                    .into_response();
+
+                response.headers_mut().append(
+                    "Set-Cookie",
+                    HeaderValue::from_str(
+                        &new_refresh_token_cookie(&app_state.config, refresh_token).to_string(),
+                    )?,
+                );
+
+                return Ok(response);
             }
-        }
 
-        let access_token = generate_token::<16>()?;
-
-        update(access_tokens::table)
-            .filter(dsl::refresh_token.eq(&refresh_token))
-            .set((
-                dsl::token.eq(&access_token),
-                dsl::created_at.eq(current_time),
-            ))
-            .execute(&mut conn)
-            .await?;
-
-        let mut response = (
-            StatusCode::OK,
-            Json(Response {
-                access_token,
-                device_name,
-            }),
-        )
-            .into_response();
-
-        // TODO: Dont set this when refresh token is unchanged
-        response.headers_mut().append(
-            "Set-Cookie",
-            HeaderValue::from_str(
-                &new_refresh_token_cookie(&app_state.config, refresh_token).to_string(),
-            )?,
-        );
-
-        return Ok(response);
+            // Another request rotated the token first, its response carries the new cookie
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+    } else if Session::revoke_reused(&mut conn, app_state, &refresh_token_hash).await? {
+        // Rotated moments ago by another tab, the cookie already holds the new token and has to stay
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
     let mut response = StatusCode::UNAUTHORIZED.into_response();
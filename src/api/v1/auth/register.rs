@@ -1,5 +1,3 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
 use argon2::{
     PasswordHasher,
     password_hash::{SaltString, rand_core::OsRng},
@@ -7,28 +5,21 @@ use argon2::{
 use axum::{
     Json,
     extract::State,
-    http::{HeaderValue, StatusCode},
-    response::IntoResponse,
+    http::StatusCode,
+    response::{IntoResponse, Response},
 };
 use diesel::{ExpressionMethods, dsl::insert_into};
 use diesel_async::RunQueryDsl;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::Response;
+use super::new_session;
 use crate::{
     AppState,
     error::Error,
-    objects::Member,
-    schema::{
-        access_tokens::{self, dsl as adsl},
-        refresh_tokens::{self, dsl as rdsl},
-        users::{self, dsl as udsl},
-    },
-    utils::{
-        EMAIL_REGEX, PASSWORD_REGEX, USERNAME_REGEX, generate_device_name, generate_token,
-        new_refresh_token_cookie,
-    },
+    objects::{InstanceSettings, Member, RegistrationBlock},
+    schema::users::{self, dsl as udsl},
+    utils::{ClientInfo, EMAIL_REGEX, PASSWORD_REGEX, USERNAME_REGEX},
 };
 
 #[derive(Deserialize)]
@@ -63,14 +54,22 @@ impl Default for ResponseError {
 
 pub async fn post(
     State(app_state): State<&'static AppState>,
+    client: ClientInfo,
     Json(account_information): Json<AccountInformation>,
-) -> Result<impl IntoResponse, Error> {
-    if !app_state.config.instance.registration {
+) -> Result<Response, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let instance_settings =
+        InstanceSettings::get(&mut conn, &app_state.cache_pool, &app_state.config).await?;
+
+    if !instance_settings.registration {
         return Err(Error::Forbidden(
             "registration is disabled on this instance".to_string(),
         ));
     }
 
+    RegistrationBlock::check(&mut conn, &account_information.email, client.ip).await?;
+
     let uuid = Uuid::now_v7();
 
     if !EMAIL_REGEX.is_match(&account_information.email) {
@@ -115,8 +114,6 @@ pub async fn post(
         .argon2
         .hash_password(account_information.password.as_bytes(), &salt)
     {
-        let mut conn = app_state.pool.get().await?;
-
         // TODO: Check security of this implementation
         insert_into(users::table)
             .values((
@@ -128,54 +125,11 @@ pub async fn post(
             .execute(&mut conn)
             .await?;
 
-        let refresh_token = generate_token::<32>()?;
-        let access_token = generate_token::<16>()?;
-
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-
-        let device_name = generate_device_name();
-
-        insert_into(refresh_tokens::table)
-            .values((
-                rdsl::token.eq(&refresh_token),
-                rdsl::uuid.eq(uuid),
-                rdsl::created_at.eq(current_time),
-                rdsl::device_name.eq(&device_name),
-            ))
-            .execute(&mut conn)
-            .await?;
-
-        insert_into(access_tokens::table)
-            .values((
-                adsl::token.eq(&access_token),
-                adsl::refresh_token.eq(&refresh_token),
-                adsl::uuid.eq(uuid),
-                adsl::created_at.eq(current_time),
-            ))
-            .execute(&mut conn)
-            .await?;
-
         if let Some(initial_guild) = app_state.config.instance.initial_guild {
             Member::new(&mut conn, &app_state.cache_pool, uuid, initial_guild).await?;
         }
 
-        let mut response = (
-            StatusCode::OK,
-            Json(Response {
-                access_token,
-                device_name,
-            }),
-        )
-            .into_response();
-
-        response.headers_mut().append(
-            "Set-Cookie",
-            HeaderValue::from_str(
-                &new_refresh_token_cookie(&app_state.config, refresh_token).to_string(),
-            )?,
-        );
-
-        return Ok(response);
+        return new_session(&mut conn, app_state, uuid, &client).await;
     }
 
     Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
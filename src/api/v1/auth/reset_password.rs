@@ -9,7 +9,12 @@ use axum::{
 use chrono::{Duration, Utc};
 use serde::Deserialize;
 
-use crate::{AppState, error::Error, objects::PasswordResetToken};
+use crate::{
+    AppState,
+    error::Error,
+    objects::{AttemptSubject, LoginAttempts, PasswordResetToken},
+    utils::ClientIp,
+};
 
 #[derive(Deserialize)]
 pub struct QueryParams {
@@ -34,8 +39,15 @@ pub struct QueryParams {
 ///
 pub async fn get(
     State(app_state): State<&'static AppState>,
+    ClientIp(ip): ClientIp,
     query: Query<QueryParams>,
 ) -> Result<impl IntoResponse, Error> {
+    let login_protection = &app_state.config.login_protection;
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Ip(ip))
+        .await?
+        .check(login_protection)?;
+
     let mut conn = app_state.pool.get().await?;
 
     if let Ok(password_reset_token) = PasswordResetToken::get_with_identifier(
@@ -54,7 +66,18 @@ pub async fn get(
         }
     }
 
-    PasswordResetToken::new(&mut conn, &app_state, query.identifier.clone()).await?;
+    let result = PasswordResetToken::new(&mut conn, app_state, query.identifier.clone()).await;
+
+    if let Err(Error::SqlError(diesel::result::Error::NotFound)) = result {
+        LoginAttempts::register_failure(
+            &app_state.cache_pool,
+            login_protection,
+            AttemptSubject::Ip(ip),
+        )
+        .await?;
+    }
+
+    result?;
 
     Ok(StatusCode::OK)
 }
@@ -88,15 +111,34 @@ pub struct ResetPassword {
 ///
 pub async fn post(
     State(app_state): State<&'static AppState>,
+    ClientIp(ip): ClientIp,
     reset_password: Json<ResetPassword>,
 ) -> Result<impl IntoResponse, Error> {
+    let login_protection = &app_state.config.login_protection;
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Ip(ip))
+        .await?
+        .check(login_protection)?;
+
     let password_reset_token =
-        PasswordResetToken::get(&app_state.cache_pool, reset_password.token.clone()).await?;
+        match PasswordResetToken::get(&app_state.cache_pool, reset_password.token.clone()).await {
+            Ok(password_reset_token) => password_reset_token,
+            Err(error) => {
+                LoginAttempts::register_failure(
+                    &app_state.cache_pool,
+                    login_protection,
+                    AttemptSubject::Ip(ip),
+                )
+                .await?;
+
+                return Err(error);
+            }
+        };
 
     password_reset_token
         .set_password(
             &mut app_state.pool.get().await?,
-            &app_state,
+            app_state,
             reset_password.password.clone(),
         )
         .await?;
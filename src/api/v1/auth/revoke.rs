@@ -1,7 +1,10 @@
-use argon2::{PasswordHash, PasswordVerifier};
+//! `/api/v1/auth/revoke` Endpoints for logging out other devices
+
 use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
-use diesel::{ExpressionMethods, QueryDsl, delete};
-use diesel_async::RunQueryDsl;
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
 use serde::Deserialize;
 use uuid::Uuid;
 
@@ -9,18 +12,35 @@ use crate::{
     AppState,
     api::v1::auth::CurrentUser,
     error::Error,
-    schema::{
-        refresh_tokens::{self, dsl as rdsl},
-        users::dsl as udsl,
-    },
+    objects::{Me, Session},
 };
 
 #[derive(Deserialize)]
 pub struct RevokeRequest {
     password: String,
-    device_name: String,
+    /// Session uuid from `GET /api/v1/auth/devices`
+    uuid: Uuid,
 }
 
+/// `POST /api/v1/auth/revoke` Logs out a device
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "sha512hashedpassword",
+///     "uuid": "0198a7e2-3c4b-7d1e-9f20-5b6c7d8e9f01"
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Success
+///
+/// 401 Wrong password
+///
+/// 404 Not Found
+///
 // TODO: Should maybe be a delete request?
 pub async fn post(
     State(app_state): State<&'static AppState>,
@@ -29,30 +49,53 @@ pub async fn post(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    let database_password: String = udsl::users
-        .filter(udsl::uuid.eq(uuid))
-        .select(udsl::password)
-        .get_result(&mut conn)
+    Me::get(&mut conn, uuid)
+        .await?
+        .verify_password(&mut conn, app_state, &revoke_request.password)
         .await?;
 
-    let hashed_password = PasswordHash::new(&database_password)
-        .map_err(|e| Error::PasswordHashError(e.to_string()))?;
-
-    if app_state
-        .argon2
-        .verify_password(revoke_request.password.as_bytes(), &hashed_password)
-        .is_err()
-    {
-        return Err(Error::Unauthorized(
-            "Wrong username or password".to_string(),
-        ));
-    }
-
-    delete(refresh_tokens::table)
-        .filter(rdsl::uuid.eq(uuid))
-        .filter(rdsl::device_name.eq(&revoke_request.device_name))
-        .execute(&mut conn)
+    Session::revoke(&mut conn, &app_state.cache_pool, uuid, revoke_request.uuid).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct RevokeOthersRequest {
+    password: String,
+}
+
+/// `POST /api/v1/auth/revoke-others` Logs out every device except the one making the request
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "sha512hashedpassword"
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Success
+///
+/// 401 Wrong password
+///
+pub async fn others(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Json(revoke_request): Json<RevokeOthersRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    Me::get(&mut conn, uuid)
+        .await?
+        .verify_password(&mut conn, app_state, &revoke_request.password)
         .await?;
 
+    let current_session = Session::current_uuid(&mut conn, app_state, auth.token()).await?;
+
+    Session::revoke_others(&mut conn, &app_state.cache_pool, uuid, current_session).await?;
+
     Ok(StatusCode::OK)
 }
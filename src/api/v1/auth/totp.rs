@@ -0,0 +1,308 @@
+//! `/api/v1/auth/totp` Endpoints for managing two-factor authentication
+
+use axum::{
+    Extension, Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::new_session;
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{AttemptSubject, LoginAttempts, LoginChallenge, Me, Totp},
+    utils::ClientInfo,
+};
+
+#[derive(Serialize)]
+struct Status {
+    enabled: bool,
+    recovery_codes_remaining: i64,
+}
+
+#[derive(Serialize)]
+struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordConfirmation {
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct CodeConfirmation {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableConfirmation {
+    password: String,
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpLogin {
+    challenge_token: String,
+    code: String,
+}
+
+/// `GET /api/v1/auth/totp` Returns two-factor authentication status
+///
+/// requires auth: yes
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "enabled": true,
+///     "recovery_codes_remaining": 8
+/// });
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let status = Status {
+        enabled: Totp::is_enabled(&mut conn, uuid).await?,
+        recovery_codes_remaining: Totp::remaining_recovery_codes(&mut conn, uuid).await?,
+    };
+
+    Ok((StatusCode::OK, Json(status)))
+}
+
+/// `POST /api/v1/auth/totp` Starts two-factor authentication enrolment
+///
+/// requires auth: yes
+///
+/// The secret has to be confirmed with `POST /api/v1/auth/totp/confirm` within 10 minutes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "1608c17a27f6ae3891c23d680c73ae91528f20a54dcf4973e2c3126b9734f48b7253047f2395b51bb8a44a6daa188003"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+///     "uri": "otpauth://totp/Gorb:user1?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=Gorb"
+/// });
+/// ```
+pub async fn post(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(confirmation): Json<PasswordConfirmation>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.verify_password(&mut conn, app_state, &confirmation.password)
+        .await?;
+
+    if Totp::is_enabled(&mut conn, uuid).await? {
+        return Err(Error::BadRequest(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let enrolment = Totp::start_enrolment(app_state, &me).await?;
+
+    Ok((StatusCode::OK, Json(enrolment)))
+}
+
+/// `POST /api/v1/auth/totp/confirm` Enables two-factor authentication
+///
+/// requires auth: yes
+///
+/// Recovery codes are only returned once, each code can be used in place of a TOTP code one time
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "code": "123456"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "recovery_codes": [
+///         "a1b2c-3d4e5",
+///         "f6a7b-8c9d0"
+///     ]
+/// });
+/// ```
+pub async fn confirm(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(confirmation): Json<CodeConfirmation>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    let recovery_codes =
+        Totp::confirm_enrolment(&mut conn, app_state, &me, &confirmation.code).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodes { recovery_codes })))
+}
+
+/// `DELETE /api/v1/auth/totp` Disables two-factor authentication
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "1608c17a27f6ae3891c23d680c73ae91528f20a54dcf4973e2c3126b9734f48b7253047f2395b51bb8a44a6daa188003",
+///     "code": "123456"
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Success
+///
+/// 400 Not enabled
+///
+/// 401 Wrong password or code
+///
+pub async fn delete(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(confirmation): Json<DisableConfirmation>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.verify_password(&mut conn, app_state, &confirmation.password)
+        .await?;
+
+    Totp::verify(&mut conn, app_state, uuid, &confirmation.code).await?;
+
+    Totp::disable(&mut conn, uuid).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// `POST /api/v1/auth/totp/recovery-codes` Replaces all recovery codes
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "code": "123456"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "recovery_codes": [
+///         "a1b2c-3d4e5",
+///         "f6a7b-8c9d0"
+///     ]
+/// });
+/// ```
+pub async fn recovery_codes(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(confirmation): Json<CodeConfirmation>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    Totp::verify(&mut conn, app_state, uuid, &confirmation.code).await?;
+
+    let recovery_codes = Totp::generate_recovery_codes(&mut conn, app_state, uuid).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodes { recovery_codes })))
+}
+
+/// `POST /api/v1/auth/login/totp` Completes a login that requires two-factor authentication
+///
+/// requires auth: no
+///
+/// `code` can be a TOTP code or a recovery code
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "challenge_token": "a3f7e29c1b8d0456e2c9f83b7a1d6e4f5028c3b9a7e1f2d5c6b8a0d3e7f4a2b",
+///     "code": "123456"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "access_token": "c3b9a7e1f2d5c6b8a0d3e7f4a2b1c9d8",
+///     "device_name": "Clever Fox"
+/// });
+/// ```
+pub async fn login(
+    State(app_state): State<&'static AppState>,
+    client: ClientInfo,
+    Json(totp_login): Json<TotpLogin>,
+) -> Result<Response, Error> {
+    let login_protection = &app_state.config.login_protection;
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Ip(client.ip))
+        .await?
+        .check(login_protection)?;
+
+    let login_challenge =
+        LoginChallenge::get(&app_state.cache_pool, &totp_login.challenge_token).await?;
+
+    let uuid = login_challenge.user_uuid;
+
+    LoginAttempts::get(&app_state.cache_pool, AttemptSubject::Account(uuid))
+        .await?
+        .check(login_protection)?;
+
+    let mut conn = app_state.pool.get().await?;
+
+    if let Err(error) = Totp::verify(&mut conn, app_state, uuid, &totp_login.code).await {
+        LoginAttempts::register_failure(
+            &app_state.cache_pool,
+            login_protection,
+            AttemptSubject::Ip(client.ip),
+        )
+        .await?;
+
+        let locked = LoginAttempts::register_failure(
+            &app_state.cache_pool,
+            login_protection,
+            AttemptSubject::Account(uuid),
+        )
+        .await?;
+
+        if locked {
+            // Make the user start over with their password once the lockout ends
+            login_challenge.delete(&app_state.cache_pool).await?;
+
+            if let Err(error) = LoginAttempts::send_lockout_email(&mut conn, app_state, uuid).await
+            {
+                error!("failed to send lockout email: {error}");
+            }
+        }
+
+        return Err(error);
+    }
+
+    login_challenge.delete(&app_state.cache_pool).await?;
+
+    LoginAttempts::clear(&app_state.cache_pool, AttemptSubject::Account(uuid)).await?;
+
+    new_session(&mut conn, app_state, uuid, &client).await
+}
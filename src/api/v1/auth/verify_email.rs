@@ -83,7 +83,9 @@ pub async fn post(
     State(app_state): State<&'static AppState>,
     Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
 ) -> Result<impl IntoResponse, Error> {
-    let me = Me::get(&mut app_state.pool.get().await?, uuid).await?;
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
 
     if me.email_verified {
         return Ok(StatusCode::NO_CONTENT);
@@ -99,7 +101,7 @@ pub async fn post(
         }
     }
 
-    EmailToken::new(&app_state, me).await?;
+    EmailToken::new(&mut conn, app_state, me).await?;
 
     Ok(StatusCode::OK)
 }
@@ -1,7 +1,7 @@
 use axum::{
     Router,
     middleware::from_fn_with_state,
-    routing::{any, delete, get, patch},
+    routing::{any, delete, get, patch, post},
 };
 //use socketioxide::SocketIo;
 
@@ -15,6 +15,10 @@ pub fn router(app_state: &'static AppState) -> Router<&'static AppState> {
         .route("/{uuid}", delete(uuid::delete))
         .route("/{uuid}", patch(uuid::patch))
         .route("/{uuid}/messages", get(uuid::messages::get))
+        .route(
+            "/{uuid}/messages/{message_uuid}/report",
+            post(uuid::messages::report),
+        )
         .layer(from_fn_with_state(app_state, CurrentUser::check_auth_layer));
 
     Router::new()
@@ -4,7 +4,7 @@ use crate::{
     AppState,
     api::v1::auth::CurrentUser,
     error::Error,
-    objects::{Channel, Member},
+    objects::{Channel, Member, Report, ReportRequest},
     utils::global_checks,
 };
 use ::uuid::Uuid;
@@ -48,7 +48,8 @@ pub struct MessageRequest {
 ///         "username": "1234",
 ///         "display_name": null,
 ///         "avatar": "https://cdn.gorb.app/avatar/0196fc96-a822-76b0-b9bf-a9de232f54b7/avatar.jpg"
-///     }
+///     },
+///     "author_blocked": false
 /// });
 /// ```
 ///
@@ -60,7 +61,7 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let channel = Channel::fetch_one(&mut conn, &app_state.cache_pool, channel_uuid).await?;
 
@@ -70,6 +71,7 @@ pub async fn get(
         .fetch_messages(
             &mut conn,
             &app_state.cache_pool,
+            uuid,
             message_request.amount,
             message_request.offset,
         )
@@ -77,3 +79,63 @@ pub async fn get(
 
     Ok((StatusCode::OK, Json(messages)))
 }
+
+/// `POST /api/v1/channels/{uuid}/messages/{message_uuid}/report` Reports a message to the moderators of the guild
+///
+/// The content of the message is saved with the report
+///
+/// requires auth: yes
+///
+/// requires relation: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "category": "harassment",
+///     "reason": "Keeps insulting people"
+/// });
+/// ```
+///
+/// `category` is one of `spam`, `harassment`, `hate`, `violence`, `sexual`, `self_harm`, `illegal` or `other`
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "uuid": "0198c3a1-5b2e-7f40-9c1d-2e3f4a5b6c7d",
+///     "reporter_uuid": "155d2291-fb23-46bd-a656-ae7c5d8218e6",
+///     "kind": "message",
+///     "target_uuid": "01971976-8618-74c0-b040-7ffbc44823f6",
+///     "guild_uuid": "5ba61ec7-5f97-43e1-89a5-d4693c155612",
+///     "category": "harassment",
+///     "reason": "Keeps insulting people",
+///     "message_author_uuid": "d48a3317-7b4d-443f-a250-ea9ab2bb8661",
+///     "message_content": "reported message",
+///     "status": "open",
+///     "resolver_uuid": null,
+///     "resolution_note": null,
+///     "created_at": 1755771824,
+///     "resolved_at": null
+/// });
+/// ```
+pub async fn report(
+    State(app_state): State<&'static AppState>,
+    Path((channel_uuid, message_uuid)): Path<(Uuid, Uuid)>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(report_request): Json<ReportRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let report = Report::report_message(
+        &mut conn,
+        &app_state.cache_pool,
+        uuid,
+        channel_uuid,
+        message_uuid,
+        report_request,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
@@ -27,7 +27,7 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let channel = Channel::fetch_one(&mut conn, &app_state.cache_pool, channel_uuid).await?;
 
@@ -43,7 +43,7 @@ pub async fn delete(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let channel = Channel::fetch_one(&mut conn, &app_state.cache_pool, channel_uuid).await?;
 
@@ -63,6 +63,7 @@ pub struct NewInfo {
     name: Option<String>,
     description: Option<String>,
     is_above: Option<String>,
+    slowmode: Option<i32>,
 }
 
 /// `PATCH /api/v1/channels/{uuid}` Returns user with the given UUID
@@ -77,7 +78,8 @@ pub struct NewInfo {
 /// json!({
 ///     "name": "gaming-chat",
 ///     "description": "Gaming related topics.",
-///     "is_above": "398f6d7b-752c-4348-9771-fe6024adbfb1"
+///     "is_above": "398f6d7b-752c-4348-9771-fe6024adbfb1",
+///     "slowmode": 10
 /// });
 /// ```
 ///
@@ -89,6 +91,7 @@ pub struct NewInfo {
 ///     name: "gaming-chat",
 ///     description: "Gaming related topics.",
 ///     is_above: "398f6d7b-752c-4348-9771-fe6024adbfb1",
+///     slowmode: 10,
 ///     permissions: {
 ///         role_uuid: "79cc0806-0f37-4a06-a468-6639c4311a2d",
 ///         permissions: 0
@@ -104,7 +107,7 @@ pub async fn patch(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let mut channel = Channel::fetch_one(&mut conn, &app_state.cache_pool, channel_uuid).await?;
 
@@ -136,5 +139,11 @@ pub async fn patch(
             .await?;
     }
 
+    if let Some(new_slowmode) = new_info.slowmode {
+        channel
+            .set_slowmode(&mut conn, &app_state.cache_pool, new_slowmode)
+            .await?;
+    }
+
     Ok((StatusCode::OK, Json(channel)))
 }
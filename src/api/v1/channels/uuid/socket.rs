@@ -1,5 +1,10 @@
+use std::{collections::HashSet, sync::Arc};
+
 use axum::{
-    extract::{Path, State, WebSocketUpgrade, ws::Message},
+    extract::{
+        Path, State, WebSocketUpgrade,
+        ws::{CloseFrame, Message, close_code},
+    },
     http::HeaderMap,
     response::IntoResponse,
 };
@@ -7,19 +12,23 @@ use diesel::{ExpressionMethods, QueryDsl, SelectableHelper, delete, update};
 use diesel_async::RunQueryDsl;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{
     AppState,
-    api::v1::auth::check_access_token,
+    api::{rate_limit::take_token, v1::auth::check_access_token},
+    cache::CacheKey,
     error::Error,
-    objects::{self, Channel, Member, message::MessageBuilder},
+    objects::{self, Channel, Member, Permissions, Session, UserBlock, message::MessageBuilder},
     schema::messages,
+    telemetry,
     utils::global_checks,
 };
 
 #[derive(Deserialize)]
 #[serde(tag = "event")]
+#[allow(clippy::enum_variant_names)]
 enum ReceiveEvent {
     MessageSend { entity: MessageSend },
     MessageEdit { entity: MessageEdit },
@@ -55,6 +64,8 @@ enum SendEvent {
 #[derive(Serialize)]
 struct SendError {
     message: String,
+    /// Seconds until the client is allowed to retry, only set when rate limited
+    retry_after: Option<i64>,
 }
 
 pub async fn ws(
@@ -103,89 +114,156 @@ pub async fn ws(
         .map_err(crate::error::Error::from)?;
 
     // Authorize client using auth header
-    let uuid = check_access_token(auth_header, &mut conn).await?;
+    let uuid = check_access_token(app_state, auth_header, &mut conn).await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    let session_uuid = Session::current_uuid(&mut conn, app_state, auth_header).await?;
 
     let channel = Channel::fetch_one(&mut conn, &app_state.cache_pool, channel_uuid).await?;
 
     Member::check_membership(&mut conn, uuid, channel.guild_uuid).await?;
 
-    let mut pubsub = app_state
-        .cache_pool
-        .get_async_pubsub()
-        .await
-        .map_err(crate::error::Error::from)?;
+    let mut blocked = UserBlock::blocked_by(&mut conn, uuid).await?;
+
+    let mut pubsub = app_state.cache_pool.pubsub().await?;
 
     let mut res = ws.on_upgrade(async move |socket| {
-        let (mut sender, mut receiver) = socket.split();
+        let (sender, mut receiver) = socket.split();
 
-        tokio::spawn(async move {
-            pubsub.subscribe(channel_uuid.to_string()).await?;
-            while let Some(msg) = pubsub.on_message().next().await {
-                let payload: String = msg.get_payload()?;
-                sender.send(payload.into()).await?;
-            }
+        let sender = Arc::new(Mutex::new(sender));
+        let pubsub_sender = sender.clone();
 
-            Ok::<(), crate::error::Error>(())
-        });
+        let mut receive_task = app_state.tasks.spawn(async move {
+            // Counted until the client goes away or the task is aborted
+            let _connection = telemetry::SocketConnection::open();
+
+            loop {
+                let msg = tokio::select! {
+                    msg = receiver.next() => msg,
+                    // The message being handled is finished first, the pubsub task closes the socket afterwards
+                    _ = app_state.shutdown.cancelled() => break,
+                };
+
+                let Some(msg) = msg else {
+                    break;
+                };
 
-        tokio::spawn(async move {
-            while let Some(msg) = receiver.next().await {
                 if let Ok(Message::Text(text)) = msg {
                     let message_body: ReceiveEvent = serde_json::from_str(&text)?;
 
                     match message_body {
                         ReceiveEvent::MessageSend { entity } => {
-                            let message = channel
+                            if app_state.config.rate_limit.enabled {
+                                let status = take_token(
+                                    &app_state.cache_pool,
+                                    CacheKey::RateLimit(format!("user:{uuid}:message_send")),
+                                    app_state.config.rate_limit.bucket("message_send"),
+                                )
+                                .await?;
+
+                                if !status.allowed {
+                                    let error = serde_json::to_string(&SendEvent::Error {
+                                        entity: SendError {
+                                            message: format!(
+                                                "Too many messages, try again in {} seconds",
+                                                status.retry_after
+                                            ),
+                                            retry_after: Some(status.retry_after as i64),
+                                        },
+                                    })?;
+
+                                    sender.lock().await.send(error.into()).await?;
+
+                                    continue;
+                                }
+                            }
+
+                            let mut conn = app_state.pool.get().await?;
+
+                            // Read again on every message, slowmode and permissions can change while the socket is open
+                            let channel =
+                                Channel::fetch_one(&mut conn, &app_state.cache_pool, channel_uuid)
+                                    .await?;
+
+                            let bypass_slowmode =
+                                Member::check_membership(&mut conn, uuid, channel.guild_uuid)
+                                    .await?
+                                    .check_permission(
+                                        &mut conn,
+                                        &app_state.cache_pool,
+                                        Permissions::BypassSlowmode,
+                                    )
+                                    .await
+                                    .is_ok();
+
+                            if !bypass_slowmode
+                                && let Some(retry_after) = channel
+                                    .check_slowmode(&app_state.cache_pool, uuid)
+                                    .await?
+                            {
+                                let error = serde_json::to_string(&SendEvent::Error {
+                                    entity: SendError {
+                                        message: format!(
+                                            "Slowmode is enabled, try again in {retry_after} seconds"
+                                        ),
+                                        retry_after: Some(retry_after),
+                                    },
+                                })?;
+
+                                sender.lock().await.send(error.into()).await?;
+
+                                continue;
+                            }
+
+                            let message = match channel
                                 .new_message(
-                                    &mut app_state.pool.get().await?,
+                                    &mut conn,
                                     &app_state.cache_pool,
                                     uuid,
                                     entity.text,
                                     entity.reply_to,
                                 )
-                                .await?;
-
-                            redis::cmd("PUBLISH")
-                                .arg(&[
-                                    channel_uuid.to_string(),
-                                    serde_json::to_string(&SendEvent::MessageSend {
-                                        entity: message,
-                                    })?,
-                                ])
-                                .exec_async(
-                                    &mut app_state
-                                        .cache_pool
-                                        .get_multiplexed_tokio_connection()
-                                        .await?,
-                                )
+                                .await
+                            {
+                                Ok(message) => message,
+                                Err(error) => {
+                                    if !bypass_slowmode {
+                                        channel.clear_slowmode(&app_state.cache_pool, uuid).await?;
+                                    }
+
+                                    return Err(error);
+                                }
+                            };
+
+                            let payload =
+                                serde_json::to_string(&SendEvent::MessageSend { entity: message })?;
+
+                            app_state
+                                .cache_pool
+                                .publish(channel_uuid.to_string(), payload)
                                 .await?;
                         }
                         ReceiveEvent::MessageEdit { entity } => {
                             use messages::dsl;
                             let mut message: MessageBuilder = dsl::messages
                                 .filter(dsl::uuid.eq(entity.uuid))
+                                .filter(dsl::channel_uuid.eq(channel_uuid))
                                 .select(MessageBuilder::as_select())
                                 .get_result(&mut app_state.pool.get().await?)
                                 .await?;
 
                             if uuid != message.user_uuid {
-                                redis::cmd("PUBLISH")
-                                    .arg(&[
-                                        channel_uuid.to_string(),
-                                        serde_json::to_string(&SendEvent::Error {
-                                            entity: SendError {
-                                                message: "Not allowed".to_string(),
-                                            },
-                                        })?,
-                                    ])
-                                    .exec_async(
-                                        &mut app_state
-                                            .cache_pool
-                                            .get_multiplexed_tokio_connection()
-                                            .await?,
-                                    )
+                                let payload = serde_json::to_string(&SendEvent::Error {
+                                    entity: SendError {
+                                        message: "Not allowed".to_string(),
+                                        retry_after: None,
+                                    },
+                                })?;
+
+                                app_state
+                                    .cache_pool
+                                    .publish(channel_uuid.to_string(), payload)
                                     .await?;
 
                                 continue;
@@ -199,50 +277,42 @@ pub async fn ws(
 
                             message.message = entity.text;
 
-                            redis::cmd("PUBLISH")
-                                .arg(&[
-                                    channel_uuid.to_string(),
-                                    serde_json::to_string(&SendEvent::MessageEdit {
-                                        entity: message
-                                            .build(
-                                                &mut app_state.pool.get().await?,
-                                                &app_state.cache_pool,
-                                            )
-                                            .await?,
-                                    })?,
-                                ])
-                                .exec_async(
-                                    &mut app_state
-                                        .cache_pool
-                                        .get_multiplexed_tokio_connection()
-                                        .await?,
+                            let message = message
+                                .build(
+                                    &mut app_state.pool.get().await?,
+                                    &app_state.cache_pool,
+                                    channel.guild_uuid,
                                 )
                                 .await?;
+
+                            let payload =
+                                serde_json::to_string(&SendEvent::MessageEdit { entity: message })?;
+
+                            app_state
+                                .cache_pool
+                                .publish(channel_uuid.to_string(), payload)
+                                .await?;
                         }
                         ReceiveEvent::MessageDelete { entity } => {
                             use messages::dsl;
                             let message: MessageBuilder = dsl::messages
                                 .filter(dsl::uuid.eq(entity.uuid))
+                                .filter(dsl::channel_uuid.eq(channel_uuid))
                                 .select(MessageBuilder::as_select())
                                 .get_result(&mut app_state.pool.get().await?)
                                 .await?;
 
                             if uuid != message.user_uuid {
-                                redis::cmd("PUBLISH")
-                                    .arg(&[
-                                        channel_uuid.to_string(),
-                                        serde_json::to_string(&SendEvent::Error {
-                                            entity: SendError {
-                                                message: "Not allowed".to_string(),
-                                            },
-                                        })?,
-                                    ])
-                                    .exec_async(
-                                        &mut app_state
-                                            .cache_pool
-                                            .get_multiplexed_tokio_connection()
-                                            .await?,
-                                    )
+                                let payload = serde_json::to_string(&SendEvent::Error {
+                                    entity: SendError {
+                                        message: "Not allowed".to_string(),
+                                        retry_after: None,
+                                    },
+                                })?;
+
+                                app_state
+                                    .cache_pool
+                                    .publish(channel_uuid.to_string(), payload)
                                     .await?;
 
                                 continue;
@@ -253,17 +323,12 @@ pub async fn ws(
                                 .execute(&mut app_state.pool.get().await?)
                                 .await?;
 
-                            redis::cmd("PUBLISH")
-                                .arg(&[
-                                    channel_uuid.to_string(),
-                                    serde_json::to_string(&SendEvent::MessageDelete { entity })?,
-                                ])
-                                .exec_async(
-                                    &mut app_state
-                                        .cache_pool
-                                        .get_multiplexed_tokio_connection()
-                                        .await?,
-                                )
+                            let payload =
+                                serde_json::to_string(&SendEvent::MessageDelete { entity })?;
+
+                            app_state
+                                .cache_pool
+                                .publish(channel_uuid.to_string(), payload)
                                 .await?;
                         }
                     }
@@ -272,6 +337,67 @@ pub async fn ws(
 
             Ok::<(), crate::error::Error>(())
         });
+
+        app_state.tasks.spawn(async move {
+            let disconnect_channel = format!("{uuid}_disconnect");
+            let blocks_channel = format!("{uuid}_blocks");
+
+            pubsub.subscribe(channel_uuid.to_string()).await?;
+            // Events of the user that aren't tied to a channel, such as friend requests
+            pubsub.subscribe(format!("{uuid}_events")).await?;
+            pubsub.subscribe(&disconnect_channel).await?;
+            pubsub.subscribe(&blocks_channel).await?;
+
+            let mut messages = pubsub.on_message();
+
+            loop {
+                let msg = tokio::select! {
+                    msg = messages.next() => msg,
+                    _ = app_state.shutdown.cancelled() => {
+                        let _ = (&mut receive_task).await;
+
+                        // Clients reconnect on this code, the load balancer sends them to a replica that isn't shutting down
+                        let frame = CloseFrame {
+                            code: close_code::RESTART,
+                            reason: "reconnect".into(),
+                        };
+
+                        pubsub_sender.lock().await.send(Message::Close(Some(frame))).await?;
+                        break;
+                    }
+                };
+
+                let Some(msg) = msg else {
+                    break;
+                };
+
+                // Sessions of the user were revoked, stop handling their messages
+                if msg.get_channel_name() == disconnect_channel {
+                    let payload: String = msg.get_payload()?;
+
+                    // An empty payload revokes every session, otherwise it lists the revoked ones
+                    if !payload.is_empty()
+                        && !serde_json::from_str::<Vec<Uuid>>(&payload)?.contains(&session_uuid)
+                    {
+                        continue;
+                    }
+
+                    receive_task.abort();
+                    pubsub_sender.lock().await.send(Message::Close(None)).await?;
+                    break;
+                }
+
+                if msg.get_channel_name() == blocks_channel {
+                    blocked = UserBlock::blocked_by(&mut app_state.pool.get().await?, uuid).await?;
+                    continue;
+                }
+
+                let payload = flag_blocked_author(msg.get_payload()?, &blocked);
+                pubsub_sender.lock().await.send(payload.into()).await?;
+            }
+
+            Ok::<(), crate::error::Error>(())
+        });
     });
 
     let headers = res.headers_mut();
@@ -284,3 +410,30 @@ pub async fn ws(
     // respond immediately with response connected to WS session
     Ok(res)
 }
+
+/// Messages are published once for every subscriber, the author is flagged here for subscribers who blocked them
+fn flag_blocked_author(payload: String, blocked: &HashSet<Uuid>) -> String {
+    if blocked.is_empty() {
+        return payload;
+    }
+
+    let Ok(mut event) = serde_json::from_str::<serde_json::Value>(&payload) else {
+        return payload;
+    };
+
+    if !matches!(event["event"].as_str(), Some("MessageSend" | "MessageEdit")) {
+        return payload;
+    }
+
+    let author = event["entity"]["user_uuid"]
+        .as_str()
+        .and_then(|user_uuid| Uuid::parse_str(user_uuid).ok());
+
+    match author.is_some_and(|author| blocked.contains(&author)) {
+        true => {
+            event["entity"]["author_blocked"] = true.into();
+            event.to_string()
+        }
+        false => payload,
+    }
+}
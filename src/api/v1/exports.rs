@@ -0,0 +1,42 @@
+//! `/api/v1/exports` Contains endpoint for downloading data exports
+
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::{AppState, error::Error, objects::DataExport};
+
+/// `GET /api/v1/exports/{token}` Downloads a data export as a zip archive
+///
+/// requires auth: no, the token from the export email is used instead
+///
+/// ### Responses
+/// 200 Success
+///
+/// 400 Link is invalid or has expired
+///
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    let data_export = DataExport::get(&app_state.cache_pool, &token).await?;
+
+    let archive = data_export.download(app_state).await?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"data-export-{}.zip\"",
+                    data_export.created_at.format("%Y-%m-%d")
+                ),
+            ),
+        ],
+        archive,
+    ))
+}
@@ -128,7 +128,7 @@ pub async fn get_guilds(
 
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let guilds = Guild::fetch_amount(&mut conn, start, amount).await?;
 
@@ -21,7 +21,7 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let caller = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
     caller
@@ -40,7 +40,7 @@ pub async fn unban(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let caller = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
     caller
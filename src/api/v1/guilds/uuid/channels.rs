@@ -10,9 +10,10 @@ use serde::Deserialize;
 use crate::{
     AppState,
     api::v1::auth::CurrentUser,
+    cache::CacheKey,
     error::Error,
     objects::{Channel, Member, Permissions},
-    utils::{CacheFns, global_checks, order_by_is_above},
+    utils::{global_checks, order_by_is_above},
 };
 
 #[derive(Deserialize)]
@@ -28,13 +29,13 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
-    if let Ok(cache_hit) = app_state
+    if let Ok(Some(cache_hit)) = app_state
         .cache_pool
-        .get_cache_key::<Vec<Channel>>(format!("{guild_uuid}_channels"))
+        .get::<Vec<Channel>>(&CacheKey::GuildChannels(guild_uuid))
         .await
     {
         return Ok((StatusCode::OK, Json(cache_hit)).into_response());
@@ -46,9 +47,9 @@ pub async fn get(
 
     app_state
         .cache_pool
-        .set_cache_key(
-            format!("{guild_uuid}_channels"),
-            channels_ordered.clone(),
+        .set(
+            &CacheKey::GuildChannels(guild_uuid),
+            &channels_ordered,
             1800,
         )
         .await?;
@@ -64,7 +65,7 @@ pub async fn create(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let member = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
@@ -14,6 +14,7 @@ mod bans;
 mod channels;
 mod invites;
 mod members;
+mod reports;
 mod roles;
 
 use crate::{
@@ -44,6 +45,10 @@ pub fn router() -> Router<&'static AppState> {
         // Bans
         .route("/bans", get(bans::get))
         .route("/bans/{uuid}", delete(bans::unban))
+        // Reports
+        .route("/report", post(reports::report))
+        .route("/reports", get(reports::get))
+        .route("/reports/{report_uuid}", patch(reports::patch))
 }
 
 /// `GET /api/v1/guilds/{uuid}` DESCRIPTION
@@ -86,7 +91,7 @@ pub async fn get_guild(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
@@ -106,7 +111,7 @@ pub async fn edit(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let member = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
@@ -129,7 +134,7 @@ pub async fn edit(
     }
 
     if let Some(icon) = icon {
-        guild.set_icon(&mut conn, &app_state, icon).await?;
+        guild.set_icon(&mut conn, app_state, icon).await?;
     }
 
     Ok(StatusCode::OK)
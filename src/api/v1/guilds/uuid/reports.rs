@@ -0,0 +1,157 @@
+//! `/api/v1/guilds/{uuid}/reports` Reporting guilds and the report queue of a guild
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    api::v1::auth::CurrentUser,
+    error::Error,
+    objects::{Member, Permissions, Report, ReportQuery, ReportRequest, ReportResolution},
+    utils::global_checks,
+};
+
+/// `POST /api/v1/guilds/{uuid}/report` Reports a guild to the instance administrators
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "category": "harassment",
+///     "reason": "Keeps insulting people"
+/// });
+/// ```
+///
+/// `category` is one of `spam`, `harassment`, `hate`, `violence`, `sexual`, `self_harm`, `illegal` or `other`
+pub async fn report(
+    State(app_state): State<&'static AppState>,
+    Path(guild_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(report_request): Json<ReportRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let report = Report::report_guild(&mut conn, uuid, guild_uuid, report_request).await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
+
+/// `GET /api/v1/guilds/{uuid}/reports` Returns reports of messages in the guild, newest first
+///
+/// The reporter isn't included, only instance administrators can see who made a report
+///
+/// requires auth: yes
+///
+/// requires permission: ManageReports
+///
+/// ### Query Parameters
+/// status: `open`, `actioned` or `dismissed`
+///
+/// start, amount
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "uuid": "0198c3a1-5b2e-7f40-9c1d-2e3f4a5b6c7d",
+///         "kind": "message",
+///         "target_uuid": "01971976-8618-74c0-b040-7ffbc44823f6",
+///         "guild_uuid": "5ba61ec7-5f97-43e1-89a5-d4693c155612",
+///         "category": "harassment",
+///         "reason": "Keeps insulting people",
+///         "message_author_uuid": "d48a3317-7b4d-443f-a250-ea9ab2bb8661",
+///         "message_content": "reported message",
+///         "status": "open",
+///         "resolver_uuid": null,
+///         "resolution_note": null,
+///         "created_at": 1755771824,
+///         "resolved_at": null
+///     }
+/// ]);
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Path(guild_uuid): Path<Uuid>,
+    Query(report_query): Query<ReportQuery>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let start = report_query.start.unwrap_or(0);
+
+    let amount = report_query.amount.unwrap_or(50);
+
+    if amount > 100 {
+        return Err(Error::BadRequest("Amount can't exceed 100".to_string()));
+    }
+
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let caller = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
+    caller
+        .check_permission(&mut conn, &app_state.cache_pool, Permissions::ManageReports)
+        .await?;
+
+    let reports = Report::fetch_amount(
+        &mut conn,
+        Some(guild_uuid),
+        report_query.status,
+        start,
+        amount,
+    )
+    .await?
+    .into_iter()
+    .map(Report::for_guild_moderators)
+    .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(reports)))
+}
+
+/// `PATCH /api/v1/guilds/{uuid}/reports/{report_uuid}` Changes the status of a report in the guild
+///
+/// requires auth: yes
+///
+/// requires permission: ManageReports
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "status": "actioned",
+///     "note": "Member was banned"
+/// });
+/// ```
+///
+/// `status` is one of `open`, `actioned` or `dismissed`, reopening a report clears its resolution
+pub async fn patch(
+    State(app_state): State<&'static AppState>,
+    Path((guild_uuid, report_uuid)): Path<(Uuid, Uuid)>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(resolution): Json<ReportResolution>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let caller = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
+    caller
+        .check_permission(&mut conn, &app_state.cache_pool, Permissions::ManageReports)
+        .await?;
+
+    let mut report = Report::fetch_one(&mut conn, report_uuid).await?;
+
+    if report.guild_uuid != Some(guild_uuid) {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    report.resolve(&mut conn, uuid, resolution).await?;
+
+    Ok((StatusCode::OK, Json(report.for_guild_moderators())))
+}
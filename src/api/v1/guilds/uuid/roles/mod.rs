@@ -10,9 +10,10 @@ use serde::Deserialize;
 use crate::{
     AppState,
     api::v1::auth::CurrentUser,
+    cache::CacheKey,
     error::Error,
     objects::{Member, Permissions, Role},
-    utils::{CacheFns, global_checks, order_by_is_above},
+    utils::{global_checks, order_by_is_above},
 };
 
 pub mod uuid;
@@ -29,13 +30,13 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
-    if let Ok(cache_hit) = app_state
+    if let Ok(Some(cache_hit)) = app_state
         .cache_pool
-        .get_cache_key::<Vec<Role>>(format!("{guild_uuid}_roles"))
+        .get::<Vec<Role>>(&CacheKey::GuildRoles(guild_uuid))
         .await
     {
         return Ok((StatusCode::OK, Json(cache_hit)).into_response());
@@ -47,7 +48,7 @@ pub async fn get(
 
     app_state
         .cache_pool
-        .set_cache_key(format!("{guild_uuid}_roles"), roles_ordered.clone(), 1800)
+        .set(&CacheKey::GuildRoles(guild_uuid), &roles_ordered, 1800)
         .await?;
 
     Ok((StatusCode::OK, Json(roles_ordered)).into_response())
@@ -61,7 +62,7 @@ pub async fn create(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let member = Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
@@ -69,7 +70,13 @@ pub async fn create(
         .check_permission(&mut conn, &app_state.cache_pool, Permissions::ManageRole)
         .await?;
 
-    let role = Role::new(&mut conn, guild_uuid, role_info.name.clone()).await?;
+    let role = Role::new(
+        &mut conn,
+        &app_state.cache_pool,
+        guild_uuid,
+        role_info.name.clone(),
+    )
+    .await?;
 
     Ok((StatusCode::OK, Json(role)).into_response())
 }
@@ -9,9 +9,10 @@ use axum::{
 use crate::{
     AppState,
     api::v1::auth::CurrentUser,
+    cache::CacheKey,
     error::Error,
     objects::{Member, Role},
-    utils::{CacheFns, global_checks},
+    utils::global_checks,
 };
 
 pub async fn get(
@@ -21,13 +22,13 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     Member::check_membership(&mut conn, uuid, guild_uuid).await?;
 
-    if let Ok(cache_hit) = app_state
+    if let Ok(Some(cache_hit)) = app_state
         .cache_pool
-        .get_cache_key::<Role>(format!("{role_uuid}"))
+        .get::<Role>(&CacheKey::Role(role_uuid))
         .await
     {
         return Ok((StatusCode::OK, Json(cache_hit)).into_response());
@@ -37,7 +38,7 @@ pub async fn get(
 
     app_state
         .cache_pool
-        .set_cache_key(format!("{role_uuid}"), role.clone(), 60)
+        .set(&CacheKey::Role(role_uuid), &role, 60)
         .await?;
 
     Ok((StatusCode::OK, Json(role)).into_response())
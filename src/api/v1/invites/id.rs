@@ -34,7 +34,7 @@ pub async fn join(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let invite = Invite::fetch_one(&mut conn, invite_id).await?;
 
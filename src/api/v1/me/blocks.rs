@@ -0,0 +1,105 @@
+//! `/api/v1/me/blocks` Endpoints for blocking other users
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState, api::v1::auth::CurrentUser, error::Error, objects::Me, utils::global_checks,
+};
+
+/// `GET /api/v1/me/blocks` Returns the users you have blocked
+///
+/// requires auth: yes
+///
+/// ### Response Example
+/// ```
+/// json!([
+///     {
+///         "user": {
+///             "uuid": "0198a5b8-0d2f-7e61-b5c1-6f4a3c2d1e0f",
+///             "username": "someone",
+///             "display_name": null,
+///             "avatar": null,
+///             "pronouns": null,
+///             "about": null,
+///             "online_status": 0,
+///             "friends_since": null
+///         },
+///         "blocked_at": "2025-08-20T12:00:00Z"
+///     }
+/// ]);
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    let blocks = me.get_blocks(&mut conn, &app_state.cache_pool).await?;
+
+    Ok((StatusCode::OK, Json(blocks)))
+}
+
+/// `POST /api/v1/me/blocks/{uuid}` Blocks the user
+///
+/// Removes any friendship or pending friend request with the user and stops them from sending you friend requests
+///
+/// requires auth: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found
+///
+/// 400 Bad Request (trying to block yourself)
+///
+pub async fn post(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.block_user(&mut conn, app_state, user_uuid).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// `DELETE /api/v1/me/blocks/{uuid}` Unblocks the user
+///
+/// requires auth: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found (the user isn't blocked)
+///
+pub async fn delete(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.unblock_user(&mut conn, &app_state.cache_pool, user_uuid)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,28 @@
+//! `/api/v1/me/export` Endpoint for requesting a copy of your data
+
+use axum::{Extension, extract::State, http::StatusCode, response::IntoResponse};
+use uuid::Uuid;
+
+use crate::{AppState, api::v1::auth::CurrentUser, error::Error, objects::DataExport};
+
+/// `POST /api/v1/me/export` Requests an export of your data
+///
+/// The export is built in the background and a download link valid for 7 days is sent to your email address
+///
+/// requires auth: yes
+///
+/// ### Responses
+/// 202 Export started
+///
+/// 429 Too Many Requests
+///
+/// 401 Unauthorized
+///
+pub async fn post(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    DataExport::request(app_state, uuid).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
@@ -2,6 +2,7 @@ use ::uuid::Uuid;
 use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
 use serde::Deserialize;
 
+pub mod requests;
 pub mod uuid;
 
 use crate::{
@@ -19,7 +20,7 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let me = Me::get(&mut conn, uuid).await?;
 
@@ -40,17 +41,27 @@ pub struct UserReq {
 /// ### Request Example:
 /// ```
 /// json!({
-///     "uuid": "155d2291-fb23-46bd-a656-ae7c5d8218e6",
+///     "username": "someone",
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "sender": "0198a5b8-0d2f-7e61-b5c1-6f4a3c2d1e0f",
+///     "receiver": "0198a5b8-4c1e-7a02-9d3b-2e5f6a7b8c9d",
+///     "requested_at": "2025-08-19T12:00:00Z"
 /// });
 /// ```
-/// NOTE: UUIDs in this response are made using `uuidgen`, UUIDs made by the actual backend will be UUIDv7 and have extractable timestamps
 ///
 /// ### Responses
 /// 200 Success
 ///
 /// 404 Not Found
 ///
-/// 400 Bad Request (usually means users are already friends)
+/// 400 Bad Request (users are already friends or a request between them is already pending)
+///
+/// 429 Too Many Requests (too many pending outgoing requests)
 ///
 pub async fn post(
     State(app_state): State<&'static AppState>,
@@ -59,12 +70,14 @@ pub async fn post(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let me = Me::get(&mut conn, uuid).await?;
 
     let target_uuid = user_uuid_from_username(&mut conn, &user_request.username).await?;
-    me.add_friend(&mut conn, target_uuid).await?;
+    let friend_request = me
+        .send_friend_request(&mut conn, app_state, target_uuid)
+        .await?;
 
-    Ok(StatusCode::OK)
+    Ok((StatusCode::OK, Json(friend_request)))
 }
@@ -0,0 +1,141 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState, api::v1::auth::CurrentUser, error::Error, objects::Me, utils::global_checks,
+};
+
+/// `GET /api/v1/me/friends/requests` Returns pending friend requests sent to and by you
+///
+/// requires auth: yes
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "incoming": [
+///         {
+///             "user": {
+///                 "uuid": "0198a5b8-0d2f-7e61-b5c1-6f4a3c2d1e0f",
+///                 "username": "someone",
+///                 "display_name": null,
+///                 "avatar": null,
+///                 "pronouns": null,
+///                 "about": null,
+///                 "online_status": 0,
+///                 "friends_since": null
+///             },
+///             "requested_at": "2025-08-19T12:00:00Z"
+///         }
+///     ],
+///     "outgoing": []
+/// });
+/// ```
+pub async fn get(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    let friend_requests = me
+        .get_friend_requests(&mut conn, &app_state.cache_pool)
+        .await?;
+
+    Ok((StatusCode::OK, Json(friend_requests)))
+}
+
+/// `POST /api/v1/me/friends/requests/{uuid}/accept` Accepts the friend request sent by the user
+///
+/// requires auth: yes
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "uuid1": "0198a5b8-0d2f-7e61-b5c1-6f4a3c2d1e0f",
+///     "uuid2": "0198a5b8-4c1e-7a02-9d3b-2e5f6a7b8c9d",
+///     "accepted_at": "2025-08-19T12:00:00Z"
+/// });
+/// ```
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found (the user hasn't sent you a friend request)
+///
+pub async fn accept(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    let friend = me
+        .accept_friend_request(&mut conn, app_state, user_uuid)
+        .await?;
+
+    Ok((StatusCode::OK, Json(friend)))
+}
+
+/// `POST /api/v1/me/friends/requests/{uuid}/decline` Declines the friend request sent by the user
+///
+/// requires auth: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found (the user hasn't sent you a friend request)
+///
+pub async fn decline(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.decline_friend_request(&mut conn, app_state, user_uuid)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// `DELETE /api/v1/me/friends/requests/{uuid}` Cancels the friend request you sent to the user
+///
+/// requires auth: yes
+///
+/// ### Responses
+/// 200 Success
+///
+/// 404 Not Found (you haven't sent the user a friend request)
+///
+pub async fn delete(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.cancel_friend_request(&mut conn, app_state, user_uuid)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
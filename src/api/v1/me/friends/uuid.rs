@@ -17,11 +17,11 @@ pub async fn delete(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let me = Me::get(&mut conn, uuid).await?;
 
-    me.remove_friend(&mut conn, friend_uuid).await?;
+    me.remove_friend(&mut conn, app_state, friend_uuid).await?;
 
     Ok(StatusCode::OK)
 }
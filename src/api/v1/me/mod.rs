@@ -6,13 +6,21 @@ use axum::{
     routing::{delete, get, patch, post},
 };
 use bytes::Bytes;
-use serde::Deserialize;
+use diesel_async::{AsyncConnection, scoped_futures::ScopedFutureExt};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    AppState, api::v1::auth::CurrentUser, error::Error, objects::Me, utils::global_checks,
+    AppState,
+    api::v1::auth::CurrentUser,
+    cache::CacheKey,
+    error::Error,
+    objects::{AccountDeletion, Me, Totp},
+    utils::global_checks,
 };
 
+mod blocks;
+mod export;
 mod friends;
 mod guilds;
 
@@ -25,9 +33,27 @@ pub fn router() -> Router<&'static AppState> {
                 100 * 1024 * 1024, /* limit is in bytes */
             )),
         )
+        .route("/", delete(delete_me))
+        .route("/export", post(export::post))
+        .route("/blocks", get(blocks::get))
+        .route("/blocks/{uuid}", post(blocks::post))
+        .route("/blocks/{uuid}", delete(blocks::delete))
         .route("/guilds", get(guilds::get))
         .route("/friends", get(friends::get))
         .route("/friends", post(friends::post))
+        .route("/friends/requests", get(friends::requests::get))
+        .route(
+            "/friends/requests/{uuid}",
+            delete(friends::requests::delete),
+        )
+        .route(
+            "/friends/requests/{uuid}/accept",
+            post(friends::requests::accept),
+        )
+        .route(
+            "/friends/requests/{uuid}/decline",
+            post(friends::requests::decline),
+        )
         .route("/friends/{uuid}", delete(friends::uuid::delete))
 }
 
@@ -48,6 +74,10 @@ struct NewInfo {
     pronouns: Option<String>,
     about: Option<String>,
     online_status: Option<i16>,
+    /// Language emails are sent in, such as `en` or `pt-BR`, an empty string resets it
+    locale: Option<String>,
+    /// Required to change the email address when two-factor authentication is enabled
+    totp_code: Option<String>,
 }
 
 pub async fn update(
@@ -75,44 +105,142 @@ pub async fn update(
     let mut conn = app_state.pool.get().await?;
 
     if avatar.is_some() || json.username.is_some() || json.display_name.is_some() {
-        global_checks(&mut conn, &app_state.config, uuid).await?;
+        global_checks(&mut conn, app_state, uuid).await?;
     }
 
     let mut me = Me::get(&mut conn, uuid).await?;
 
-    if let Some(avatar) = avatar {
-        me.set_avatar(&mut conn, &app_state, avatar).await?;
+    // Everything is checked before the first change is saved, so a rejected update doesn't leave part of it applied
+    if json.email.is_some() {
+        Totp::require(&mut conn, app_state, uuid, json.totp_code.as_ref()).await?;
     }
 
-    if let Some(username) = &json.username {
-        me.set_username(&mut conn, &app_state.cache_pool, username.clone())
-            .await?;
+    if let Some(avatar) = &avatar {
+        Me::check_avatar(avatar.clone()).await?;
     }
 
-    if let Some(display_name) = &json.display_name {
-        me.set_display_name(&mut conn, &app_state.cache_pool, display_name.clone())
-            .await?;
-    }
+    let NewInfo {
+        username,
+        display_name,
+        email,
+        pronouns,
+        about,
+        online_status,
+        locale,
+        ..
+    } = json;
+
+    let profile_changed = username.is_some()
+        || display_name.is_some()
+        || email.is_some()
+        || pronouns.is_some()
+        || about.is_some()
+        || online_status.is_some();
+
+    let me_ref = &mut me;
+
+    conn.transaction::<_, Error, _>(|conn| {
+        async move {
+            if let Some(username) = username {
+                me_ref.set_username(conn, username).await?;
+            }
+
+            if let Some(display_name) = display_name {
+                me_ref.set_display_name(conn, display_name).await?;
+            }
+
+            if let Some(email) = email {
+                me_ref.set_email(conn, email).await?;
+            }
+
+            if let Some(pronouns) = pronouns {
+                me_ref.set_pronouns(conn, pronouns).await?;
+            }
+
+            if let Some(about) = about {
+                me_ref.set_about(conn, about).await?;
+            }
+
+            if let Some(online_status) = online_status {
+                me_ref.set_online_status(conn, online_status).await?;
+            }
+
+            if let Some(locale) = locale {
+                me_ref.set_locale(conn, locale).await?;
+            }
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
 
-    if let Some(email) = &json.email {
-        me.set_email(&mut conn, &app_state.cache_pool, email.clone())
-            .await?;
+    if profile_changed {
+        app_state.cache_pool.del(&[CacheKey::User(uuid)]).await?;
     }
 
-    if let Some(pronouns) = &json.pronouns {
-        me.set_pronouns(&mut conn, &app_state.cache_pool, pronouns.clone())
-            .await?;
+    // Uploaded last, the old avatar is deleted from storage and can't be rolled back
+    if let Some(avatar) = avatar {
+        me.set_avatar(&mut conn, app_state, avatar).await?;
     }
 
-    if let Some(about) = &json.about {
-        me.set_about(&mut conn, &app_state.cache_pool, about.clone())
-            .await?;
-    }
+    Ok(StatusCode::OK)
+}
 
-    if let Some(online_status) = &json.online_status {
-        me.set_online_status(&mut conn, &app_state.cache_pool, *online_status)
-            .await?;
-    }
+#[derive(Deserialize)]
+pub struct DeleteInformation {
+    password: String,
+    totp_code: Option<String>,
+}
 
-    Ok(StatusCode::OK)
+#[derive(Serialize)]
+pub struct DeletionResponse {
+    deleted_at: i64,
+}
+
+/// `DELETE /api/v1/me` Requests deletion of the account
+///
+/// The account is logged out everywhere and deleted once the instance's grace period has passed,
+/// logging in again before then cancels the deletion
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "password": "sha512hashedpassword",
+///     "totp_code": "123456"
+/// });
+/// ```
+///
+/// ### Response Example
+/// ```
+/// json!({
+///     "deleted_at": 1757808000
+/// });
+/// ```
+/// NOTE: `totp_code` is only required when two-factor authentication is enabled
+pub async fn delete_me(
+    State(app_state): State<&'static AppState>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(delete_information): Json<DeleteInformation>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let me = Me::get(&mut conn, uuid).await?;
+
+    me.verify_password(&mut conn, app_state, &delete_information.password)
+        .await?;
+
+    Totp::require(
+        &mut conn,
+        app_state,
+        uuid,
+        delete_information.totp_code.as_ref(),
+    )
+    .await?;
+
+    let deleted_at = AccountDeletion::schedule(&mut conn, app_state, uuid).await?;
+
+    Ok((StatusCode::OK, Json(DeletionResponse { deleted_at })))
 }
@@ -29,7 +29,7 @@ pub async fn post(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let member =
         Member::fetch_one_with_uuid(&mut conn, &app_state.cache_pool, None, member_uuid).await?;
@@ -4,8 +4,10 @@ use axum::{Router, middleware::from_fn_with_state, routing::get};
 
 use crate::{AppState, api::v1::auth::CurrentUser};
 
-mod auth;
+mod admin;
+pub mod auth;
 mod channels;
+mod exports;
 mod guilds;
 mod invites;
 mod me;
@@ -20,10 +22,12 @@ pub fn router(app_state: &'static AppState) -> Router<&'static AppState> {
         .nest("/invites", invites::router())
         .nest("/members", members::router())
         .nest("/me", me::router())
+        .nest("/admin", admin::router(app_state))
         .layer(from_fn_with_state(app_state, CurrentUser::check_auth_layer));
 
     Router::new()
         .route("/stats", get(stats::res))
+        .route("/exports/{token}", get(exports::get))
         .nest("/auth", auth::router(app_state))
         .nest("/channels", channels::router(app_state))
         .merge(router_with_auth)
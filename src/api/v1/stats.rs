@@ -12,6 +12,7 @@ use serde::Serialize;
 
 use crate::AppState;
 use crate::error::Error;
+use crate::objects::InstanceSettings;
 use crate::schema::users::dsl::{users, uuid};
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
@@ -43,11 +44,12 @@ struct Response {
 /// });
 /// ```
 pub async fn res(State(app_state): State<&'static AppState>) -> Result<impl IntoResponse, Error> {
-    let accounts: i64 = users
-        .select(uuid)
-        .count()
-        .get_result(&mut app_state.pool.get().await?)
-        .await?;
+    let mut conn = app_state.pool.get().await?;
+
+    let accounts: i64 = users.select(uuid).count().get_result(&mut conn).await?;
+
+    let instance_settings =
+        InstanceSettings::get(&mut conn, &app_state.cache_pool, &app_state.config).await?;
 
     let response = Response {
         // TODO: Get number of accounts from db
@@ -57,8 +59,8 @@ pub async fn res(State(app_state): State<&'static AppState>) -> Result<impl Into
             .expect("Seriously why dont you have time??")
             .as_secs(),
         version: String::from(VERSION.unwrap_or("UNKNOWN")),
-        registration_enabled: app_state.config.instance.registration,
-        email_verification_required: app_state.config.instance.require_email_verification,
+        registration_enabled: instance_settings.registration,
+        email_verification_required: instance_settings.require_email_verification,
         // TODO: Get build number from git hash or remove this from the spec
         build_number: String::from(GIT_SHORT_HASH),
     };
@@ -6,14 +6,14 @@ use axum::{
     extract::{Query, State},
     http::StatusCode,
     response::IntoResponse,
-    routing::get,
+    routing::{get, post},
 };
 
 use crate::{
     AppState,
     api::v1::auth::CurrentUser,
     error::Error,
-    objects::{StartAmountQuery, User},
+    objects::{InstancePermissions, StartAmountQuery, User},
     utils::global_checks,
 };
 
@@ -23,6 +23,7 @@ pub fn router() -> Router<&'static AppState> {
     Router::new()
         .route("/", get(users))
         .route("/{uuid}", get(uuid::get))
+        .route("/{uuid}/report", post(uuid::report))
 }
 
 /// `GET /api/v1/users` Returns all users on this instance
@@ -70,7 +71,9 @@ pub async fn users(
 
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    InstancePermissions::check_admin(&mut conn, uuid).await?;
 
     let users = User::fetch_amount(&mut conn, start, amount).await?;
 
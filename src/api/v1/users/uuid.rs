@@ -12,7 +12,7 @@ use crate::{
     AppState,
     api::v1::auth::CurrentUser,
     error::Error,
-    objects::{Me, User},
+    objects::{Me, Report, ReportRequest, User},
     utils::global_checks,
 };
 
@@ -39,7 +39,7 @@ pub async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let mut conn = app_state.pool.get().await?;
 
-    global_checks(&mut conn, &app_state.config, uuid).await?;
+    global_checks(&mut conn, app_state, uuid).await?;
 
     let me = Me::get(&mut conn, uuid).await?;
 
@@ -48,3 +48,38 @@ pub async fn get(
 
     Ok((StatusCode::OK, Json(user)))
 }
+
+/// `POST /api/v1/users/{uuid}/report` Reports a user to the instance administrators
+///
+/// requires auth: yes
+///
+/// ### Request Example
+/// ```
+/// json!({
+///     "category": "harassment",
+///     "reason": "Keeps insulting people"
+/// });
+/// ```
+///
+/// `category` is one of `spam`, `harassment`, `hate`, `violence`, `sexual`, `self_harm`, `illegal` or `other`
+pub async fn report(
+    State(app_state): State<&'static AppState>,
+    Path(user_uuid): Path<Uuid>,
+    Extension(CurrentUser(uuid)): Extension<CurrentUser<Uuid>>,
+    Json(report_request): Json<ReportRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    global_checks(&mut conn, app_state, uuid).await?;
+
+    let report = Report::report_user(
+        &mut conn,
+        &app_state.cache_pool,
+        uuid,
+        user_uuid,
+        report_request,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
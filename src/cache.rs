@@ -0,0 +1,495 @@
+//! Cache database access shared by every replica.
+//!
+//! Values are stored as json under namespaced [`CacheKey`]s. Entities such as users, channels and roles are
+//! also kept in memory for a short while, deleting them publishes the keys so every replica drops its copy.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use log::warn;
+use redis::aio::{ConnectionManager, PubSub};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{error::Error, telemetry};
+
+/// Prefix of every key, bump it when the format of a cached value changes so old values are ignored
+const CACHE_VERSION: &str = "v1";
+
+/// Longest time a value is kept in memory, limits how stale it can get if an invalidation is missed
+const LOCAL_LIFETIME: Duration = Duration::from_secs(30);
+
+const MAX_LOCAL_ENTRIES: usize = 10000;
+
+#[derive(Clone)]
+pub enum CacheKey {
+    User(Uuid),
+    Channel(Uuid),
+    GuildChannels(Uuid),
+    Role(Uuid),
+    GuildRoles(Uuid),
+    MemberRoles(Uuid),
+    InstanceSettings,
+    Slowmode { channel_uuid: Uuid, user_uuid: Uuid },
+    RateLimit(String),
+    LoginAttempts(String),
+    LoginChallenge(String),
+    TotpEnrolment(Uuid),
+    TotpUsed { user_uuid: Uuid, code: String },
+    PasskeyRegistration(Uuid),
+    PasskeyAuthentication(String),
+    EmailVerification(Uuid),
+    PasswordReset(Uuid),
+    PasswordResetToken(String),
+    DataExport(String),
+    UserDataExport(Uuid),
+    JobLock(&'static str),
+    JobRunning(&'static str),
+    JobStatus(&'static str),
+}
+
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{CACHE_VERSION}:")?;
+
+        match self {
+            CacheKey::User(uuid) => write!(f, "user:{uuid}"),
+            CacheKey::Channel(uuid) => write!(f, "channel:{uuid}"),
+            CacheKey::GuildChannels(uuid) => write!(f, "guild_channels:{uuid}"),
+            CacheKey::Role(uuid) => write!(f, "role:{uuid}"),
+            CacheKey::GuildRoles(uuid) => write!(f, "guild_roles:{uuid}"),
+            CacheKey::MemberRoles(uuid) => write!(f, "member_roles:{uuid}"),
+            CacheKey::InstanceSettings => write!(f, "instance_settings"),
+            CacheKey::Slowmode {
+                channel_uuid,
+                user_uuid,
+            } => write!(f, "slowmode:{channel_uuid}:{user_uuid}"),
+            CacheKey::RateLimit(subject) => write!(f, "rate_limit:{subject}"),
+            CacheKey::LoginAttempts(subject) => write!(f, "login_attempts:{subject}"),
+            CacheKey::LoginChallenge(token) => write!(f, "login_challenge:{token}"),
+            CacheKey::TotpEnrolment(uuid) => write!(f, "totp_enrolment:{uuid}"),
+            CacheKey::TotpUsed { user_uuid, code } => write!(f, "totp_used:{user_uuid}:{code}"),
+            CacheKey::PasskeyRegistration(uuid) => write!(f, "passkey_registration:{uuid}"),
+            CacheKey::PasskeyAuthentication(token) => {
+                write!(f, "passkey_authentication:{token}")
+            }
+            CacheKey::EmailVerification(uuid) => write!(f, "email_verification:{uuid}"),
+            CacheKey::PasswordReset(uuid) => write!(f, "password_reset:{uuid}"),
+            CacheKey::PasswordResetToken(token) => write!(f, "password_reset_token:{token}"),
+            CacheKey::DataExport(token) => write!(f, "data_export:{token}"),
+            CacheKey::UserDataExport(uuid) => write!(f, "user_data_export:{uuid}"),
+            CacheKey::JobLock(name) => write!(f, "job_lock:{name}"),
+            CacheKey::JobRunning(name) => write!(f, "job_running:{name}"),
+            CacheKey::JobStatus(name) => write!(f, "job_status:{name}"),
+        }
+    }
+}
+
+impl CacheKey {
+    /// Entities that are read on most requests are also kept in memory
+    fn is_local(&self) -> bool {
+        matches!(
+            self,
+            CacheKey::User(_)
+                | CacheKey::Channel(_)
+                | CacheKey::GuildChannels(_)
+                | CacheKey::Role(_)
+                | CacheKey::GuildRoles(_)
+                | CacheKey::MemberRoles(_)
+        )
+    }
+}
+
+fn invalidation_channel() -> String {
+    format!("{CACHE_VERSION}:invalidate")
+}
+
+#[derive(Default)]
+struct LocalCache {
+    entries: HashMap<String, (Instant, String)>,
+    /// Increased on every invalidation, values read before an invalidation arrived aren't kept
+    generation: u64,
+}
+
+impl LocalCache {
+    fn get(&mut self, key: &str) -> Option<String> {
+        match self.entries.get(key) {
+            Some((expires_at, value)) if *expires_at > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, generation: u64, key: String, value: String, expire: u32) {
+        if generation != self.generation {
+            return;
+        }
+
+        if self.entries.len() >= MAX_LOCAL_ENTRIES {
+            let now = Instant::now();
+            self.entries.retain(|_, (expires_at, _)| *expires_at > now);
+
+            if self.entries.len() >= MAX_LOCAL_ENTRIES {
+                self.entries.clear();
+            }
+        }
+
+        let lifetime = LOCAL_LIFETIME.min(Duration::from_secs(expire.into()));
+
+        self.entries.insert(key, (Instant::now() + lifetime, value));
+    }
+
+    fn invalidate<'a>(&mut self, keys: impl IntoIterator<Item = &'a str>) {
+        self.generation += 1;
+
+        for key in keys {
+            self.entries.remove(key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+    }
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    client: redis::Client,
+    connection: ConnectionManager,
+    local: Arc<Mutex<LocalCache>>,
+    closed: CancellationToken,
+}
+
+impl Cache {
+    /// Connects to the cache database and starts listening for invalidations from other replicas
+    pub async fn new(url: String) -> Result<Self, Error> {
+        let client = redis::Client::open(url)?;
+
+        let connection = ConnectionManager::new(client.clone()).await?;
+
+        let cache = Cache {
+            client,
+            connection,
+            local: Arc::default(),
+            closed: CancellationToken::new(),
+        };
+
+        let listener = cache.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    result = listener.listen_for_invalidations() => {
+                        if let Err(error) = result {
+                            warn!("cache invalidation listener stopped: {error}");
+                        }
+                    }
+                    _ = listener.closed.cancelled() => break,
+                }
+
+                // Invalidations may have been missed while disconnected
+                listener.local.lock().unwrap().clear();
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+
+        Ok(cache)
+    }
+
+    async fn listen_for_invalidations(&self) -> Result<(), Error> {
+        let mut pubsub = self.pubsub().await?;
+
+        pubsub.subscribe(invalidation_channel()).await?;
+
+        // Anything deleted before the subscription was established could still be held
+        self.local.lock().unwrap().clear();
+
+        let mut messages = pubsub.on_message();
+
+        while let Some(msg) = messages.next().await {
+            let payload: String = msg.get_payload()?;
+
+            self.local.lock().unwrap().invalidate(payload.lines());
+        }
+
+        Ok(())
+    }
+
+    /// Stops listening for invalidations, called on shutdown once nothing uses the cache anymore
+    ///
+    /// The shared connection has no explicit close, it's dropped with the runtime
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Shared connection for commands that aren't covered by the cache functions
+    pub fn connection(&self) -> ConnectionManager {
+        self.connection.clone()
+    }
+
+    /// Pubsub needs a dedicated connection, each subscriber gets its own
+    pub async fn pubsub(&self) -> Result<PubSub, Error> {
+        Ok(self.client.get_async_pubsub().await?)
+    }
+
+    /// Round-trip to the cache database, used by the readiness check
+    pub async fn ping(&self) -> Result<(), Error> {
+        let start = Instant::now();
+
+        redis::cmd("PING")
+            .exec_async(&mut self.connection())
+            .await?;
+
+        telemetry::record_cache_command("PING", start);
+
+        Ok(())
+    }
+
+    pub async fn publish(&self, channel: String, payload: String) -> Result<(), Error> {
+        let start = Instant::now();
+
+        redis::cmd("PUBLISH")
+            .arg(channel)
+            .arg(payload)
+            .exec_async(&mut self.connection())
+            .await?;
+
+        telemetry::record_cache_command("PUBLISH", start);
+
+        Ok(())
+    }
+
+    /// Returns `None` when the key doesn't exist or holds a value that can't be read as `T`
+    pub async fn get<T>(&self, key: &CacheKey) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(self.get_many(std::slice::from_ref(key)).await?.remove(0))
+    }
+
+    /// Fetches several keys in one round-trip, keys held in memory are skipped
+    pub async fn get_many<T>(&self, keys: &[CacheKey]) -> Result<Vec<Option<T>>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let names: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+
+        let (mut values, generation) = {
+            let mut local = self.local.lock().unwrap();
+
+            let values: Vec<Option<String>> = keys
+                .iter()
+                .zip(&names)
+                .map(|(key, name)| key.is_local().then(|| local.get(name)).flatten())
+                .collect();
+
+            (values, local.generation)
+        };
+
+        let missing: Vec<usize> = (0..values.len()).filter(|i| values[*i].is_none()).collect();
+
+        if !missing.is_empty() {
+            let start = Instant::now();
+
+            let fetched: Vec<Option<String>> = redis::cmd("MGET")
+                .arg(missing.iter().map(|i| &names[*i]).collect::<Vec<_>>())
+                .query_async(&mut self.connection())
+                .await?;
+
+            telemetry::record_cache_command("MGET", start);
+
+            let mut local = self.local.lock().unwrap();
+
+            for (i, value) in missing.into_iter().zip(fetched) {
+                if let Some(value) = &value
+                    && keys[i].is_local()
+                {
+                    local.insert(generation, names[i].clone(), value.clone(), u32::MAX);
+                }
+
+                values[i] = value;
+            }
+        }
+
+        Ok(values
+            .into_iter()
+            .map(|value| value.and_then(|value| serde_json::from_str(&value).ok()))
+            .collect())
+    }
+
+    pub async fn set<T>(&self, key: &CacheKey, value: &T, expire: u32) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.set_many(&[(key.clone(), value)], expire).await
+    }
+
+    /// Sets several keys in one round-trip
+    pub async fn set_many<T>(&self, values: &[(CacheKey, T)], expire: u32) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        let generation = self.local.lock().unwrap().generation;
+
+        let mut pipe = redis::pipe();
+        let mut local_values = vec![];
+
+        for (key, value) in values {
+            let name = key.to_string();
+            let value = serde_json::to_string(value)?;
+
+            pipe.cmd("SET")
+                .arg(&name)
+                .arg(&value)
+                .arg("EX")
+                .arg(expire)
+                .ignore();
+
+            if key.is_local() {
+                local_values.push((name, value));
+            }
+        }
+
+        let start = Instant::now();
+
+        pipe.exec_async(&mut self.connection()).await?;
+
+        telemetry::record_cache_command("SET", start);
+
+        let mut local = self.local.lock().unwrap();
+
+        for (name, value) in local_values {
+            local.insert(generation, name, value, expire);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the key only if it doesn't exist yet, returns false if it already did
+    pub async fn set_nx<T>(&self, key: &CacheKey, value: &T, expire: u32) -> Result<bool, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let start = Instant::now();
+
+        let set: Option<String> = redis::cmd("SET")
+            .arg(key.to_string())
+            .arg(serde_json::to_string(value)?)
+            .arg("NX")
+            .arg("EX")
+            .arg(expire)
+            .query_async(&mut self.connection())
+            .await?;
+
+        telemetry::record_cache_command("SET NX", start);
+
+        Ok(set.is_some())
+    }
+
+    /// Seconds until the key expires, negative if it doesn't exist or has no expiry
+    pub async fn ttl(&self, key: &CacheKey) -> Result<i64, Error> {
+        let start = Instant::now();
+
+        let ttl = redis::cmd("TTL")
+            .arg(key.to_string())
+            .query_async(&mut self.connection())
+            .await?;
+
+        telemetry::record_cache_command("TTL", start);
+
+        Ok(ttl)
+    }
+
+    /// Deletes the keys in one round-trip and makes every replica drop its in memory copies
+    pub async fn del(&self, keys: &[CacheKey]) -> Result<(), Error> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let names: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+
+        let local_names: Vec<&str> = keys
+            .iter()
+            .zip(&names)
+            .filter(|(key, _)| key.is_local())
+            .map(|(_, name)| name.as_str())
+            .collect();
+
+        let mut pipe = redis::pipe();
+
+        pipe.cmd("DEL").arg(&names).ignore();
+
+        if !local_names.is_empty() {
+            pipe.cmd("PUBLISH")
+                .arg(invalidation_channel())
+                .arg(local_names.join("\n"))
+                .ignore();
+        }
+
+        let start = Instant::now();
+
+        let result = pipe.exec_async(&mut self.connection()).await;
+
+        telemetry::record_cache_command("DEL", start);
+
+        // Dropped even if the cache database is unreachable, this replica won't keep serving the old value
+        self.local.lock().unwrap().invalidate(local_names);
+
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_versioned_and_namespaced() {
+        let uuid = Uuid::nil();
+
+        assert_eq!(CacheKey::User(uuid).to_string(), format!("v1:user:{uuid}"));
+        assert_eq!(
+            CacheKey::Slowmode {
+                channel_uuid: uuid,
+                user_uuid: uuid,
+            }
+            .to_string(),
+            format!("v1:slowmode:{uuid}:{uuid}")
+        );
+        assert_eq!(
+            CacheKey::RateLimit("ip:127.0.0.1".to_string()).to_string(),
+            "v1:rate_limit:ip:127.0.0.1"
+        );
+        assert_eq!(
+            CacheKey::InstanceSettings.to_string(),
+            "v1:instance_settings"
+        );
+        assert!(invalidation_channel().starts_with(CACHE_VERSION));
+    }
+
+    #[test]
+    fn only_entities_are_kept_locally() {
+        let uuid = Uuid::nil();
+
+        assert!(CacheKey::User(uuid).is_local());
+        assert!(CacheKey::MemberRoles(uuid).is_local());
+        assert!(!CacheKey::InstanceSettings.is_local());
+        assert!(!CacheKey::RateLimit(String::new()).is_local());
+        assert!(!CacheKey::LoginChallenge(String::new()).is_local());
+    }
+}
@@ -3,6 +3,7 @@ use bunny_api_tokio::edge_storage::Endpoint;
 use lettre::transport::smtp::authentication::Credentials;
 use log::debug;
 use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf};
 use tokio::fs::read_to_string;
 use url::Url;
 use uuid::Uuid;
@@ -14,7 +15,14 @@ pub struct ConfigBuilder {
     web: WebBuilder,
     instance: Option<InstanceBuilder>,
     bunny: BunnyBuilder,
-    mail: Mail,
+    mail: MailBuilder,
+    rate_limit: Option<RateLimitBuilder>,
+    login_protection: Option<LoginProtectionBuilder>,
+    auth: Option<AuthBuilder>,
+    metrics: Option<MetricsBuilder>,
+    logging: Option<LoggingBuilder>,
+    #[serde(skip)]
+    path: PathBuf,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -41,6 +49,10 @@ struct WebBuilder {
     port: Option<u16>,
     frontend_url: Url,
     backend_url: Option<Url>,
+    real_ip_header: Option<String>,
+    trusted_proxies: Option<usize>,
+    shutdown_grace: Option<u64>,
+    shutdown_timeout: Option<u64>,
     _ssl: Option<bool>,
 }
 
@@ -50,6 +62,8 @@ struct InstanceBuilder {
     registration: Option<bool>,
     require_email_verification: Option<bool>,
     initial_guild: Option<Uuid>,
+    deletion_grace_period: Option<i64>,
+    max_pending_friend_requests: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -60,11 +74,77 @@ struct BunnyBuilder {
     cdn_url: Url,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct Mail {
-    pub smtp: Smtp,
-    pub address: String,
-    pub tls: String,
+#[derive(Debug, Deserialize)]
+struct RateLimitBuilder {
+    enabled: Option<bool>,
+    default: Option<Bucket>,
+    routes: Option<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LoginProtectionBuilder {
+    delay_after: Option<u32>,
+    max_delay: Option<i64>,
+    lockout_after: Option<u32>,
+    ip_lockout_after: Option<u32>,
+    lockout_duration: Option<i64>,
+    attempt_window: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct AuthBuilder {
+    token_pepper: Option<String>,
+    token_pepper_file: Option<String>,
+    access_token_lifetime: Option<i64>,
+    refresh_token_lifetime: Option<i64>,
+    refresh_token_reuse_grace: Option<i64>,
+    cookie_max_age: Option<i64>,
+    email_verification_ttl: Option<u32>,
+    password_reset_ttl: Option<u32>,
+    max_sessions: Option<i64>,
+    idle_session_expiry: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct MetricsBuilder {
+    enabled: Option<bool>,
+    ip: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LoggingBuilder {
+    format: Option<LogFormat>,
+    filter: Option<String>,
+    otlp_endpoint: Option<Url>,
+    service_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+/// Token bucket, holds up to `capacity` requests and refills `refill_rate` requests per second
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Bucket {
+    pub capacity: u32,
+    pub refill_rate: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MailBuilder {
+    transport: Option<String>,
+    smtp: Option<Smtp>,
+    address: String,
+    tls: Option<String>,
+    file_directory: Option<String>,
+    template_directory: Option<String>,
+    max_attempts: Option<i32>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -77,9 +157,11 @@ pub struct Smtp {
 impl ConfigBuilder {
     pub async fn load(path: String) -> Result<Self, Error> {
         debug!("loading config from: {path}");
-        let raw = read_to_string(path).await?;
+        let raw = read_to_string(&path).await?;
+
+        let mut config: ConfigBuilder = toml::from_str(&raw)?;
 
-        let config = toml::from_str(&raw)?;
+        config.path = PathBuf::from(path);
 
         Ok(config)
     }
@@ -94,6 +176,10 @@ impl ConfigBuilder {
                 .backend_url
                 .or_else(|| self.web.frontend_url.join("/api").ok())
                 .unwrap(),
+            real_ip_header: self.web.real_ip_header,
+            trusted_proxies: self.web.trusted_proxies.unwrap_or(1).max(1),
+            shutdown_grace: self.web.shutdown_grace.unwrap_or(5),
+            shutdown_timeout: self.web.shutdown_timeout.unwrap_or(30),
         };
 
         let endpoint = match &*self.bunny.endpoint {
@@ -122,22 +208,161 @@ impl ConfigBuilder {
                 registration: instance.registration.unwrap_or(true),
                 require_email_verification: instance.require_email_verification.unwrap_or(false),
                 initial_guild: instance.initial_guild,
+                deletion_grace_period: instance.deletion_grace_period.unwrap_or(2592000),
+                max_pending_friend_requests: instance.max_pending_friend_requests.unwrap_or(100),
             },
             None => Instance {
                 name: "Gorb".to_string(),
                 registration: true,
                 require_email_verification: false,
                 initial_guild: None,
+                deletion_grace_period: 2592000,
+                max_pending_friend_requests: 100,
             },
         };
 
+        let mut rate_limit_routes = HashMap::from([
+            (
+                "/v1/auth/login".to_string(),
+                Bucket {
+                    capacity: 5,
+                    refill_rate: 1.0 / 60.0,
+                },
+            ),
+            (
+                "/v1/auth/login/totp".to_string(),
+                Bucket {
+                    capacity: 5,
+                    refill_rate: 1.0 / 60.0,
+                },
+            ),
+            (
+                "/v1/auth/login/passkey".to_string(),
+                Bucket {
+                    capacity: 5,
+                    refill_rate: 1.0 / 60.0,
+                },
+            ),
+            (
+                "/v1/auth/register".to_string(),
+                Bucket {
+                    capacity: 3,
+                    refill_rate: 1.0 / 300.0,
+                },
+            ),
+            (
+                "/v1/auth/reset-password".to_string(),
+                Bucket {
+                    capacity: 3,
+                    refill_rate: 1.0 / 300.0,
+                },
+            ),
+            (
+                "message_send".to_string(),
+                Bucket {
+                    capacity: 10,
+                    refill_rate: 1.0,
+                },
+            ),
+        ]);
+
+        let rate_limit = match self.rate_limit {
+            Some(rate_limit) => {
+                rate_limit_routes.extend(rate_limit.routes.unwrap_or_default());
+
+                RateLimit {
+                    enabled: rate_limit.enabled.unwrap_or(true),
+                    default: rate_limit.default.unwrap_or(Bucket {
+                        capacity: 60,
+                        refill_rate: 1.0,
+                    }),
+                    routes: rate_limit_routes,
+                }
+            }
+            None => RateLimit {
+                enabled: true,
+                default: Bucket {
+                    capacity: 60,
+                    refill_rate: 1.0,
+                },
+                routes: rate_limit_routes,
+            },
+        };
+
+        let login_protection = self.login_protection.unwrap_or_default();
+
+        let login_protection = LoginProtection {
+            delay_after: login_protection.delay_after.unwrap_or(3),
+            max_delay: login_protection.max_delay.unwrap_or(60),
+            lockout_after: login_protection.lockout_after.unwrap_or(10),
+            ip_lockout_after: login_protection.ip_lockout_after.unwrap_or(50),
+            lockout_duration: login_protection.lockout_duration.unwrap_or(900),
+            attempt_window: login_protection.attempt_window.unwrap_or(3600),
+        };
+
+        let auth = self.auth.unwrap_or_default();
+
+        let refresh_token_lifetime = auth.refresh_token_lifetime.unwrap_or(2592000);
+
+        let auth = Auth {
+            token_pepper: auth.token_pepper,
+            token_pepper_file: auth
+                .token_pepper_file
+                .map(PathBuf::from)
+                .unwrap_or_else(|| self.path.with_file_name("token_pepper")),
+            access_token_lifetime: auth.access_token_lifetime.unwrap_or(3600),
+            refresh_token_lifetime,
+            refresh_token_reuse_grace: auth.refresh_token_reuse_grace.unwrap_or(10),
+            cookie_max_age: auth.cookie_max_age.unwrap_or(refresh_token_lifetime),
+            email_verification_ttl: auth.email_verification_ttl.unwrap_or(86400),
+            password_reset_ttl: auth.password_reset_ttl.unwrap_or(86400),
+            max_sessions: auth.max_sessions,
+            idle_session_expiry: auth.idle_session_expiry,
+        };
+
+        let mail = Mail {
+            transport: self.mail.transport.unwrap_or("smtp".to_string()),
+            smtp: self.mail.smtp,
+            address: self.mail.address,
+            tls: self.mail.tls.unwrap_or("tls".to_string()),
+            file_directory: self
+                .mail
+                .file_directory
+                .map(PathBuf::from)
+                .unwrap_or_else(|| self.path.with_file_name("mail")),
+            template_directory: self.mail.template_directory.map(PathBuf::from),
+            max_attempts: self.mail.max_attempts.unwrap_or(8),
+        };
+
+        let metrics = self.metrics.unwrap_or_default();
+
+        let metrics = Metrics {
+            enabled: metrics.enabled.unwrap_or(false),
+            ip: metrics.ip.unwrap_or(String::from("127.0.0.1")),
+            port: metrics.port,
+        };
+
+        let logging = self.logging.unwrap_or_default();
+
+        let logging = Logging {
+            format: logging.format.unwrap_or(LogFormat::Text),
+            filter: logging.filter.unwrap_or(String::from("info")),
+            otlp_endpoint: logging.otlp_endpoint,
+            service_name: logging.service_name.unwrap_or(String::from("gorb")),
+        };
+
         Config {
             database: self.database,
             cache_database: self.cache_database,
             web,
             instance,
             bunny,
-            mail: self.mail,
+            mail,
+            rate_limit,
+            login_protection,
+            auth,
+            metrics,
+            logging,
         }
     }
 }
@@ -150,6 +375,11 @@ pub struct Config {
     pub instance: Instance,
     pub bunny: Bunny,
     pub mail: Mail,
+    pub rate_limit: RateLimit,
+    pub login_protection: LoginProtection,
+    pub auth: Auth,
+    pub metrics: Metrics,
+    pub logging: Logging,
 }
 
 #[derive(Debug, Clone)]
@@ -158,6 +388,13 @@ pub struct Web {
     pub port: u16,
     pub frontend_url: Url,
     pub backend_url: Url,
+    pub real_ip_header: Option<String>,
+    /// Proxies in front of the server that append to `real_ip_header`, the client address is this many entries from the right
+    pub trusted_proxies: usize,
+    /// Seconds between failing readiness checks and refusing connections, gives load balancers time to stop routing here
+    pub shutdown_grace: u64,
+    /// Seconds to wait for requests, sockets and jobs to finish after a shutdown signal before exiting anyway
+    pub shutdown_timeout: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -166,6 +403,10 @@ pub struct Instance {
     pub registration: bool,
     pub require_email_verification: bool,
     pub initial_guild: Option<Uuid>,
+    /// Seconds between an account deletion request and the account being deleted
+    pub deletion_grace_period: i64,
+    /// Outgoing friend requests a user can have waiting for an answer at once
+    pub max_pending_friend_requests: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -176,6 +417,85 @@ pub struct Bunny {
     pub cdn_url: Url,
 }
 
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub enabled: bool,
+    pub default: Bucket,
+    pub routes: HashMap<String, Bucket>,
+}
+
+/// Limits on failed login and password reset attempts, durations are in seconds
+#[derive(Debug, Clone)]
+pub struct LoginProtection {
+    /// Failed attempts before each new attempt has to wait an exponentially increasing delay
+    pub delay_after: u32,
+    pub max_delay: i64,
+    /// Failed attempts before the account is locked
+    pub lockout_after: u32,
+    /// Failed attempts before the IP address is locked
+    pub ip_lockout_after: u32,
+    pub lockout_duration: i64,
+    /// How long failed attempts are remembered after the last failure
+    pub attempt_window: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    /// `smtp` to deliver mail, `file` to write it to `file_directory` or `log` to print it, the latter two need no mail server
+    pub transport: String,
+    pub smtp: Option<Smtp>,
+    pub address: String,
+    pub tls: String,
+    /// Defaults to `mail` next to the config file
+    pub file_directory: PathBuf,
+    /// Overrides the built in templates, laid out as `layout.html` and `{locale}/{template}.txt|html`
+    pub template_directory: Option<PathBuf>,
+    /// Delivery attempts before a queued email is given up on
+    pub max_attempts: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Auth {
+    /// Secret mixed into token hashes, read from `token_pepper_file` when not set
+    pub token_pepper: Option<String>,
+    /// Generated on first start if it doesn't exist, defaults to `token_pepper` next to the config file.
+    /// All replicas have to use the same pepper, so the file has to be shared between them
+    pub token_pepper_file: PathBuf,
+    pub access_token_lifetime: i64,
+    /// Seconds a refresh token stays valid without being used, every refresh issues a new one
+    pub refresh_token_lifetime: i64,
+    /// Seconds a rotated refresh token is treated as a concurrent refresh instead of reuse
+    pub refresh_token_reuse_grace: i64,
+    pub cookie_max_age: i64,
+    pub email_verification_ttl: u32,
+    pub password_reset_ttl: u32,
+    /// Logging in beyond this many sessions logs out the least recently used one, `None` for no limit
+    pub max_sessions: Option<i64>,
+    /// Seconds of inactivity after which sessions are logged out, `None` to keep them until they expire
+    pub idle_session_expiry: Option<i64>,
+}
+
+/// Prometheus metrics served at `/metrics` on their own listener
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub enabled: bool,
+    /// Address the metrics listener binds to
+    pub ip: String,
+    /// Required when metrics are enabled, metrics are never served on the API port
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Logging {
+    pub format: LogFormat,
+    /// Same syntax as `RUST_LOG`, which takes precedence when set
+    pub filter: String,
+    /// Exports request spans to an OpenTelemetry collector over OTLP/HTTP, e.g. `http://localhost:4318/v1/traces`
+    pub otlp_endpoint: Option<Url>,
+    /// `service.name` of the exported spans
+    pub service_name: String,
+}
+
 impl Database {
     pub fn url(&self) -> String {
         let mut url = String::from("postgres://");
@@ -228,8 +548,82 @@ impl CacheDatabase {
     }
 }
 
+impl RateLimit {
+    pub fn bucket(&self, route: &str) -> Bucket {
+        self.routes.get(route).copied().unwrap_or(self.default)
+    }
+}
+
 impl Smtp {
     pub fn credentials(&self) -> Credentials {
         Credentials::new(self.username.clone(), self.password.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = r#"
+        [database]
+        username = "gorb"
+        password = "gorb"
+        host = "localhost"
+        database = "gorb"
+        port = 5432
+
+        [cache_database]
+        host = "localhost"
+        port = 6379
+
+        [web]
+        frontend_url = "https://gorb.app"
+
+        [bunny]
+        api_key = "key"
+        endpoint = "Frankfurt"
+        storage_zone = "gorb"
+        cdn_url = "https://cdn.gorb.app"
+
+        [mail]
+        address = "noreply@gorb.app"
+    "#;
+
+    fn build(extra: &str) -> Config {
+        toml::from_str::<ConfigBuilder>(&format!("{MINIMAL}\n{extra}"))
+            .unwrap()
+            .build()
+    }
+
+    #[test]
+    fn rate_limit_defaults() {
+        let rate_limit = build("").rate_limit;
+
+        assert!(rate_limit.enabled);
+        assert_eq!(rate_limit.bucket("/v1/users").capacity, 60);
+        assert_eq!(rate_limit.bucket("/v1/users").refill_rate, 1.0);
+        assert_eq!(rate_limit.bucket("/v1/auth/login").capacity, 5);
+        assert_eq!(rate_limit.bucket("message_send").capacity, 10);
+    }
+
+    #[test]
+    fn configured_routes_override_defaults() {
+        let rate_limit = build(
+            r#"
+            [rate_limit.default]
+            capacity = 100
+            refill_rate = 2.0
+
+            [rate_limit.routes."/v1/auth/login"]
+            capacity = 1
+            refill_rate = 0.5
+            "#,
+        )
+        .rate_limit;
+
+        assert!(rate_limit.enabled);
+        assert_eq!(rate_limit.bucket("/v1/users").capacity, 100);
+        assert_eq!(rate_limit.bucket("/v1/auth/login").capacity, 1);
+        assert_eq!(rate_limit.bucket("/v1/auth/register").capacity, 3);
+    }
+}
@@ -17,15 +17,23 @@ use deadpool::managed::{BuildError, PoolError};
 use diesel::{ConnectionError, result::Error as DieselError};
 use diesel_async::pooled_connection::PoolError as DieselPoolError;
 use lettre::{
-    address::AddressError, error::Error as EmailError, transport::smtp::Error as SmtpError,
+    address::AddressError, error::Error as EmailError, transport::file::Error as MailFileError,
+    transport::smtp::Error as SmtpError,
 };
 use log::{debug, error};
+use metrics_exporter_prometheus::BuildError as MetricsBuildError;
+use opentelemetry_otlp::ExporterBuildError;
 use redis::RedisError;
 use serde::Serialize;
 use serde_json::Error as JsonError;
 use thiserror::Error;
 use tokio::task::JoinError;
 use toml::de::Error as TomlError;
+use tracing_subscriber::{filter::ParseError as LogFilterError, util::TryInitError};
+use webauthn_rs::prelude::WebauthnError;
+use zip::result::ZipError;
+
+use crate::{logging, telemetry};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -70,10 +78,26 @@ pub enum Error {
     #[error(transparent)]
     SmtpError(#[from] SmtpError),
     #[error(transparent)]
+    MailFileError(#[from] MailFileError),
+    #[error(transparent)]
     SmtpAddressError(#[from] AddressError),
+    #[error(transparent)]
+    WebauthnError(#[from] WebauthnError),
+    #[error(transparent)]
+    ZipError(#[from] ZipError),
+    #[error(transparent)]
+    MetricsBuildError(#[from] MetricsBuildError),
+    #[error(transparent)]
+    LogFilterError(#[from] LogFilterError),
+    #[error(transparent)]
+    TryInitError(#[from] TryInitError),
+    #[error(transparent)]
+    ExporterBuildError(#[from] ExporterBuildError),
     #[error("{0}")]
     PasswordHashError(String),
     #[error("{0}")]
+    TotpError(String),
+    #[error("{0}")]
     BadRequest(String),
     #[error("{0}")]
     Unauthorized(String),
@@ -118,6 +142,8 @@ impl IntoResponse for Error {
 
         let (code, _) = error;
 
+        telemetry::record_error(code);
+
         debug!("{self:?}");
         error!("{code}: {self}");
 
@@ -128,10 +154,16 @@ impl IntoResponse for Error {
 #[derive(Serialize)]
 struct WebError {
     message: String,
+    /// Lets a user reporting an error point us at the matching logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 impl WebError {
     fn new(message: String) -> Self {
-        Self { message }
+        Self {
+            message,
+            request_id: logging::request_id(),
+        }
     }
 }
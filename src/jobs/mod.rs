@@ -0,0 +1,254 @@
+//! Periodic maintenance jobs, each run is claimed through the cache database so only one replica runs it
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::future::BoxFuture;
+use log::{error, info};
+use redis::Script;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    cache::{Cache, CacheKey},
+    error::Error,
+    objects::{AccountDeletion, QueuedEmail, Session},
+    utils::generate_token,
+};
+
+mod uploads;
+
+/// Seconds job statuses are kept for
+const STATUS_LIFETIME: u32 = 604800;
+
+/// Seconds the running lock lasts without being renewed, a replica that stops mid-run only blocks the job this long
+const RUNNING_LEASE: u64 = 30;
+
+/// Extends the running lock if this replica still holds it
+static RENEW_RUNNING: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+
+return 0
+"#,
+    )
+});
+
+/// Deletes the running lock if this replica still holds it
+static RELEASE_RUNNING: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+
+return 0
+"#,
+    )
+});
+
+pub struct Job {
+    pub name: &'static str,
+    /// Seconds between runs
+    pub interval: u64,
+    /// Returns a short summary of what the run did
+    run: fn(&'static AppState) -> BoxFuture<'static, Result<String, Error>>,
+}
+
+pub static JOBS: &[Job] = &[
+    Job {
+        name: "deliver_emails",
+        interval: 15,
+        run: |app_state| {
+            Box::pin(async move {
+                let (due, delivered) = QueuedEmail::deliver_due(app_state).await?;
+
+                Ok(format!("delivered {delivered} of {due} queued emails"))
+            })
+        },
+    },
+    Job {
+        name: "prune_sessions",
+        interval: 600,
+        run: |app_state| {
+            Box::pin(async move {
+                let pruned = Session::prune(app_state).await?;
+
+                Ok(format!("logged out {pruned} expired or idle sessions"))
+            })
+        },
+    },
+    Job {
+        name: "finalise_account_deletions",
+        interval: 3600,
+        run: |app_state| {
+            Box::pin(async move {
+                let deleted = AccountDeletion::finalise_due(app_state).await?;
+
+                Ok(format!("deleted {deleted} accounts"))
+            })
+        },
+    },
+    Job {
+        name: "cleanup_uploads",
+        interval: 86400,
+        run: |app_state| {
+            Box::pin(async move {
+                let deleted = uploads::cleanup(app_state).await?;
+
+                Ok(format!("deleted {deleted} orphaned uploads"))
+            })
+        },
+    },
+];
+
+/// Result of the last run of a job, shared between replicas through the cache database
+#[derive(Serialize, Deserialize)]
+pub struct JobStatus {
+    pub started_at: i64,
+    pub duration_ms: u128,
+    pub success: bool,
+    pub summary: String,
+    /// Id of the replica that ran the job
+    pub runner: String,
+}
+
+impl Job {
+    pub async fn status(&self, cache_pool: &Cache) -> Option<JobStatus> {
+        cache_pool
+            .get(&CacheKey::JobStatus(self.name))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Claims a run for this replica, returns false while another replica runs the job or ran it less than an interval ago
+    ///
+    /// The running lock is held until the run finishes, the interval lock only spaces runs out
+    async fn claim(&self, cache_pool: &Cache, runner: &str) -> Result<bool, Error> {
+        if !cache_pool
+            .set_nx(
+                &CacheKey::JobRunning(self.name),
+                runner,
+                RUNNING_LEASE as u32,
+            )
+            .await?
+        {
+            return Ok(false);
+        }
+
+        if !cache_pool
+            .set_nx(&CacheKey::JobLock(self.name), runner, self.interval as u32)
+            .await?
+        {
+            self.release(cache_pool, runner).await?;
+
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    async fn renew(&self, cache_pool: &Cache, runner: &str) -> Result<(), Error> {
+        let _: i64 = RENEW_RUNNING
+            .key(CacheKey::JobRunning(self.name).to_string())
+            .arg(serde_json::to_string(runner)?)
+            .arg(RUNNING_LEASE)
+            .invoke_async(&mut cache_pool.connection())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn release(&self, cache_pool: &Cache, runner: &str) -> Result<(), Error> {
+        let _: i64 = RELEASE_RUNNING
+            .key(CacheKey::JobRunning(self.name).to_string())
+            .arg(serde_json::to_string(runner)?)
+            .invoke_async(&mut cache_pool.connection())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn execute(&self, app_state: &'static AppState, runner: &str) -> Result<(), Error> {
+        if !self.claim(&app_state.cache_pool, runner).await? {
+            return Ok(());
+        }
+
+        let started_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let start = Instant::now();
+
+        let run = (self.run)(app_state);
+        tokio::pin!(run);
+
+        let mut renewal = tokio::time::interval(Duration::from_secs(RUNNING_LEASE / 3));
+        renewal.tick().await;
+
+        // Runs can take longer than the interval, the lock is renewed until this one is done
+        let result = loop {
+            tokio::select! {
+                result = &mut run => break result,
+                _ = renewal.tick() => {
+                    if let Err(error) = self.renew(&app_state.cache_pool, runner).await {
+                        error!("failed to renew lock of job {}: {error}", self.name);
+                    }
+                }
+            }
+        };
+
+        let status = JobStatus {
+            started_at,
+            duration_ms: start.elapsed().as_millis(),
+            success: result.is_ok(),
+            summary: match result {
+                Ok(summary) => {
+                    info!("job {}: {summary}", self.name);
+                    summary
+                }
+                Err(error) => {
+                    error!("job {} failed: {error}", self.name);
+                    error.to_string()
+                }
+            },
+            runner: runner.to_string(),
+        };
+
+        app_state
+            .cache_pool
+            .set(&CacheKey::JobStatus(self.name), &status, STATUS_LIFETIME)
+            .await?;
+
+        self.release(&app_state.cache_pool, runner).await
+    }
+}
+
+/// Starts a task per job that tries to run it every interval, a run in progress is finished before shutting down
+pub fn start(app_state: &'static AppState) -> Result<(), Error> {
+    let runner = generate_token::<8>()?;
+
+    for job in JOBS {
+        let runner = runner.clone();
+
+        app_state.tasks.spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(job.interval));
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = app_state.shutdown.cancelled() => break,
+                }
+
+                if let Err(error) = job.execute(app_state, &runner).await {
+                    error!("failed to run job {}: {error}", job.name);
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Removes uploads that nothing references anymore, such as replaced avatars and expired data exports
+
+use bunny_api_tokio::error::Error as BunnyError;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use log::error;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::Error,
+    objects::DataExport,
+    schema::{guilds, users},
+};
+
+/// Files younger than this may belong to an upload that hasn't been saved to the database yet
+const MINIMUM_AGE: TimeDelta = TimeDelta::hours(1);
+
+/// Deletes orphaned avatars, guild icons and data exports, returns how many files were deleted
+pub async fn cleanup(app_state: &AppState) -> Result<usize, Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    let mut deleted = 0;
+
+    for (owner_uuid, path) in list_old_files(app_state, "avatar").await? {
+        use users::dsl;
+        let avatar: Option<Option<String>> = dsl::users
+            .filter(dsl::uuid.eq(owner_uuid))
+            .select(dsl::avatar)
+            .get_result(&mut conn)
+            .await
+            .optional()?;
+
+        if !is_current(app_state, avatar.flatten(), &path)? {
+            deleted += delete_file(app_state, &path).await;
+        }
+    }
+
+    for (owner_uuid, path) in list_old_files(app_state, "icons").await? {
+        use guilds::dsl;
+        let icon: Option<Option<String>> = dsl::guilds
+            .filter(dsl::uuid.eq(owner_uuid))
+            .select(dsl::icon)
+            .get_result(&mut conn)
+            .await
+            .optional()?;
+
+        if !is_current(app_state, icon.flatten(), &path)? {
+            deleted += delete_file(app_state, &path).await;
+        }
+    }
+
+    for (_, path) in list_old_files(app_state, "export").await? {
+        let token = path
+            .rsplit('/')
+            .next()
+            .and_then(|file_name| file_name.strip_suffix(".zip"))
+            .unwrap_or_default();
+
+        if DataExport::get(&app_state.cache_pool, token).await.is_err() {
+            deleted += delete_file(app_state, &path).await;
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Lists files in `{directory}/{uuid}/` older than `MINIMUM_AGE` as the uuid and the path of the file
+async fn list_old_files(
+    app_state: &AppState,
+    directory: &str,
+) -> Result<Vec<(Uuid, String)>, Error> {
+    let mut files = vec![];
+
+    let owners = match app_state.bunny_storage.list(format!("{directory}/")).await {
+        Ok(owners) => owners,
+        // Nothing has been uploaded to the directory yet
+        Err(BunnyError::NotFound(_)) => return Ok(files),
+        Err(error) => return Err(error.into()),
+    };
+
+    for owner in owners {
+        let Ok(owner_uuid) = owner.object_name.parse::<Uuid>() else {
+            continue;
+        };
+
+        if !owner.is_directory {
+            continue;
+        }
+
+        for file in app_state
+            .bunny_storage
+            .list(format!("{directory}/{owner_uuid}/"))
+            .await?
+        {
+            let old_enough =
+                NaiveDateTime::parse_from_str(&file.date_created, "%Y-%m-%dT%H:%M:%S%.f")
+                    .is_ok_and(|created| Utc::now().naive_utc() - created > MINIMUM_AGE);
+
+            if !file.is_directory && old_enough {
+                files.push((
+                    owner_uuid,
+                    format!("{directory}/{owner_uuid}/{}", file.object_name),
+                ));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_current(app_state: &AppState, url: Option<String>, path: &str) -> Result<bool, Error> {
+    let Some(url) = url else {
+        return Ok(false);
+    };
+
+    Ok(url == app_state.config.bunny.cdn_url.join(path)?.as_str())
+}
+
+async fn delete_file(app_state: &AppState, path: &str) -> usize {
+    match app_state.bunny_storage.delete(path).await {
+        Ok(()) => 1,
+        Err(error) => {
+            error!("failed to delete {path}: {error}");
+            0
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Structured logging with a tracing span per request, optionally exported to an OpenTelemetry collector.
+//!
+//! Records from the `log` macros are forwarded to the subscriber, so they pick up the fields of the request span they're logged in.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tracing::{Instrument, field::Empty, info, info_span};
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, util::SubscriberInitExt};
+use uuid::Uuid;
+
+use crate::{
+    config::{LogFormat, Logging},
+    error::Error,
+};
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Installs the global subscriber, the returned provider has to be shut down before exiting to flush pending spans
+pub fn init(config: &Logging) -> Result<Option<SdkTracerProvider>, Error> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.filter)?,
+    };
+
+    let output = match config.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint.as_str())
+                .build()?;
+
+            let resource = Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build();
+
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(resource)
+                    .build(),
+            )
+        }
+        None => None,
+    };
+
+    let otlp = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(otlp)
+        .try_init()?;
+
+    Ok(provider)
+}
+
+/// Id of the request currently being handled, `None` outside of a request
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Runs the request in a span with its method, route and id, the user uuid is recorded once the access token is checked
+///
+/// The id is taken from the `x-request-id` header when a proxy already set one, and is echoed back in the response
+pub async fn trace_requests(
+    matched_path: Option<MatchedPath>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+
+    let request_id = req
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 64)
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| Uuid::now_v7().to_string());
+
+    let route = matched_path
+        .map(|path| path.as_str().to_string())
+        .unwrap_or("unmatched".to_string());
+
+    let span = info_span!(
+        "request",
+        method = %req.method(),
+        route,
+        request_id,
+        user_uuid = Empty,
+    );
+
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(req))
+        .instrument(span.clone())
+        .await;
+
+    span.in_scope(|| {
+        info!(
+            status = response.status().as_u16(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "finished request"
+        )
+    });
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(X_REQUEST_ID, value);
+    }
+
+    response
+}
@@ -2,16 +2,24 @@ use argon2::Argon2;
 use axum::{
     Router,
     http::{Method, header},
+    routing::get,
 };
+use cache::Cache;
 use clap::Parser;
 use config::{Config, ConfigBuilder};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
 use diesel_async::pooled_connection::deadpool::Pool;
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use error::Error;
+use metrics_exporter_prometheus::PrometheusHandle;
 use objects::MailClient;
-use std::time::SystemTime;
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tower_http::cors::{AllowOrigin, CorsLayer};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
@@ -19,11 +27,15 @@ type Conn =
     deadpool::managed::Object<AsyncDieselConnectionManager<diesel_async::AsyncPgConnection>>;
 
 mod api;
+pub mod cache;
 mod config;
 pub mod error;
+mod jobs;
+mod logging;
 pub mod objects;
 pub mod schema;
 //mod socket;
+mod telemetry;
 pub mod utils;
 mod wordlist;
 
@@ -40,22 +52,46 @@ pub struct AppState {
         AsyncDieselConnectionManager<diesel_async::AsyncPgConnection>,
         Conn,
     >,
-    pub cache_pool: redis::Client,
+    pub cache_pool: Cache,
     pub config: Config,
     pub argon2: Argon2<'static>,
     pub start_time: SystemTime,
     pub bunny_storage: bunny_api_tokio::EdgeStorageClient,
     pub mail_client: MailClient,
+    pub webauthn: Webauthn,
+    pub token_pepper: Vec<u8>,
+    /// Renders the recorded metrics, `None` when metrics are disabled
+    pub metrics: Option<PrometheusHandle>,
+    /// Cancelled as soon as a shutdown signal arrives, readiness checks fail from then on
+    pub draining: CancellationToken,
+    /// Cancelled `web.shutdown_grace` seconds after `draining`, new connections are refused and sockets are closed
+    pub shutdown: CancellationToken,
+    /// Background work that has to finish before shutting down, such as sockets, jobs and email deliveries
+    pub tasks: TaskTracker,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt::init();
-
     let args = Args::parse();
 
     let config = ConfigBuilder::load(args.config).await?.build();
 
+    let tracer_provider = logging::init(&config.logging)?;
+
+    let token_pepper = utils::load_token_pepper(&config.auth).await?;
+
+    // Metrics reveal routes, error rates and queue sizes, they are never served on the public listener
+    if config.metrics.enabled && config.metrics.port.is_none() {
+        return Err(Error::InternalServerError(
+            "metrics.port is required when metrics are enabled".to_string(),
+        ));
+    }
+
+    let metrics = match config.metrics.enabled {
+        true => Some(telemetry::install()?),
+        false => None,
+    };
+
     let web = config.web.clone();
 
     // create a new connection pool with the default config
@@ -63,7 +99,7 @@ async fn main() -> Result<(), Error> {
         AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(config.database.url());
     let pool = Pool::builder(pool_config).build()?;
 
-    let cache_pool = redis::Client::open(config.cache_database.url())?;
+    let cache_pool = Cache::new(config.cache_database.url()).await?;
 
     let bunny = config.bunny.clone();
 
@@ -71,14 +107,21 @@ async fn main() -> Result<(), Error> {
         bunny_api_tokio::EdgeStorageClient::new(bunny.api_key, bunny.endpoint, bunny.storage_zone)
             .await?;
 
-    let mail = config.mail.clone();
+    let mail_client = MailClient::new(&config.mail)?;
+
+    // Passkeys are bound to the domain of the frontend, changing it invalidates all registered passkeys
+    let rp_id = web
+        .frontend_url
+        .host_str()
+        .ok_or(Error::InternalServerError(
+            "frontend_url has no host".to_string(),
+        ))?;
+
+    let rp_origin = url::Url::parse(&web.frontend_url.origin().ascii_serialization())?;
 
-    let mail_client = MailClient::new(
-        mail.smtp.credentials(),
-        mail.smtp.server,
-        mail.address,
-        mail.tls,
-    )?;
+    let webauthn = WebauthnBuilder::new(rp_id, &rp_origin)?
+        .rp_name(&config.instance.name)
+        .build()?;
 
     let database_url = config.database.url();
 
@@ -112,17 +155,24 @@ async fn main() -> Result<(), Error> {
         )
     */
 
-    let app_state = Box::leak(Box::new(AppState {
+    let app_state: &'static AppState = Box::leak(Box::new(AppState {
         pool,
         cache_pool,
         config,
-        // TODO: Possibly implement "pepper" into this (thinking it could generate one if it doesnt exist and store it on disk)
         argon2: Argon2::default(),
         start_time: SystemTime::now(),
         bunny_storage,
         mail_client,
+        webauthn,
+        token_pepper,
+        metrics,
+        draining: CancellationToken::new(),
+        shutdown: CancellationToken::new(),
+        tasks: TaskTracker::new(),
     }));
 
+    jobs::start(app_state)?;
+
     let cors = CorsLayer::new()
         // Allow any origin (equivalent to allowed_origin_fn returning true)
         .allow_origin(AllowOrigin::predicate(|_origin, _request_head| true))
@@ -148,6 +198,13 @@ async fn main() -> Result<(), Error> {
             header::COOKIE,
             "x-requested-with".parse().unwrap(),
         ])
+        .expose_headers(vec![
+            header::RETRY_AFTER,
+            "x-ratelimit-limit".parse().unwrap(),
+            "x-ratelimit-remaining".parse().unwrap(),
+            "x-ratelimit-reset".parse().unwrap(),
+            logging::X_REQUEST_ID,
+        ])
         // Allow credentials
         .allow_credentials(true);
 
@@ -163,14 +220,96 @@ async fn main() -> Result<(), Error> {
         .merge(api::router(
             web.backend_url.path().trim_end_matches("/"),
             app_state,
-        ))
+        ));
+
+    let metrics_config = app_state.config.metrics.clone();
+
+    if metrics_config.enabled
+        && let Some(port) = metrics_config.port
+    {
+        let metrics_app = Router::new()
+            .route("/metrics", get(telemetry::metrics))
+            .with_state(app_state);
+
+        let listener =
+            tokio::net::TcpListener::bind(metrics_config.ip + ":" + &port.to_string()).await?;
+
+        tokio::spawn(async move {
+            if let Err(error) = axum::serve(listener, metrics_app)
+                .with_graceful_shutdown(app_state.shutdown.cancelled())
+                .await
+            {
+                log::error!("metrics listener stopped: {error}");
+            }
+        });
+    }
+
+    let app = app
         .with_state(app_state)
         //.layer(socket_io)
         .layer(cors);
 
+    tokio::spawn(async move {
+        utils::shutdown_signal().await;
+
+        log::info!(
+            "shutting down, refusing connections in {}s",
+            app_state.config.web.shutdown_grace
+        );
+
+        app_state.draining.cancel();
+
+        // Requests keep being served until load balancers noticed the failing readiness check
+        tokio::time::sleep(Duration::from_secs(app_state.config.web.shutdown_grace)).await;
+
+        log::info!("draining connections");
+
+        app_state.shutdown.cancel();
+    });
+
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind(web.ip + ":" + &web.port.to_string()).await?;
-    axum::serve(listener, app).await?;
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(app_state.shutdown.cancelled())
+        .into_future(),
+    );
+
+    tokio::select! {
+        result = &mut server => result??,
+        _ = app_state.shutdown.cancelled() => {}
+    }
+
+    // New connections are refused from here on, sockets are told to reconnect to another replica
+    app_state.tasks.close();
+
+    let drained = tokio::time::timeout(Duration::from_secs(web.shutdown_timeout), async {
+        if let Ok(Err(error)) = server.await {
+            log::error!("server stopped while draining: {error}");
+        }
+
+        app_state.tasks.wait().await;
+    })
+    .await;
+
+    if drained.is_err() {
+        log::warn!(
+            "shutdown timed out after {}s, dropping remaining connections",
+            web.shutdown_timeout
+        );
+    }
+
+    app_state.pool.close();
+    app_state.cache_pool.close();
+
+    if let Some(tracer_provider) = tracer_provider
+        && let Err(error) = tracer_provider.shutdown()
+    {
+        log::error!("failed to flush traces: {error}");
+    }
 
     Ok(())
 }
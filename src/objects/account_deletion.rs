@@ -0,0 +1,315 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, delete, update,
+};
+use diesel_async::{
+    AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt,
+};
+use log::{error, info};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    AppState, Conn,
+    cache::CacheKey,
+    error::Error,
+    schema::{
+        friend_requests, friends, guild_members, instance_permissions, passkeys, recovery_codes,
+        refresh_tokens, user_blocks, users,
+    },
+    utils::disconnect_sockets,
+};
+
+use super::{DataExport, Guild, load_or_empty};
+
+/// Accounts are deleted after a grace period, logging in during it cancels the deletion
+pub struct AccountDeletion;
+
+impl AccountDeletion {
+    /// Schedules the account for deletion and logs it out everywhere, returns when it will be deleted
+    pub async fn schedule(
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<i64, Error> {
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .set(dsl::deleted_at.eq(current_time))
+            .execute(conn)
+            .await?;
+
+        delete(refresh_tokens::table)
+            .filter(refresh_tokens::uuid.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        disconnect_sockets(&app_state.cache_pool, user_uuid).await?;
+
+        Ok(current_time + app_state.config.instance.deletion_grace_period)
+    }
+
+    /// Cancels a pending deletion, returns true if there was one
+    pub async fn cancel(conn: &mut Conn, user_uuid: Uuid) -> Result<bool, Error> {
+        use users::dsl;
+        let cancelled = update(users::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .filter(dsl::is_deleted.eq(false))
+            .filter(dsl::deleted_at.is_not_null())
+            .set(dsl::deleted_at.eq(None::<i64>))
+            .execute(conn)
+            .await?;
+
+        Ok(cancelled > 0)
+    }
+
+    /// Deletes every account whose grace period has ended, returns how many were deleted
+    pub async fn finalise_due(app_state: &AppState) -> Result<usize, Error> {
+        let mut conn = app_state.pool.get().await?;
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        use users::dsl;
+        let due: Vec<Uuid> = load_or_empty(
+            dsl::users
+                .filter(dsl::is_deleted.eq(false))
+                .filter(
+                    dsl::deleted_at
+                        .le(current_time - app_state.config.instance.deletion_grace_period),
+                )
+                .select(dsl::uuid)
+                .load(&mut conn)
+                .await,
+        )?;
+
+        let mut deleted = 0;
+
+        for user_uuid in due {
+            if let Err(error) = Self::finalise(&mut conn, app_state, user_uuid).await {
+                error!("failed to delete account {user_uuid}: {error}");
+            } else {
+                info!("deleted account {user_uuid}");
+                deleted += 1;
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Anonymises the account, the user row is kept so messages stay attributed to a deleted user
+    ///
+    /// The database changes are made in one transaction with `is_deleted` set last, if any of them fails the
+    /// account is picked up again on the next run instead of being left half deleted
+    pub async fn finalise(
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        // Each guild deletion is complete on its own, a retry won't find these guilds again
+        Self::delete_abandoned_guilds(conn, app_state, user_uuid).await?;
+
+        let avatar = conn
+            .transaction::<_, Error, _>(|conn| {
+                async move {
+                    Self::transfer_memberships(conn, user_uuid).await?;
+
+                    use users::dsl;
+                    let avatar: Option<String> = dsl::users
+                        .filter(dsl::uuid.eq(user_uuid))
+                        .select(dsl::avatar)
+                        .get_result(conn)
+                        .await?;
+
+                    delete(refresh_tokens::table)
+                        .filter(refresh_tokens::uuid.eq(user_uuid))
+                        .execute(conn)
+                        .await?;
+
+                    delete(passkeys::table)
+                        .filter(passkeys::user_uuid.eq(user_uuid))
+                        .execute(conn)
+                        .await?;
+
+                    delete(recovery_codes::table)
+                        .filter(recovery_codes::user_uuid.eq(user_uuid))
+                        .execute(conn)
+                        .await?;
+
+                    delete(instance_permissions::table)
+                        .filter(instance_permissions::uuid.eq(user_uuid))
+                        .execute(conn)
+                        .await?;
+
+                    delete(friends::table)
+                        .filter(
+                            friends::uuid1
+                                .eq(user_uuid)
+                                .or(friends::uuid2.eq(user_uuid)),
+                        )
+                        .execute(conn)
+                        .await?;
+
+                    delete(friend_requests::table)
+                        .filter(
+                            friend_requests::sender
+                                .eq(user_uuid)
+                                .or(friend_requests::receiver.eq(user_uuid)),
+                        )
+                        .execute(conn)
+                        .await?;
+
+                    delete(user_blocks::table)
+                        .filter(
+                            user_blocks::blocker
+                                .eq(user_uuid)
+                                .or(user_blocks::blocked.eq(user_uuid)),
+                        )
+                        .execute(conn)
+                        .await?;
+
+                    update(users::table)
+                        .filter(dsl::uuid.eq(user_uuid))
+                        .set((
+                            dsl::username.eq(user_uuid.simple().to_string()),
+                            dsl::display_name.eq(None::<String>),
+                            dsl::email.eq(format!("{user_uuid}@deleted.invalid")),
+                            dsl::email_verified.eq(false),
+                            dsl::password.eq(""),
+                            dsl::avatar.eq(None::<String>),
+                            dsl::pronouns.eq(None::<String>),
+                            dsl::about.eq(None::<String>),
+                            dsl::online_status.eq(0),
+                            dsl::totp_secret.eq(None::<String>),
+                            dsl::is_deleted.eq(true),
+                        ))
+                        .execute(conn)
+                        .await?;
+
+                    Ok(avatar)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        // The account is deleted at this point, failures are only logged since it won't be picked up again
+        if let Err(error) = app_state.cache_pool.del(&[CacheKey::User(user_uuid)]).await {
+            error!("failed to clear cached user of deleted account {user_uuid}: {error}");
+        }
+
+        if let Some(avatar) = avatar {
+            let deleted = async {
+                let avatar_url: Url = avatar.parse()?;
+
+                let relative_url = avatar_url.path().trim_start_matches('/');
+
+                app_state.bunny_storage.delete(relative_url).await?;
+
+                Ok::<(), Error>(())
+            }
+            .await;
+
+            if let Err(error) = deleted {
+                error!("failed to delete avatar of deleted account {user_uuid}: {error}");
+            }
+        }
+
+        let deleted = async {
+            if let Some(data_export) = app_state
+                .cache_pool
+                .get::<DataExport>(&CacheKey::UserDataExport(user_uuid))
+                .await?
+            {
+                data_export.delete(app_state).await?;
+            }
+
+            Ok::<(), Error>(())
+        }
+        .await;
+
+        if let Err(error) = deleted {
+            error!("failed to delete data export of deleted account {user_uuid}: {error}");
+        }
+
+        Ok(())
+    }
+
+    /// Deletes owned guilds that have no other members
+    async fn delete_abandoned_guilds(
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        use guild_members::dsl;
+        let owned_guilds: Vec<Uuid> = load_or_empty(
+            dsl::guild_members
+                .filter(dsl::user_uuid.eq(user_uuid))
+                .filter(dsl::is_owner.eq(true))
+                .select(dsl::guild_uuid)
+                .load(conn)
+                .await,
+        )?;
+
+        for guild_uuid in owned_guilds {
+            let other_members: i64 = dsl::guild_members
+                .filter(dsl::guild_uuid.eq(guild_uuid))
+                .filter(dsl::user_uuid.ne(user_uuid))
+                .count()
+                .get_result(conn)
+                .await?;
+
+            if other_members == 0 {
+                Guild::fetch_one(conn, guild_uuid)
+                    .await?
+                    .delete(conn, app_state)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Leaves every guild, owned guilds are handed to the longest standing member
+    async fn transfer_memberships(
+        conn: &mut AsyncPgConnection,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        use guild_members::dsl;
+        let owned_guilds: Vec<Uuid> = load_or_empty(
+            dsl::guild_members
+                .filter(dsl::user_uuid.eq(user_uuid))
+                .filter(dsl::is_owner.eq(true))
+                .select(dsl::guild_uuid)
+                .load(conn)
+                .await,
+        )?;
+
+        for guild_uuid in owned_guilds {
+            let successor: Option<Uuid> = dsl::guild_members
+                .filter(dsl::guild_uuid.eq(guild_uuid))
+                .filter(dsl::user_uuid.ne(user_uuid))
+                .order(dsl::uuid.asc())
+                .select(dsl::uuid)
+                .first(conn)
+                .await
+                .optional()?;
+
+            if let Some(member_uuid) = successor {
+                update(guild_members::table)
+                    .filter(dsl::uuid.eq(member_uuid))
+                    .set(dsl::is_owner.eq(true))
+                    .execute(conn)
+                    .await?;
+            }
+        }
+
+        delete(guild_members::table)
+            .filter(dsl::user_uuid.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
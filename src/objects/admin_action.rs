@@ -0,0 +1,70 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{
+    ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper, insert_into,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{Conn, error::Error, schema::admin_actions};
+
+use super::load_or_empty;
+
+/// Audit log entry for an action taken by an instance administrator
+#[derive(Serialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = admin_actions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AdminAction {
+    uuid: Uuid,
+    admin_uuid: Option<Uuid>,
+    action: String,
+    target_uuid: Option<Uuid>,
+    details: Option<String>,
+    created_at: i64,
+}
+
+impl AdminAction {
+    pub async fn record(
+        conn: &mut Conn,
+        admin_uuid: Uuid,
+        action: &str,
+        target_uuid: Option<Uuid>,
+        details: Option<String>,
+    ) -> Result<(), Error> {
+        let admin_action = AdminAction {
+            uuid: Uuid::now_v7(),
+            admin_uuid: Some(admin_uuid),
+            action: action.to_string(),
+            target_uuid,
+            details,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+        };
+
+        insert_into(admin_actions::table)
+            .values(admin_action)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_amount(
+        conn: &mut Conn,
+        offset: i64,
+        amount: i64,
+    ) -> Result<Vec<Self>, Error> {
+        use admin_actions::dsl;
+        let admin_actions: Vec<AdminAction> = load_or_empty(
+            dsl::admin_actions
+                .order(dsl::uuid.desc())
+                .limit(amount)
+                .offset(offset)
+                .select(AdminAction::as_select())
+                .load(conn)
+                .await,
+        )?;
+
+        Ok(admin_actions)
+    }
+}
@@ -0,0 +1,174 @@
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, PgTextExpressionMethods,
+    QueryDsl, Queryable, Selectable, SelectableHelper, delete, update,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    Conn,
+    cache::{Cache, CacheKey},
+    error::Error,
+    schema::{instance_permissions, refresh_tokens, users},
+    utils::disconnect_sockets,
+};
+
+use super::{Suspension, load_or_empty};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = users)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct AdminUserBuilder {
+    uuid: Uuid,
+    username: String,
+    display_name: Option<String>,
+    email: String,
+    email_verified: bool,
+    disabled: bool,
+    suspended_at: Option<i64>,
+    suspended_until: Option<i64>,
+    suspension_reason: Option<String>,
+}
+
+impl AdminUserBuilder {
+    fn build(self, administrator: Option<bool>) -> Result<AdminUser, Error> {
+        Ok(AdminUser {
+            uuid: self.uuid,
+            username: self.username,
+            display_name: self.display_name,
+            email: self.email,
+            email_verified: self.email_verified,
+            disabled: self.disabled,
+            suspension: Suspension::from_columns(
+                self.suspended_at,
+                self.suspended_until,
+                self.suspension_reason,
+            )?,
+            administrator: administrator.unwrap_or(false),
+        })
+    }
+}
+
+/// User as seen by instance administrators, includes account state that is hidden from other users
+#[derive(Serialize)]
+pub struct AdminUser {
+    pub uuid: Uuid,
+    username: String,
+    display_name: Option<String>,
+    pub email: String,
+    email_verified: bool,
+    disabled: bool,
+    pub suspension: Option<Suspension>,
+    administrator: bool,
+}
+
+impl AdminUser {
+    pub async fn fetch_one(conn: &mut Conn, user_uuid: Uuid) -> Result<Self, Error> {
+        use users::dsl;
+        let (builder, administrator): (AdminUserBuilder, Option<bool>) = dsl::users
+            .left_join(instance_permissions::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .select((
+                AdminUserBuilder::as_select(),
+                instance_permissions::administrator.nullable(),
+            ))
+            .get_result(conn)
+            .await?;
+
+        builder.build(administrator)
+    }
+
+    /// Lists users, `query` matches usernames and email addresses
+    pub async fn fetch_amount(
+        conn: &mut Conn,
+        query: Option<String>,
+        offset: i64,
+        amount: i64,
+    ) -> Result<Vec<Self>, Error> {
+        use users::dsl;
+        let mut select = dsl::users
+            .left_join(instance_permissions::table)
+            .select((
+                AdminUserBuilder::as_select(),
+                instance_permissions::administrator.nullable(),
+            ))
+            .order(dsl::uuid)
+            .limit(amount)
+            .offset(offset)
+            .into_boxed();
+
+        if let Some(query) = query {
+            let pattern = format!("%{}%", query.replace('%', "\\%").replace('_', "\\_"));
+
+            select = select.filter(
+                dsl::username
+                    .ilike(pattern.clone())
+                    .or(dsl::email.ilike(pattern)),
+            );
+        }
+
+        let users: Vec<(AdminUserBuilder, Option<bool>)> = load_or_empty(select.load(conn).await)?;
+
+        users
+            .into_iter()
+            .map(|(builder, administrator)| builder.build(administrator))
+            .collect()
+    }
+
+    pub async fn verify_email(&mut self, conn: &mut Conn, cache_pool: &Cache) -> Result<(), Error> {
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(self.uuid))
+            .set(dsl::email_verified.eq(true))
+            .execute(conn)
+            .await?;
+
+        cache_pool
+            .del(&[CacheKey::EmailVerification(self.uuid)])
+            .await?;
+
+        self.email_verified = true;
+
+        Ok(())
+    }
+
+    /// Disabled accounts can't log in, disabling also logs the user out everywhere
+    pub async fn set_disabled(
+        &mut self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        disabled: bool,
+    ) -> Result<(), Error> {
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(self.uuid))
+            .set(dsl::disabled.eq(disabled))
+            .execute(conn)
+            .await?;
+
+        if disabled {
+            self.logout_everywhere(conn, cache_pool).await?;
+        }
+
+        self.disabled = disabled;
+
+        Ok(())
+    }
+
+    /// Revokes all refresh tokens of the user, access tokens are removed with them
+    pub async fn logout_everywhere(
+        &self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+    ) -> Result<(), Error> {
+        delete(refresh_tokens::table)
+            .filter(refresh_tokens::uuid.eq(self.uuid))
+            .execute(conn)
+            .await?;
+
+        disconnect_sockets(cache_pool, self.uuid).await?;
+
+        Ok(())
+    }
+}
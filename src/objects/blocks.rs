@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, Queryable, Selectable};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{Conn, error::Error, objects::User, schema::user_blocks};
+
+use super::load_or_empty;
+
+#[derive(Serialize, Queryable, Selectable, Clone)]
+#[diesel(table_name = user_blocks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserBlock {
+    pub blocker: Uuid,
+    pub blocked: Uuid,
+    pub blocked_at: DateTime<Utc>,
+}
+
+/// Blocked user as returned to the user who blocked them
+#[derive(Serialize)]
+pub struct BlockedUser {
+    pub user: User,
+    pub blocked_at: DateTime<Utc>,
+}
+
+impl UserBlock {
+    /// Returns true if either of the users has blocked the other
+    pub async fn exists_between(conn: &mut Conn, uuid1: Uuid, uuid2: Uuid) -> Result<bool, Error> {
+        use user_blocks::dsl;
+        let blocks: i64 = dsl::user_blocks
+            .filter(
+                (dsl::blocker.eq(uuid1).and(dsl::blocked.eq(uuid2)))
+                    .or(dsl::blocker.eq(uuid2).and(dsl::blocked.eq(uuid1))),
+            )
+            .count()
+            .get_result(conn)
+            .await?;
+
+        Ok(blocks > 0)
+    }
+
+    /// Uuids of every user the user has blocked
+    pub async fn blocked_by(conn: &mut Conn, blocker: Uuid) -> Result<HashSet<Uuid>, Error> {
+        use user_blocks::dsl;
+        let blocked: Vec<Uuid> = load_or_empty(
+            dsl::user_blocks
+                .filter(dsl::blocker.eq(blocker))
+                .select(dsl::blocked)
+                .load(conn)
+                .await,
+        )?;
+
+        Ok(blocked.into_iter().collect())
+    }
+}
@@ -1,6 +1,6 @@
 use diesel::{
-    ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper, delete,
-    insert_into, update,
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper, delete, insert_into, update,
 };
 use diesel_async::RunQueryDsl;
 use serde::{Deserialize, Serialize};
@@ -8,12 +8,14 @@ use uuid::Uuid;
 
 use crate::{
     Conn,
+    cache::{Cache, CacheKey},
     error::Error,
     schema::{channel_permissions, channels, messages},
-    utils::{CHANNEL_REGEX, CacheFns, order_by_is_above},
+    telemetry,
+    utils::{CHANNEL_REGEX, order_by_is_above},
 };
 
-use super::{HasIsAbove, HasUuid, Message, load_or_empty, message::MessageBuilder};
+use super::{HasIsAbove, HasUuid, Message, UserBlock, load_or_empty, message::MessageBuilder};
 
 #[derive(Queryable, Selectable, Insertable, Clone, Debug)]
 #[diesel(table_name = channels)]
@@ -24,6 +26,7 @@ struct ChannelBuilder {
     name: String,
     description: Option<String>,
     is_above: Option<Uuid>,
+    slowmode: i32,
 }
 
 impl ChannelBuilder {
@@ -43,6 +46,7 @@ impl ChannelBuilder {
             name: self.name,
             description: self.description,
             is_above: self.is_above,
+            slowmode: self.slowmode,
             permissions: channel_permission,
         })
     }
@@ -55,6 +59,7 @@ pub struct Channel {
     name: String,
     description: Option<String>,
     pub is_above: Option<Uuid>,
+    pub slowmode: i32,
     pub permissions: Vec<ChannelPermission>,
 }
 
@@ -100,10 +105,10 @@ impl Channel {
 
     pub async fn fetch_one(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         channel_uuid: Uuid,
     ) -> Result<Self, Error> {
-        if let Ok(cache_hit) = cache_pool.get_cache_key(channel_uuid.to_string()).await {
+        if let Ok(Some(cache_hit)) = cache_pool.get(&CacheKey::Channel(channel_uuid)).await {
             return Ok(cache_hit);
         }
 
@@ -117,7 +122,7 @@ impl Channel {
         let channel = channel_builder.build(conn).await?;
 
         cache_pool
-            .set_cache_key(channel_uuid.to_string(), channel.clone(), 60)
+            .set(&CacheKey::Channel(channel_uuid), &channel, 60)
             .await?;
 
         Ok(channel)
@@ -125,7 +130,7 @@ impl Channel {
 
     pub async fn new(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         guild_uuid: Uuid,
         name: String,
         description: Option<String>,
@@ -148,6 +153,7 @@ impl Channel {
             name: name.clone(),
             description: description.clone(),
             is_above: None,
+            slowmode: 0,
         };
 
         insert_into(channels::table)
@@ -171,28 +177,35 @@ impl Channel {
             name,
             description,
             is_above: None,
+            slowmode: 0,
             permissions: vec![],
         };
 
         cache_pool
-            .set_cache_key(channel_uuid.to_string(), channel.clone(), 1800)
+            .set(&CacheKey::Channel(channel_uuid), &channel, 1800)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<Vec<Channel>>(format!("{guild_uuid}_channels"))
-            .await
-            .is_ok()
-        {
-            cache_pool
-                .del_cache_key(format!("{guild_uuid}_channels"))
-                .await?;
+        let mut stale = vec![CacheKey::GuildChannels(guild_uuid)];
+
+        if let Some(old_last_channel) = last_channel {
+            stale.push(CacheKey::Channel(old_last_channel.uuid));
         }
 
+        cache_pool.del(&stale).await?;
+
         Ok(channel)
     }
 
-    pub async fn delete(self, conn: &mut Conn, cache_pool: &redis::Client) -> Result<(), Error> {
+    pub async fn delete(self, conn: &mut Conn, cache_pool: &Cache) -> Result<(), Error> {
         use channels::dsl;
+        // Channel below this one, its position changes
+        let below_uuid: Option<Uuid> = dsl::channels
+            .filter(dsl::is_above.eq(self.uuid))
+            .select(dsl::uuid)
+            .get_result(conn)
+            .await
+            .optional()?;
+
         match update(channels::table)
             .filter(dsl::is_above.eq(self.uuid))
             .set(dsl::is_above.eq(None::<Uuid>))
@@ -220,23 +233,14 @@ impl Channel {
             Err(e) => Err(e),
         }?;
 
-        if cache_pool
-            .get_cache_key::<Channel>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?;
-        }
+        let mut stale = vec![
+            CacheKey::Channel(self.uuid),
+            CacheKey::GuildChannels(self.guild_uuid),
+        ];
 
-        if cache_pool
-            .get_cache_key::<Vec<Channel>>(format!("{}_channels", self.guild_uuid))
-            .await
-            .is_ok()
-        {
-            cache_pool
-                .del_cache_key(format!("{}_channels", self.guild_uuid))
-                .await?;
-        }
+        stale.extend(below_uuid.map(CacheKey::Channel));
+
+        cache_pool.del(&stale).await?;
 
         Ok(())
     }
@@ -244,7 +248,8 @@ impl Channel {
     pub async fn fetch_messages(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
+        viewer_uuid: Uuid,
         amount: i64,
         offset: i64,
     ) -> Result<Vec<Message>, Error> {
@@ -260,10 +265,13 @@ impl Channel {
                 .await,
         )?;
 
-        let mut messages = vec![];
+        let blocked = UserBlock::blocked_by(conn, viewer_uuid).await?;
+
+        let mut messages =
+            MessageBuilder::build_many(conn, cache_pool, self.guild_uuid, message_builders).await?;
 
-        for builder in message_builders {
-            messages.push(builder.build(conn, cache_pool).await?);
+        for message in messages.iter_mut() {
+            message.author_blocked = blocked.contains(&message.user_uuid);
         }
 
         Ok(messages)
@@ -272,7 +280,7 @@ impl Channel {
     pub async fn new_message(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         user_uuid: Uuid,
         message: String,
         reply_to: Option<Uuid>,
@@ -292,13 +300,15 @@ impl Channel {
             .execute(conn)
             .await?;
 
-        message.build(conn, cache_pool).await
+        telemetry::record_message_sent();
+
+        message.build(conn, cache_pool, self.guild_uuid).await
     }
 
     pub async fn set_name(
         &mut self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         new_name: String,
     ) -> Result<(), Error> {
         if !CHANNEL_REGEX.is_match(&new_name) {
@@ -314,23 +324,12 @@ impl Channel {
 
         self.name = new_name;
 
-        if cache_pool
-            .get_cache_key::<Channel>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?;
-        }
-
-        if cache_pool
-            .get_cache_key::<Vec<Channel>>(format!("{}_channels", self.guild_uuid))
-            .await
-            .is_ok()
-        {
-            cache_pool
-                .del_cache_key(format!("{}_channels", self.guild_uuid))
-                .await?;
-        }
+        cache_pool
+            .del(&[
+                CacheKey::Channel(self.uuid),
+                CacheKey::GuildChannels(self.guild_uuid),
+            ])
+            .await?;
 
         Ok(())
     }
@@ -338,7 +337,7 @@ impl Channel {
     pub async fn set_description(
         &mut self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         new_description: String,
     ) -> Result<(), Error> {
         use channels::dsl;
@@ -350,31 +349,91 @@ impl Channel {
 
         self.description = Some(new_description);
 
-        if cache_pool
-            .get_cache_key::<Channel>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?;
-        }
+        cache_pool
+            .del(&[
+                CacheKey::Channel(self.uuid),
+                CacheKey::GuildChannels(self.guild_uuid),
+            ])
+            .await?;
 
-        if cache_pool
-            .get_cache_key::<Vec<Channel>>(format!("{}_channels", self.guild_uuid))
-            .await
-            .is_ok()
-        {
-            cache_pool
-                .del_cache_key(format!("{}_channels", self.guild_uuid))
-                .await?;
+        Ok(())
+    }
+
+    pub async fn set_slowmode(
+        &mut self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        new_slowmode: i32,
+    ) -> Result<(), Error> {
+        if !(0..=21600).contains(&new_slowmode) {
+            return Err(Error::BadRequest(
+                "Slowmode has to be between 0 and 21600 seconds".to_string(),
+            ));
         }
 
+        use channels::dsl;
+        update(channels::table)
+            .filter(dsl::uuid.eq(self.uuid))
+            .set(dsl::slowmode.eq(new_slowmode))
+            .execute(conn)
+            .await?;
+
+        self.slowmode = new_slowmode;
+
+        cache_pool
+            .del(&[
+                CacheKey::Channel(self.uuid),
+                CacheKey::GuildChannels(self.guild_uuid),
+            ])
+            .await?;
+
         Ok(())
     }
 
+    /// Registers a message send for `user_uuid` against the channel slowmode.
+    ///
+    /// Returns the remaining cooldown in seconds if the user has to wait before sending again.
+    pub async fn check_slowmode(
+        &self,
+        cache_pool: &Cache,
+        user_uuid: Uuid,
+    ) -> Result<Option<i64>, Error> {
+        if self.slowmode == 0 {
+            return Ok(None);
+        }
+
+        let key = CacheKey::Slowmode {
+            channel_uuid: self.uuid,
+            user_uuid,
+        };
+
+        if cache_pool.set_nx(&key, &1, self.slowmode as u32).await? {
+            return Ok(None);
+        }
+
+        let remaining = cache_pool.ttl(&key).await?;
+
+        Ok(Some(remaining.max(1)))
+    }
+
+    /// Gives back the cooldown taken by `check_slowmode`, used when the message couldn't be sent
+    pub async fn clear_slowmode(&self, cache_pool: &Cache, user_uuid: Uuid) -> Result<(), Error> {
+        if self.slowmode == 0 {
+            return Ok(());
+        }
+
+        cache_pool
+            .del(&[CacheKey::Slowmode {
+                channel_uuid: self.uuid,
+                user_uuid,
+            }])
+            .await
+    }
+
     pub async fn move_channel(
         &mut self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         new_is_above: Uuid,
     ) -> Result<(), Error> {
         use channels::dsl;
@@ -389,6 +448,14 @@ impl Channel {
             Err(e) => Err(e),
         }?;
 
+        // Channel that will be below this one, its position changes
+        let new_below_uuid: Option<Uuid> = dsl::channels
+            .filter(dsl::is_above.eq(new_is_above))
+            .select(dsl::uuid)
+            .get_result(conn)
+            .await
+            .optional()?;
+
         if let Some(uuid) = old_above_uuid {
             update(channels::table)
                 .filter(dsl::uuid.eq(uuid))
@@ -424,23 +491,19 @@ impl Channel {
 
         self.is_above = Some(new_is_above);
 
-        if cache_pool
-            .get_cache_key::<Channel>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?;
-        }
+        let mut stale = vec![
+            CacheKey::Channel(self.uuid),
+            CacheKey::GuildChannels(self.guild_uuid),
+        ];
 
-        if cache_pool
-            .get_cache_key::<Vec<Channel>>(format!("{}_channels", self.guild_uuid))
-            .await
-            .is_ok()
-        {
-            cache_pool
-                .del_cache_key(format!("{}_channels", self.guild_uuid))
-                .await?;
-        }
+        stale.extend(
+            old_above_uuid
+                .into_iter()
+                .chain(new_below_uuid)
+                .map(CacheKey::Channel),
+        );
+
+        cache_pool.del(&stale).await?;
 
         Ok(())
     }
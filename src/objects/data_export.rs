@@ -0,0 +1,221 @@
+use std::io::{Cursor, Write};
+
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use log::error;
+use serde::{Deserialize, Serialize};
+use tokio::task;
+use url::Url;
+use uuid::Uuid;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    AppState,
+    cache::{Cache, CacheKey},
+    error::Error,
+    objects::{EmailTemplate, FriendRequest, Me, QueuedEmail, Session, message::MessageBuilder},
+    schema::{friend_requests, messages},
+    utils::generate_token,
+};
+
+use super::load_or_empty;
+
+/// Seconds a finished export can be downloaded for
+const EXPORT_LIFETIME: u32 = 604800;
+
+/// Zip of everything stored about a user, built in the background and downloaded through an emailed link
+#[derive(Serialize, Deserialize)]
+pub struct DataExport {
+    user_uuid: Uuid,
+    pub token: String,
+    path: String,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+impl DataExport {
+    pub async fn get(cache_pool: &Cache, token: &str) -> Result<Self, Error> {
+        cache_pool
+            .get(&CacheKey::DataExport(token.to_string()))
+            .await?
+            .ok_or(Error::BadRequest(
+                "Export link is invalid or has expired".to_string(),
+            ))
+    }
+
+    /// Starts building an export for the user, one export can be requested per day
+    pub async fn request(app_state: &'static AppState, user_uuid: Uuid) -> Result<(), Error> {
+        if let Some(previous) = app_state
+            .cache_pool
+            .get::<DataExport>(&CacheKey::UserDataExport(user_uuid))
+            .await?
+        {
+            if Utc::now().signed_duration_since(previous.created_at) < Duration::hours(24) {
+                return Err(Error::TooManyRequests(
+                    "Please allow 24 hours between data export requests".to_string(),
+                ));
+            }
+
+            previous.delete(app_state).await?;
+        }
+
+        let token = generate_token::<32>()?;
+
+        let data_export = DataExport {
+            user_uuid,
+            path: format!("export/{user_uuid}/{token}.zip"),
+            token,
+            created_at: Utc::now(),
+        };
+
+        // Reserved before building so a second request can't start another export
+        app_state
+            .cache_pool
+            .set(
+                &CacheKey::UserDataExport(user_uuid),
+                &data_export,
+                EXPORT_LIFETIME,
+            )
+            .await?;
+
+        app_state.tasks.spawn(async move {
+            if let Err(error) = data_export.build(app_state).await {
+                error!(
+                    "failed to export data of {}: {error}",
+                    data_export.user_uuid
+                );
+
+                if let Err(error) = app_state
+                    .cache_pool
+                    .del(&[CacheKey::UserDataExport(data_export.user_uuid)])
+                    .await
+                {
+                    error!("{error}");
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn build(&self, app_state: &'static AppState) -> Result<(), Error> {
+        let mut conn = app_state.pool.get().await?;
+
+        let me = Me::get(&mut conn, self.user_uuid).await?;
+
+        let guilds = me.fetch_memberships(&mut conn).await?;
+
+        let friends = me.get_friends(&mut conn, &app_state.cache_pool).await?;
+
+        use friend_requests::dsl as fdsl;
+        let friend_requests: Vec<FriendRequest> = load_or_empty(
+            fdsl::friend_requests
+                .filter(
+                    fdsl::sender
+                        .eq(self.user_uuid)
+                        .or(fdsl::receiver.eq(self.user_uuid)),
+                )
+                .select(FriendRequest::as_select())
+                .load(&mut conn)
+                .await,
+        )?;
+
+        let blocks = me.get_blocks(&mut conn, &app_state.cache_pool).await?;
+
+        let sessions = Session::fetch_all(&mut conn, self.user_uuid, None).await?;
+
+        use messages::dsl as mdsl;
+        let messages: Vec<MessageBuilder> = load_or_empty(
+            mdsl::messages
+                .filter(mdsl::user_uuid.eq(self.user_uuid))
+                .order(mdsl::uuid)
+                .select(MessageBuilder::as_select())
+                .load(&mut conn)
+                .await,
+        )?;
+
+        let files = vec![
+            ("profile.json", serde_json::to_vec_pretty(&me)?),
+            ("guilds.json", serde_json::to_vec_pretty(&guilds)?),
+            ("friends.json", serde_json::to_vec_pretty(&friends)?),
+            (
+                "friend_requests.json",
+                serde_json::to_vec_pretty(&friend_requests)?,
+            ),
+            ("blocks.json", serde_json::to_vec_pretty(&blocks)?),
+            ("sessions.json", serde_json::to_vec_pretty(&sessions)?),
+            ("messages.json", serde_json::to_vec_pretty(&messages)?),
+        ];
+
+        let archive = task::spawn_blocking(move || {
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+            for (name, contents) in files {
+                zip.start_file(name, SimpleFileOptions::default())?;
+                zip.write_all(&contents)?;
+            }
+
+            Ok::<_, Error>(zip.finish()?.into_inner())
+        })
+        .await??;
+
+        app_state
+            .bunny_storage
+            .upload(&self.path, Bytes::from(archive))
+            .await?;
+
+        app_state
+            .cache_pool
+            .set(
+                &CacheKey::DataExport(self.token.clone()),
+                self,
+                EXPORT_LIFETIME,
+            )
+            .await?;
+
+        let download_endpoint: Url = format!(
+            "{}/v1/exports/{}",
+            app_state
+                .config
+                .web
+                .backend_url
+                .as_str()
+                .trim_end_matches('/'),
+            self.token
+        )
+        .parse()?;
+
+        QueuedEmail::enqueue(
+            &mut conn,
+            app_state,
+            self.user_uuid,
+            EmailTemplate::DataExport,
+            vec![
+                ("days", (EXPORT_LIFETIME / 86400).to_string()),
+                ("link", download_endpoint.to_string()),
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn download(&self, app_state: &AppState) -> Result<Bytes, Error> {
+        Ok(app_state.bunny_storage.download(&self.path).await?)
+    }
+
+    /// Removes the archive and invalidates its download link
+    pub async fn delete(&self, app_state: &AppState) -> Result<(), Error> {
+        if let Err(error) = app_state.bunny_storage.delete(&self.path).await {
+            error!("failed to delete data export {}: {error}", self.path);
+        }
+
+        app_state
+            .cache_pool
+            .del(&[CacheKey::DataExport(self.token.clone())])
+            .await?;
+
+        Ok(())
+    }
+}
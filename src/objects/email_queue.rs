@@ -0,0 +1,218 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper, delete, insert_into, update,
+};
+use diesel_async::RunQueryDsl;
+use lettre::{Address, message::MultiPart};
+use log::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    AppState, Conn,
+    error::Error,
+    schema::{email_queue, users},
+};
+
+use super::{EmailTemplate, load_or_empty};
+
+/// Seconds a claimed email is hidden from other senders while it is being delivered
+const DELIVERY_LEASE: i64 = 300;
+
+/// Longest wait between delivery attempts
+const MAX_BACKOFF: i64 = 21600;
+
+/// Seconds emails that could not be delivered are kept for
+const FAILED_RETENTION: i64 = 2592000;
+
+/// Emails due per run of the delivery job
+const BATCH_SIZE: i64 = 100;
+
+/// Rendered email waiting to be delivered, kept until it is sent or has run out of attempts
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = email_queue)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct QueuedEmail {
+    uuid: Uuid,
+    recipient: String,
+    subject: String,
+    text_body: String,
+    html_body: String,
+    attempts: i32,
+    next_attempt_at: i64,
+    last_error: Option<String>,
+    failed: bool,
+    created_at: i64,
+}
+
+impl QueuedEmail {
+    /// Renders the template in the user's locale and queues it, delivery is attempted right away in the background
+    pub async fn enqueue(
+        conn: &mut Conn,
+        app_state: &'static AppState,
+        user_uuid: Uuid,
+        template: EmailTemplate,
+        mut variables: Vec<(&str, String)>,
+    ) -> Result<(), Error> {
+        use users::dsl;
+        let (username, email_address, locale): (String, String, Option<String>) = dsl::users
+            .filter(dsl::uuid.eq(user_uuid))
+            .select((dsl::username, dsl::email, dsl::locale))
+            .get_result(conn)
+            .await?;
+
+        // Checked here so an invalid address fails the request instead of every delivery attempt
+        email_address.parse::<Address>()?;
+
+        variables.push(("username", username));
+
+        let email = template
+            .render(app_state, locale.as_deref(), &variables)
+            .await?;
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let queued_email = QueuedEmail {
+            uuid: Uuid::now_v7(),
+            recipient: email_address,
+            subject: email.subject,
+            text_body: email.text,
+            html_body: email.html,
+            attempts: 0,
+            next_attempt_at: current_time,
+            last_error: None,
+            failed: false,
+            created_at: current_time,
+        };
+
+        insert_into(email_queue::table)
+            .values(&queued_email)
+            .execute(conn)
+            .await?;
+
+        let uuid = queued_email.uuid;
+
+        app_state.tasks.spawn(async move {
+            if let Err(error) = Self::deliver(app_state, uuid).await {
+                error!("failed to deliver email {uuid}: {error}");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Attempts every due email, returns how many were due and how many of them were delivered
+    pub async fn deliver_due(app_state: &AppState) -> Result<(usize, usize), Error> {
+        let mut conn = app_state.pool.get().await?;
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        use email_queue::dsl;
+        delete(email_queue::table)
+            .filter(dsl::failed.eq(true))
+            .filter(dsl::created_at.lt(current_time - FAILED_RETENTION))
+            .execute(&mut conn)
+            .await?;
+
+        let due: Vec<Uuid> = load_or_empty(
+            dsl::email_queue
+                .filter(dsl::failed.eq(false))
+                .filter(dsl::next_attempt_at.le(current_time))
+                .order(dsl::next_attempt_at.asc())
+                .limit(BATCH_SIZE)
+                .select(dsl::uuid)
+                .load(&mut conn)
+                .await,
+        )?;
+
+        let mut delivered = 0;
+
+        for uuid in &due {
+            if Self::deliver(app_state, *uuid).await? {
+                delivered += 1;
+            }
+        }
+
+        Ok((due.len(), delivered))
+    }
+
+    /// Claims the email and sends it, returns false if it wasn't due, was claimed by another sender or failed
+    async fn deliver(app_state: &AppState, uuid: Uuid) -> Result<bool, Error> {
+        let mut conn = app_state.pool.get().await?;
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        use email_queue::dsl;
+        let claimed: Option<QueuedEmail> = update(email_queue::table)
+            .filter(dsl::uuid.eq(uuid))
+            .filter(dsl::failed.eq(false))
+            .filter(dsl::next_attempt_at.le(current_time))
+            .set((
+                dsl::attempts.eq(dsl::attempts + 1),
+                dsl::next_attempt_at.eq(current_time + DELIVERY_LEASE),
+            ))
+            .returning(QueuedEmail::as_returning())
+            .get_result(&mut conn)
+            .await
+            .optional()?;
+
+        let Some(queued_email) = claimed else {
+            return Ok(false);
+        };
+
+        let error = match queued_email.send(app_state).await {
+            Ok(()) => {
+                delete(email_queue::table)
+                    .filter(dsl::uuid.eq(uuid))
+                    .execute(&mut conn)
+                    .await?;
+
+                return Ok(true);
+            }
+            Err(error) => error.to_string(),
+        };
+
+        let failed = queued_email.attempts >= app_state.config.mail.max_attempts;
+
+        if failed {
+            error!(
+                "giving up on email {uuid} after {} attempts: {error}",
+                queued_email.attempts
+            );
+        } else {
+            warn!(
+                "failed to send email {uuid} on attempt {}: {error}",
+                queued_email.attempts
+            );
+        }
+
+        let backoff = (30i64 << (queued_email.attempts - 1).clamp(0, 10)).min(MAX_BACKOFF);
+
+        update(email_queue::table)
+            .filter(dsl::uuid.eq(uuid))
+            .set((
+                dsl::next_attempt_at.eq(current_time + backoff),
+                dsl::last_error.eq(error.chars().take(1024).collect::<String>()),
+                dsl::failed.eq(failed),
+            ))
+            .execute(&mut conn)
+            .await?;
+
+        Ok(false)
+    }
+
+    async fn send(&self, app_state: &AppState) -> Result<(), Error> {
+        let email = app_state
+            .mail_client
+            .message_builder()
+            .to(self.recipient.parse()?)
+            .subject(&self.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                self.text_body.clone(),
+                self.html_body.clone(),
+            ))?;
+
+        app_state.mail_client.send_mail(email).await
+    }
+}
@@ -0,0 +1,251 @@
+use std::path::Path;
+
+use tokio::fs::read_to_string;
+
+use crate::{AppState, error::Error};
+
+/// Locale the built in templates are written in, used when a template doesn't exist in the user's locale
+const DEFAULT_LOCALE: &str = "en";
+
+const LAYOUT: &str = include_str!("../../templates/email/layout.html");
+
+/// Emails sent to users, each is a `.txt` template whose first line is the subject and a `.html` template
+/// that is placed inside the layout
+#[derive(Clone, Copy)]
+pub enum EmailTemplate {
+    AccountLocked,
+    EmailVerification,
+    PasswordReset,
+    PasswordResetConfirmation,
+    DataExport,
+    SessionReuse,
+}
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl EmailTemplate {
+    fn name(self) -> &'static str {
+        match self {
+            Self::AccountLocked => "account_locked",
+            Self::EmailVerification => "email_verification",
+            Self::PasswordReset => "password_reset",
+            Self::PasswordResetConfirmation => "password_reset_confirmation",
+            Self::DataExport => "data_export",
+            Self::SessionReuse => "session_reuse",
+        }
+    }
+
+    fn built_in(self) -> (&'static str, &'static str) {
+        match self {
+            Self::AccountLocked => (
+                include_str!("../../templates/email/en/account_locked.txt"),
+                include_str!("../../templates/email/en/account_locked.html"),
+            ),
+            Self::EmailVerification => (
+                include_str!("../../templates/email/en/email_verification.txt"),
+                include_str!("../../templates/email/en/email_verification.html"),
+            ),
+            Self::PasswordReset => (
+                include_str!("../../templates/email/en/password_reset.txt"),
+                include_str!("../../templates/email/en/password_reset.html"),
+            ),
+            Self::PasswordResetConfirmation => (
+                include_str!("../../templates/email/en/password_reset_confirmation.txt"),
+                include_str!("../../templates/email/en/password_reset_confirmation.html"),
+            ),
+            Self::DataExport => (
+                include_str!("../../templates/email/en/data_export.txt"),
+                include_str!("../../templates/email/en/data_export.html"),
+            ),
+            Self::SessionReuse => (
+                include_str!("../../templates/email/en/session_reuse.txt"),
+                include_str!("../../templates/email/en/session_reuse.html"),
+            ),
+        }
+    }
+
+    /// Looks for the template in the template directory, trying the full locale, its language and then the
+    /// default locale before falling back to the built in templates, returns the locale that was found too
+    async fn load(
+        self,
+        template_directory: Option<&Path>,
+        locale: Option<&str>,
+    ) -> (String, String, String) {
+        if let Some(template_directory) = template_directory {
+            let mut locales = Vec::new();
+
+            // Locales are validated when set, this only keeps paths from escaping the template directory
+            if let Some(locale) = locale.filter(|locale| {
+                locale
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }) {
+                locales.push(locale);
+
+                if let Some((language, _)) = locale.split_once('-') {
+                    locales.push(language);
+                }
+            }
+
+            locales.push(DEFAULT_LOCALE);
+
+            for locale in locales {
+                let base = template_directory.join(locale).join(self.name());
+
+                if let (Ok(text), Ok(html)) = (
+                    read_to_string(base.with_extension("txt")).await,
+                    read_to_string(base.with_extension("html")).await,
+                ) {
+                    return (locale.to_string(), text, html);
+                }
+            }
+        }
+
+        let (text, html) = self.built_in();
+
+        (
+            DEFAULT_LOCALE.to_string(),
+            text.to_string(),
+            html.to_string(),
+        )
+    }
+
+    /// Renders the template, `{{ variable }}` is replaced with the value of the variable, escaped in html
+    pub async fn render(
+        self,
+        app_state: &AppState,
+        locale: Option<&str>,
+        variables: &[(&str, String)],
+    ) -> Result<RenderedEmail, Error> {
+        let template_directory = app_state.config.mail.template_directory.as_deref();
+
+        let (locale, text, html) = self.load(template_directory, locale).await;
+
+        let layout = match template_directory {
+            Some(template_directory) => read_to_string(template_directory.join("layout.html"))
+                .await
+                .unwrap_or(LAYOUT.to_string()),
+            None => LAYOUT.to_string(),
+        };
+
+        let mut variables = variables.to_vec();
+        variables.push(("instance_name", app_state.config.instance.name.clone()));
+        variables.push(("locale", locale));
+
+        let text = fill(&text, &variables, false);
+
+        let (subject, text) = text
+            .split_once('\n')
+            .ok_or(Error::InternalServerError(format!(
+                "email template {} has no body",
+                self.name()
+            )))?;
+
+        let content = fill(&html, &variables, true);
+        variables.push(("content", content));
+
+        Ok(RenderedEmail {
+            subject: subject.trim().to_string(),
+            text: text.trim_start_matches(['\r', '\n']).to_string(),
+            html: fill(&layout, &variables, true),
+        })
+    }
+}
+
+/// Replaces `{{ variable }}` placeholders, `{{{ variable }}}` inserts the value without escaping it
+fn fill(template: &str, variables: &[(&str, String)], escape: bool) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let raw = rest.starts_with("{{{");
+        let (open, close) = if raw { ("{{{", "}}}") } else { ("{{", "}}") };
+
+        let Some(end) = rest.find(close) else {
+            break;
+        };
+
+        let name = rest[open.len()..end].trim();
+
+        if let Some((_, value)) = variables.iter().find(|(key, _)| *key == name) {
+            if escape && !raw {
+                output.push_str(&escape_html(value));
+            } else {
+                output.push_str(value);
+            }
+        }
+
+        rest = &rest[end + close.len()..];
+    }
+
+    output.push_str(rest);
+
+    output
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Vec<(&'static str, String)> {
+        vec![
+            ("username", "<b>gorb</b>".to_string()),
+            ("link", "https://gorb.app/?a=1&b=2".to_string()),
+        ]
+    }
+
+    #[test]
+    fn fill_replaces_variables() {
+        assert_eq!(
+            fill("Hi {{username}}, {{ link }}", &variables(), false),
+            "Hi <b>gorb</b>, https://gorb.app/?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn fill_escapes_html_unless_raw() {
+        assert_eq!(
+            fill("{{ username }} {{{ username }}}", &variables(), true),
+            "&lt;b&gt;gorb&lt;/b&gt; <b>gorb</b>"
+        );
+    }
+
+    #[test]
+    fn fill_drops_unknown_and_keeps_unclosed_placeholders() {
+        assert_eq!(fill("a{{ missing }}b", &variables(), true), "ab");
+        assert_eq!(fill("a {{ link", &variables(), true), "a {{ link");
+    }
+
+    #[test]
+    fn escape_html_escapes_special_characters() {
+        assert_eq!(
+            escape_html(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain"), "plain");
+    }
+}
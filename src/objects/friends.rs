@@ -3,7 +3,13 @@ use diesel::{Queryable, Selectable};
 use serde::Serialize;
 use uuid::Uuid;
 
-use crate::schema::{friend_requests, friends};
+use crate::{
+    cache::Cache,
+    error::Error,
+    objects::User,
+    schema::{friend_requests, friends},
+    utils::publish_user_event,
+};
 
 #[derive(Serialize, Queryable, Selectable, Clone)]
 #[diesel(table_name = friends)]
@@ -22,3 +28,56 @@ pub struct FriendRequest {
     pub receiver: Uuid,
     pub requested_at: DateTime<Utc>,
 }
+
+/// Friend request with the other user of the request
+#[derive(Serialize)]
+pub struct PendingFriendRequest {
+    pub user: User,
+    pub requested_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct FriendRequests {
+    /// Requests sent to the user
+    pub incoming: Vec<PendingFriendRequest>,
+    /// Requests the user sent that haven't been answered yet
+    pub outgoing: Vec<PendingFriendRequest>,
+}
+
+/// Sent to the sockets of both users whenever a friend request or friendship between them changes
+#[derive(Serialize)]
+#[serde(tag = "event")]
+#[allow(clippy::enum_variant_names)]
+pub enum FriendEvent {
+    FriendRequestCreate {
+        entity: FriendRequest,
+    },
+    /// Sent when a request is accepted, declined or cancelled
+    FriendRequestDelete {
+        entity: FriendRequest,
+    },
+    FriendAdd {
+        entity: Friend,
+    },
+    FriendRemove {
+        entity: Friend,
+    },
+}
+
+impl FriendEvent {
+    pub async fn publish(&self, cache_pool: &Cache) -> Result<(), Error> {
+        let (uuid1, uuid2) = match self {
+            Self::FriendRequestCreate { entity } | Self::FriendRequestDelete { entity } => {
+                (entity.sender, entity.receiver)
+            }
+            Self::FriendAdd { entity } | Self::FriendRemove { entity } => {
+                (entity.uuid1, entity.uuid2)
+            }
+        };
+
+        let payload = serde_json::to_string(self)?;
+
+        publish_user_event(cache_pool, uuid1, payload.clone()).await?;
+        publish_user_event(cache_pool, uuid2, payload).await
+    }
+}
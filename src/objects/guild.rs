@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 use axum::body::Bytes;
 use diesel::{
-    ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper, insert_into,
-    update,
+    ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper, delete,
+    dsl::count_star, insert_into, update,
 };
 use diesel_async::RunQueryDsl;
+use log::error;
 use serde::Serialize;
 use tokio::task;
 use url::Url;
@@ -11,12 +14,13 @@ use uuid::Uuid;
 
 use crate::{
     AppState, Conn,
+    cache::CacheKey,
     error::Error,
     schema::{guild_members, guilds, invites},
     utils::image_check,
 };
 
-use super::{Invite, Member, Role, load_or_empty, member::MemberBuilder};
+use super::{Channel, HasUuid, Invite, Member, Role, load_or_empty, member::MemberBuilder};
 
 #[derive(Serialize, Queryable, Selectable, Insertable, Clone)]
 #[diesel(table_name = guilds)]
@@ -29,6 +33,45 @@ pub struct GuildBuilder {
 }
 
 impl GuildBuilder {
+    /// Builds several guilds with one query for member counts and one for roles
+    pub async fn build_many(conn: &mut Conn, builders: Vec<Self>) -> Result<Vec<Guild>, Error> {
+        let guild_uuids: Vec<Uuid> = builders.iter().map(|builder| builder.uuid).collect();
+
+        if guild_uuids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        use guild_members::dsl;
+        let member_counts: HashMap<Uuid, i64> = load_or_empty(
+            dsl::guild_members
+                .filter(dsl::guild_uuid.eq_any(&guild_uuids))
+                .group_by(dsl::guild_uuid)
+                .select((dsl::guild_uuid, count_star()))
+                .load::<(Uuid, i64)>(conn)
+                .await,
+        )?
+        .into_iter()
+        .collect();
+
+        let mut roles: HashMap<Uuid, Vec<Role>> = HashMap::new();
+
+        for role in Role::fetch_all_in(conn, &guild_uuids).await? {
+            roles.entry(role.guild_uuid).or_default().push(role);
+        }
+
+        Ok(builders
+            .into_iter()
+            .map(|builder| Guild {
+                member_count: member_counts.get(&builder.uuid).copied().unwrap_or(0),
+                roles: roles.remove(&builder.uuid).unwrap_or_default(),
+                uuid: builder.uuid,
+                name: builder.name,
+                description: builder.description,
+                icon: builder.icon.and_then(|i| i.parse().ok()),
+            })
+            .collect())
+    }
+
     pub async fn build(self, conn: &mut Conn) -> Result<Guild, Error> {
         let member_count = Member::count(conn, self.uuid).await?;
 
@@ -48,7 +91,7 @@ impl GuildBuilder {
 #[derive(Serialize)]
 pub struct Guild {
     pub uuid: Uuid,
-    name: String,
+    pub name: String,
     description: Option<String>,
     icon: Option<Url>,
     pub roles: Vec<Role>,
@@ -84,13 +127,7 @@ impl Guild {
                 .await,
         )?;
 
-        let mut guilds = vec![];
-
-        for builder in guild_builders {
-            guilds.push(builder.build(conn).await?);
-        }
-
-        Ok(guilds)
+        GuildBuilder::build_many(conn, guild_builders).await
     }
 
     pub async fn new(conn: &mut Conn, name: String, owner_uuid: Uuid) -> Result<Self, Error> {
@@ -179,6 +216,41 @@ impl Guild {
         Ok(invite)
     }
 
+    /// Deletes the guild, channels, roles, members and invites are removed with it
+    pub async fn delete(self, conn: &mut Conn, app_state: &AppState) -> Result<(), Error> {
+        let channels = Channel::fetch_all(conn, self.uuid).await?;
+
+        use guilds::dsl;
+        delete(guilds::table)
+            .filter(dsl::uuid.eq(self.uuid))
+            .execute(conn)
+            .await?;
+
+        let mut stale: Vec<CacheKey> = channels
+            .iter()
+            .map(|channel| CacheKey::Channel(channel.uuid))
+            .collect();
+
+        stale.extend(self.roles.iter().map(|role| CacheKey::Role(*role.uuid())));
+        stale.push(CacheKey::GuildChannels(self.uuid));
+        stale.push(CacheKey::GuildRoles(self.uuid));
+
+        app_state.cache_pool.del(&stale).await?;
+
+        if let Some(icon) = &self.icon {
+            let relative_url = icon.path().trim_start_matches('/');
+
+            if let Err(error) = app_state.bunny_storage.delete(relative_url).await {
+                error!(
+                    "failed to delete icon of deleted guild {}: {error}",
+                    self.uuid
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     // FIXME: Horrible security
     pub async fn set_icon(
         &mut self,
@@ -0,0 +1,31 @@
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use crate::{Conn, error::Error, schema::instance_permissions};
+
+pub struct InstancePermissions;
+
+impl InstancePermissions {
+    pub async fn is_admin(conn: &mut Conn, user_uuid: Uuid) -> Result<bool, Error> {
+        use instance_permissions::dsl;
+        let administrator: Option<bool> = dsl::instance_permissions
+            .filter(dsl::uuid.eq(user_uuid))
+            .select(dsl::administrator)
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        Ok(administrator.unwrap_or(false))
+    }
+
+    pub async fn check_admin(conn: &mut Conn, user_uuid: Uuid) -> Result<(), Error> {
+        if !Self::is_admin(conn, user_uuid).await? {
+            return Err(Error::Forbidden(
+                "Instance administrator permissions required".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use diesel::{ExpressionMethods, QueryDsl, insert_into, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Conn,
+    cache::{Cache, CacheKey},
+    config::Config,
+    error::Error,
+    schema::instance_settings,
+};
+
+use super::load_or_empty;
+
+const REGISTRATION: &str = "registration";
+const REQUIRE_EMAIL_VERIFICATION: &str = "require_email_verification";
+
+/// Instance settings that administrators can change at runtime, values not set in the database fall back to the config
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct InstanceSettings {
+    pub registration: bool,
+    pub require_email_verification: bool,
+}
+
+impl InstanceSettings {
+    pub async fn get(conn: &mut Conn, cache_pool: &Cache, config: &Config) -> Result<Self, Error> {
+        if let Ok(Some(cache_hit)) = cache_pool.get(&CacheKey::InstanceSettings).await {
+            return Ok(cache_hit);
+        }
+
+        use instance_settings::dsl;
+        let overrides: Vec<(String, bool)> = load_or_empty(
+            dsl::instance_settings
+                .select((dsl::key, dsl::value))
+                .load(conn)
+                .await,
+        )?;
+
+        let mut instance_settings = InstanceSettings {
+            registration: config.instance.registration,
+            require_email_verification: config.instance.require_email_verification,
+        };
+
+        for (key, value) in overrides {
+            match key.as_str() {
+                REGISTRATION => instance_settings.registration = value,
+                REQUIRE_EMAIL_VERIFICATION => instance_settings.require_email_verification = value,
+                _ => {}
+            }
+        }
+
+        cache_pool
+            .set(&CacheKey::InstanceSettings, &instance_settings, 1800)
+            .await?;
+
+        Ok(instance_settings)
+    }
+
+    pub async fn set(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        key: &str,
+        value: bool,
+    ) -> Result<(), Error> {
+        if key != REGISTRATION && key != REQUIRE_EMAIL_VERIFICATION {
+            return Err(Error::BadRequest(format!("Unknown instance setting {key}")));
+        }
+
+        use instance_settings::dsl;
+        insert_into(instance_settings::table)
+            .values((dsl::key.eq(key), dsl::value.eq(value)))
+            .on_conflict(dsl::key)
+            .do_update()
+            .set(dsl::value.eq(excluded(dsl::value)))
+            .execute(conn)
+            .await?;
+
+        cache_pool.del(&[CacheKey::InstanceSettings]).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use std::{fmt::Display, net::IpAddr, sync::LazyLock};
+
+use chrono::{DateTime, Duration, Utc};
+use redis::Script;
+use uuid::Uuid;
+
+use crate::{
+    AppState, Conn,
+    cache::{Cache, CacheKey},
+    config::LoginProtection,
+    error::Error,
+};
+
+use super::{EmailTemplate, QueuedEmail};
+
+/// What failed attempts are counted against
+#[derive(Clone, Copy)]
+pub enum AttemptSubject {
+    Account(Uuid),
+    Ip(IpAddr),
+}
+
+impl Display for AttemptSubject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttemptSubject::Account(uuid) => write!(f, "account:{uuid}"),
+            AttemptSubject::Ip(ip) => write!(f, "ip:{ip}"),
+        }
+    }
+}
+
+/// Counts a failure and locks the subject once it reaches the threshold, in one step so concurrent attempts can't skip the lockout
+///
+/// Returns the number of failures before the lockout was applied, the attempt that locked the subject is the one that sees the threshold
+static REGISTER_FAILURE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+local now = tonumber(ARGV[1])
+local lockout_after = tonumber(ARGV[2])
+local lockout_duration = tonumber(ARGV[3])
+local expire = tonumber(ARGV[4])
+
+local failures = redis.call('HINCRBY', KEYS[1], 'failures', 1)
+redis.call('HSET', KEYS[1], 'last_failure', now)
+
+if failures >= lockout_after then
+    redis.call('HSET', KEYS[1], 'failures', 0, 'locked_until', now + lockout_duration)
+    expire = math.max(expire, lockout_duration)
+end
+
+redis.call('EXPIRE', KEYS[1], expire)
+
+return failures
+"#,
+    )
+});
+
+#[derive(Default)]
+pub struct LoginAttempts {
+    failures: u32,
+    last_failure: Option<DateTime<Utc>>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl LoginAttempts {
+    /// Fails when the cache can't be read, so attempts are rejected instead of skipping the limits
+    pub async fn get(cache_pool: &Cache, subject: AttemptSubject) -> Result<Self, Error> {
+        let (failures, last_failure, locked_until): (Option<u32>, Option<i64>, Option<i64>) =
+            redis::cmd("HMGET")
+                .arg(CacheKey::LoginAttempts(subject.to_string()).to_string())
+                .arg("failures")
+                .arg("last_failure")
+                .arg("locked_until")
+                .query_async(&mut cache_pool.connection())
+                .await?;
+
+        Ok(LoginAttempts {
+            failures: failures.unwrap_or(0),
+            last_failure: last_failure.and_then(|time| DateTime::from_timestamp(time, 0)),
+            locked_until: locked_until.and_then(|time| DateTime::from_timestamp(time, 0)),
+        })
+    }
+
+    /// Returns `TooManyRequests` while the subject is locked or has to wait before trying again
+    pub fn check(&self, login_protection: &LoginProtection) -> Result<(), Error> {
+        let now = Utc::now();
+
+        if let Some(locked_until) = self.locked_until
+            && locked_until > now
+        {
+            return Err(Error::TooManyRequests(format!(
+                "Too many failed attempts, try again in {} seconds",
+                (locked_until - now).num_seconds().max(1)
+            )));
+        }
+
+        if self.failures >= login_protection.delay_after
+            && let Some(last_failure) = self.last_failure
+        {
+            let exponent = (self.failures - login_protection.delay_after).min(16);
+            let delay = Duration::seconds((1i64 << exponent).min(login_protection.max_delay));
+
+            let allowed_at = last_failure + delay;
+
+            if allowed_at > now {
+                return Err(Error::TooManyRequests(format!(
+                    "Too many failed attempts, try again in {} seconds",
+                    (allowed_at - now).num_seconds().max(1)
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts a failed attempt, returns true if this attempt locked the subject
+    pub async fn register_failure(
+        cache_pool: &Cache,
+        login_protection: &LoginProtection,
+        subject: AttemptSubject,
+    ) -> Result<bool, Error> {
+        let lockout_after = match subject {
+            AttemptSubject::Account(_) => login_protection.lockout_after,
+            AttemptSubject::Ip(_) => login_protection.ip_lockout_after,
+        };
+
+        let failures: u32 = REGISTER_FAILURE
+            .key(CacheKey::LoginAttempts(subject.to_string()).to_string())
+            .arg(Utc::now().timestamp())
+            .arg(lockout_after)
+            .arg(login_protection.lockout_duration)
+            .arg(login_protection.attempt_window)
+            .invoke_async(&mut cache_pool.connection())
+            .await?;
+
+        Ok(failures >= lockout_after)
+    }
+
+    pub async fn clear(cache_pool: &Cache, subject: AttemptSubject) -> Result<(), Error> {
+        cache_pool
+            .del(&[CacheKey::LoginAttempts(subject.to_string())])
+            .await
+    }
+
+    /// Lets the account owner know their account was locked after too many failed login attempts
+    pub async fn send_lockout_email(
+        conn: &mut Conn,
+        app_state: &'static AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        let minutes = app_state.config.login_protection.lockout_duration / 60;
+
+        let reset_endpoint = app_state.config.web.frontend_url.join("reset-password")?;
+
+        QueuedEmail::enqueue(
+            conn,
+            app_state,
+            user_uuid,
+            EmailTemplate::AccountLocked,
+            vec![
+                ("minutes", minutes.to_string()),
+                ("link", reset_endpoint.to_string()),
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login_protection() -> LoginProtection {
+        LoginProtection {
+            delay_after: 3,
+            max_delay: 60,
+            lockout_after: 10,
+            ip_lockout_after: 50,
+            lockout_duration: 900,
+            attempt_window: 3600,
+        }
+    }
+
+    fn attempts(failures: u32, seconds_since_failure: i64) -> LoginAttempts {
+        LoginAttempts {
+            failures,
+            last_failure: Some(Utc::now() - Duration::seconds(seconds_since_failure)),
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn no_delay_before_delay_after() {
+        assert!(attempts(2, 0).check(&login_protection()).is_ok());
+    }
+
+    #[test]
+    fn delay_doubles_with_each_failure() {
+        // 3 failures wait 1 second, 5 failures wait 4 seconds
+        assert!(attempts(3, 0).check(&login_protection()).is_err());
+        assert!(attempts(3, 2).check(&login_protection()).is_ok());
+        assert!(attempts(5, 3).check(&login_protection()).is_err());
+        assert!(attempts(5, 5).check(&login_protection()).is_ok());
+    }
+
+    #[test]
+    fn delay_is_capped_at_max_delay() {
+        assert!(attempts(40, 59).check(&login_protection()).is_err());
+        assert!(attempts(40, 61).check(&login_protection()).is_ok());
+    }
+
+    #[test]
+    fn locked_until_rejects_attempts() {
+        let locked = LoginAttempts {
+            locked_until: Some(Utc::now() + Duration::seconds(30)),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            locked.check(&login_protection()),
+            Err(Error::TooManyRequests(_))
+        ));
+
+        let expired = LoginAttempts {
+            locked_until: Some(Utc::now() - Duration::seconds(1)),
+            ..Default::default()
+        };
+
+        assert!(expired.check(&login_protection()).is_ok());
+    }
+}
@@ -0,0 +1,54 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    cache::{Cache, CacheKey},
+    error::Error,
+    utils::generate_token,
+};
+
+/// Issued by login when the account requires a second factor, exchanged for a session once the second factor is verified
+#[derive(Serialize, Deserialize)]
+pub struct LoginChallenge {
+    pub user_uuid: Uuid,
+    pub token: String,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+impl LoginChallenge {
+    pub async fn new(cache_pool: &Cache, user_uuid: Uuid) -> Result<Self, Error> {
+        let token = generate_token::<32>()?;
+
+        let login_challenge = LoginChallenge {
+            user_uuid,
+            token: token.clone(),
+            created_at: Utc::now(),
+        };
+
+        cache_pool
+            .set(
+                &CacheKey::LoginChallenge(token.clone()),
+                &login_challenge,
+                300,
+            )
+            .await?;
+
+        Ok(login_challenge)
+    }
+
+    pub async fn get(cache_pool: &Cache, token: &str) -> Result<Self, Error> {
+        cache_pool
+            .get(&CacheKey::LoginChallenge(token.to_string()))
+            .await?
+            .ok_or(Error::Unauthorized(
+                "Invalid or expired login challenge".to_string(),
+            ))
+    }
+
+    pub async fn delete(&self, cache_pool: &Cache) -> Result<(), Error> {
+        cache_pool
+            .del(&[CacheKey::LoginChallenge(self.token.clone())])
+            .await
+    }
+}
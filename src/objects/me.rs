@@ -1,9 +1,10 @@
+use argon2::{PasswordHash, PasswordVerifier};
 use axum::body::Bytes;
 use diesel::{
-    ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper, delete, insert_into,
-    update,
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper, delete, insert_into, update,
 };
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use serde::Serialize;
 use tokio::task;
 use url::Url;
@@ -11,13 +12,17 @@ use uuid::Uuid;
 
 use crate::{
     AppState, Conn,
+    cache::{Cache, CacheKey},
     error::Error,
-    objects::{Friend, FriendRequest, User},
-    schema::{friend_requests, friends, guild_members, guilds, users},
-    utils::{CacheFns, EMAIL_REGEX, USERNAME_REGEX, image_check},
+    objects::{
+        BlockedUser, Friend, FriendEvent, FriendRequest, FriendRequests, PendingFriendRequest,
+        User, UserBlock,
+    },
+    schema::{friend_requests, friends, guild_members, guilds, user_blocks, users},
+    utils::{EMAIL_REGEX, LOCALE_REGEX, USERNAME_REGEX, image_check, reload_blocks},
 };
 
-use super::{Guild, guild::GuildBuilder, load_or_empty, member::MemberBuilder};
+use super::{Guild, guild::GuildBuilder, load_or_empty};
 
 #[derive(Serialize, Queryable, Selectable)]
 #[diesel(table_name = users)]
@@ -32,6 +37,8 @@ pub struct Me {
     online_status: i16,
     pub email: String,
     pub email_verified: bool,
+    /// Language emails are sent in, `None` for the instance default
+    locale: Option<String>,
 }
 
 impl Me {
@@ -48,30 +55,16 @@ impl Me {
 
     pub async fn fetch_memberships(&self, conn: &mut Conn) -> Result<Vec<Guild>, Error> {
         use guild_members::dsl;
-        let memberships: Vec<MemberBuilder> = load_or_empty(
+        let guild_builders: Vec<GuildBuilder> = load_or_empty(
             dsl::guild_members
+                .inner_join(guilds::table)
                 .filter(dsl::user_uuid.eq(self.uuid))
-                .select(MemberBuilder::as_select())
+                .select(GuildBuilder::as_select())
                 .load(conn)
                 .await,
         )?;
 
-        let mut guilds: Vec<Guild> = vec![];
-
-        for membership in memberships {
-            use guilds::dsl;
-            guilds.push(
-                dsl::guilds
-                    .filter(dsl::uuid.eq(membership.guild_uuid))
-                    .select(GuildBuilder::as_select())
-                    .get_result(conn)
-                    .await?
-                    .build(conn)
-                    .await?,
-            )
-        }
-
-        Ok(guilds)
+        GuildBuilder::build_many(conn, guild_builders).await
     }
 
     pub async fn set_avatar(
@@ -80,8 +73,7 @@ impl Me {
         app_state: &AppState,
         avatar: Bytes,
     ) -> Result<(), Error> {
-        let avatar_clone = avatar.clone();
-        let image_type = task::spawn_blocking(move || image_check(avatar_clone)).await??;
+        let image_type = Self::check_avatar(avatar.clone()).await?;
 
         if let Some(avatar) = &self.avatar {
             let avatar_url: Url = avatar.parse()?;
@@ -104,23 +96,21 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if app_state
+        app_state
             .cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            app_state
-                .cache_pool
-                .del_cache_key(self.uuid.to_string())
-                .await?
-        }
+            .del(&[CacheKey::User(self.uuid)])
+            .await?;
 
         self.avatar = Some(avatar_url.to_string());
 
         Ok(())
     }
 
+    /// Returns the file extension of the avatar, or `BadRequest` if it isn't a supported image
+    pub async fn check_avatar(avatar: Bytes) -> Result<String, Error> {
+        task::spawn_blocking(move || image_check(avatar)).await?
+    }
+
     pub async fn verify_email(&self, conn: &mut Conn) -> Result<(), Error> {
         use users::dsl;
         update(users::table)
@@ -132,10 +122,11 @@ impl Me {
         Ok(())
     }
 
+    /// Like the other profile setters it leaves the cached user alone, so several fields can be set in one transaction.
+    /// `CacheKey::User` has to be deleted once it's committed
     pub async fn set_username(
         &mut self,
-        conn: &mut Conn,
-        cache_pool: &redis::Client,
+        conn: &mut AsyncPgConnection,
         new_username: String,
     ) -> Result<(), Error> {
         if !USERNAME_REGEX.is_match(&new_username)
@@ -152,14 +143,6 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?
-        }
-
         self.username = new_username;
 
         Ok(())
@@ -167,8 +150,7 @@ impl Me {
 
     pub async fn set_display_name(
         &mut self,
-        conn: &mut Conn,
-        cache_pool: &redis::Client,
+        conn: &mut AsyncPgConnection,
         new_display_name: String,
     ) -> Result<(), Error> {
         let new_display_name_option = if new_display_name.is_empty() {
@@ -184,23 +166,37 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?
-        }
-
         self.display_name = new_display_name_option;
 
         Ok(())
     }
 
+    /// Re-checks the password of the user, used to confirm sensitive actions
+    pub async fn verify_password(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        password: &str,
+    ) -> Result<(), Error> {
+        use users::dsl;
+        let database_password: String = dsl::users
+            .filter(dsl::uuid.eq(self.uuid))
+            .select(dsl::password)
+            .get_result(conn)
+            .await?;
+
+        let parsed_hash = PasswordHash::new(&database_password)
+            .map_err(|e| Error::PasswordHashError(e.to_string()))?;
+
+        app_state
+            .argon2
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .map_err(|_| Error::Unauthorized("Wrong password".to_string()))
+    }
+
     pub async fn set_email(
         &mut self,
-        conn: &mut Conn,
-        cache_pool: &redis::Client,
+        conn: &mut AsyncPgConnection,
         new_email: String,
     ) -> Result<(), Error> {
         if !EMAIL_REGEX.is_match(&new_email) {
@@ -217,14 +213,6 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?
-        }
-
         self.email = new_email;
 
         Ok(())
@@ -232,8 +220,7 @@ impl Me {
 
     pub async fn set_pronouns(
         &mut self,
-        conn: &mut Conn,
-        cache_pool: &redis::Client,
+        conn: &mut AsyncPgConnection,
         new_pronouns: String,
     ) -> Result<(), Error> {
         use users::dsl;
@@ -243,21 +230,12 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?
-        }
-
         Ok(())
     }
 
     pub async fn set_about(
         &mut self,
-        conn: &mut Conn,
-        cache_pool: &redis::Client,
+        conn: &mut AsyncPgConnection,
         new_about: String,
     ) -> Result<(), Error> {
         use users::dsl;
@@ -267,21 +245,12 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
-        {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?
-        }
-
         Ok(())
     }
 
     pub async fn set_online_status(
         &mut self,
-        conn: &mut Conn,
-        cache_pool: &redis::Client,
+        conn: &mut AsyncPgConnection,
         new_status: i16,
     ) -> Result<(), Error> {
         if !(0..=4).contains(&new_status) {
@@ -296,14 +265,32 @@ impl Me {
             .execute(conn)
             .await?;
 
-        if cache_pool
-            .get_cache_key::<User>(self.uuid.to_string())
-            .await
-            .is_ok()
+        Ok(())
+    }
+
+    /// Sets the language emails are sent in, an empty string resets it to the default
+    pub async fn set_locale(
+        &mut self,
+        conn: &mut AsyncPgConnection,
+        new_locale: String,
+    ) -> Result<(), Error> {
+        let new_locale = Some(new_locale).filter(|locale| !locale.is_empty());
+
+        if let Some(locale) = &new_locale
+            && !LOCALE_REGEX.is_match(locale)
         {
-            cache_pool.del_cache_key(self.uuid.to_string()).await?
+            return Err(Error::BadRequest("Invalid locale".to_string()));
         }
 
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(self.uuid))
+            .set(dsl::locale.eq(&new_locale))
+            .execute(conn)
+            .await?;
+
+        self.locale = new_locale;
+
         Ok(())
     }
 
@@ -339,148 +326,362 @@ impl Me {
         Ok(Some(friends[0].clone()))
     }
 
-    pub async fn add_friend(&self, conn: &mut Conn, user_uuid: Uuid) -> Result<(), Error> {
+    /// Sends a friend request to the user, fails if one between the two users is already pending
+    pub async fn send_friend_request(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<FriendRequest, Error> {
+        if self.uuid == user_uuid {
+            return Err(Error::BadRequest(
+                "Can't send a friend request to yourself".to_string(),
+            ));
+        }
+
         if self.friends_with(conn, user_uuid).await?.is_some() {
-            // TODO: Check if another error should be used
             return Err(Error::BadRequest("Already friends with user".to_string()));
         }
 
+        if UserBlock::exists_between(conn, self.uuid, user_uuid).await? {
+            return Err(Error::Forbidden(
+                "Can't send a friend request to this user".to_string(),
+            ));
+        }
+
+        use friend_requests::dsl;
+
+        let incoming: i64 = dsl::friend_requests
+            .filter(dsl::sender.eq(user_uuid))
+            .filter(dsl::receiver.eq(self.uuid))
+            .count()
+            .get_result(conn)
+            .await?;
+
+        if incoming > 0 {
+            return Err(Error::BadRequest(
+                "User has already sent you a friend request, accept it instead".to_string(),
+            ));
+        }
+
+        let pending: i64 = dsl::friend_requests
+            .filter(dsl::sender.eq(self.uuid))
+            .count()
+            .get_result(conn)
+            .await?;
+
+        if pending >= app_state.config.instance.max_pending_friend_requests {
+            return Err(Error::TooManyRequests(format!(
+                "You can't have more than {} pending friend requests",
+                app_state.config.instance.max_pending_friend_requests
+            )));
+        }
+
+        let friend_request: Option<FriendRequest> = insert_into(friend_requests::table)
+            .values((dsl::sender.eq(self.uuid), dsl::receiver.eq(user_uuid)))
+            .on_conflict_do_nothing()
+            .returning(FriendRequest::as_returning())
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        let friend_request =
+            friend_request.ok_or(Error::BadRequest("Friend request already sent".to_string()))?;
+
+        FriendEvent::FriendRequestCreate {
+            entity: friend_request.clone(),
+        }
+        .publish(&app_state.cache_pool)
+        .await?;
+
+        Ok(friend_request)
+    }
+
+    /// Accepts the friend request the user sent
+    pub async fn accept_friend_request(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<Friend, Error> {
+        use friend_requests::dsl;
+        let friend_request: FriendRequest = delete(friend_requests::table)
+            .filter(dsl::sender.eq(user_uuid))
+            .filter(dsl::receiver.eq(self.uuid))
+            .returning(FriendRequest::as_returning())
+            .get_result(conn)
+            .await?;
+
+        let (uuid1, uuid2) = if self.uuid < user_uuid {
+            (self.uuid, user_uuid)
+        } else {
+            (user_uuid, self.uuid)
+        };
+
+        let friend: Friend = insert_into(friends::table)
+            .values((friends::uuid1.eq(uuid1), friends::uuid2.eq(uuid2)))
+            .returning(Friend::as_returning())
+            .get_result(conn)
+            .await?;
+
+        FriendEvent::FriendRequestDelete {
+            entity: friend_request,
+        }
+        .publish(&app_state.cache_pool)
+        .await?;
+
+        FriendEvent::FriendAdd {
+            entity: friend.clone(),
+        }
+        .publish(&app_state.cache_pool)
+        .await?;
+
+        Ok(friend)
+    }
+
+    /// Declines the friend request the user sent
+    pub async fn decline_friend_request(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        self.delete_friend_request(conn, app_state, user_uuid, self.uuid)
+            .await
+    }
+
+    /// Takes back the friend request sent to the user
+    pub async fn cancel_friend_request(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        self.delete_friend_request(conn, app_state, self.uuid, user_uuid)
+            .await
+    }
+
+    async fn delete_friend_request(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        sender: Uuid,
+        receiver: Uuid,
+    ) -> Result<(), Error> {
         use friend_requests::dsl;
+        let friend_request: FriendRequest = delete(friend_requests::table)
+            .filter(dsl::sender.eq(sender))
+            .filter(dsl::receiver.eq(receiver))
+            .returning(FriendRequest::as_returning())
+            .get_result(conn)
+            .await?;
+
+        FriendEvent::FriendRequestDelete {
+            entity: friend_request,
+        }
+        .publish(&app_state.cache_pool)
+        .await
+    }
 
-        let friend_request: Vec<FriendRequest> = load_or_empty(
+    pub async fn get_friend_requests(
+        &self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+    ) -> Result<FriendRequests, Error> {
+        use friend_requests::dsl;
+        let friend_requests: Vec<FriendRequest> = load_or_empty(
             dsl::friend_requests
-                .filter(dsl::sender.eq(user_uuid))
-                .filter(dsl::receiver.eq(self.uuid))
+                .filter(dsl::sender.eq(self.uuid).or(dsl::receiver.eq(self.uuid)))
+                .order(dsl::requested_at.desc())
+                .select(FriendRequest::as_select())
                 .load(conn)
                 .await,
         )?;
 
-        #[allow(clippy::get_first)]
-        if let Some(friend_request) = friend_request.get(0) {
-            use friends::dsl;
-
-            if self.uuid < user_uuid {
-                insert_into(friends::table)
-                    .values((dsl::uuid1.eq(self.uuid), dsl::uuid2.eq(user_uuid)))
-                    .execute(conn)
-                    .await?;
-            } else {
-                insert_into(friends::table)
-                    .values((dsl::uuid1.eq(user_uuid), dsl::uuid2.eq(self.uuid)))
-                    .execute(conn)
-                    .await?;
-            }
-
-            use friend_requests::dsl as frdsl;
+        let user_uuids: Vec<Uuid> = friend_requests
+            .iter()
+            .map(
+                |friend_request| match friend_request.receiver == self.uuid {
+                    true => friend_request.sender,
+                    false => friend_request.receiver,
+                },
+            )
+            .collect();
 
-            delete(friend_requests::table)
-                .filter(frdsl::sender.eq(friend_request.sender))
-                .filter(frdsl::receiver.eq(friend_request.receiver))
-                .execute(conn)
-                .await?;
+        let users = User::fetch_many(conn, cache_pool, &user_uuids).await?;
 
-            Ok(())
-        } else {
-            use friend_requests::dsl;
+        let mut incoming = vec![];
+        let mut outgoing = vec![];
 
-            insert_into(friend_requests::table)
-                .values((dsl::sender.eq(self.uuid), dsl::receiver.eq(user_uuid)))
-                .execute(conn)
-                .await?;
+        for (friend_request, user) in friend_requests.into_iter().zip(users) {
+            let pending = PendingFriendRequest {
+                user,
+                requested_at: friend_request.requested_at,
+            };
 
-            Ok(())
+            match friend_request.receiver == self.uuid {
+                true => incoming.push(pending),
+                false => outgoing.push(pending),
+            }
         }
+
+        Ok(FriendRequests { incoming, outgoing })
     }
 
-    pub async fn remove_friend(&self, conn: &mut Conn, user_uuid: Uuid) -> Result<(), Error> {
-        if self.friends_with(conn, user_uuid).await?.is_none() {
+    pub async fn remove_friend(
+        &self,
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        let Some(friend) = self.friends_with(conn, user_uuid).await? else {
             // TODO: Check if another error should be used
             return Err(Error::BadRequest("Not friends with user".to_string()));
-        }
+        };
 
         use friends::dsl;
+        delete(friends::table)
+            .filter(dsl::uuid1.eq(friend.uuid1))
+            .filter(dsl::uuid2.eq(friend.uuid2))
+            .execute(conn)
+            .await?;
 
-        if self.uuid < user_uuid {
-            delete(friends::table)
-                .filter(dsl::uuid1.eq(self.uuid))
-                .filter(dsl::uuid2.eq(user_uuid))
-                .execute(conn)
-                .await?;
-        } else {
-            delete(friends::table)
-                .filter(dsl::uuid1.eq(user_uuid))
-                .filter(dsl::uuid2.eq(self.uuid))
-                .execute(conn)
-                .await?;
-        }
-
-        Ok(())
+        FriendEvent::FriendRemove { entity: friend }
+            .publish(&app_state.cache_pool)
+            .await
     }
 
-    pub async fn get_friends(
+    /// Blocks the user, removing any friendship or pending friend request between the two users
+    pub async fn block_user(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
-    ) -> Result<Vec<User>, Error> {
-        use friends::dsl;
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        if self.uuid == user_uuid {
+            return Err(Error::BadRequest("Can't block yourself".to_string()));
+        }
 
-        let friends1 = load_or_empty(
-            dsl::friends
-                .filter(dsl::uuid1.eq(self.uuid))
-                .select(Friend::as_select())
-                .load(conn)
-                .await,
-        )?;
+        User::fetch_one(conn, &app_state.cache_pool, user_uuid).await?;
 
-        let friends2 = load_or_empty(
-            dsl::friends
-                .filter(dsl::uuid2.eq(self.uuid))
-                .select(Friend::as_select())
-                .load(conn)
+        use user_blocks::dsl;
+        insert_into(user_blocks::table)
+            .values((dsl::blocker.eq(self.uuid), dsl::blocked.eq(user_uuid)))
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await?;
+
+        if self.friends_with(conn, user_uuid).await?.is_some() {
+            self.remove_friend(conn, app_state, user_uuid).await?;
+        }
+
+        use friend_requests::dsl as frdsl;
+        let friend_requests: Vec<FriendRequest> = load_or_empty(
+            delete(friend_requests::table)
+                .filter(
+                    (frdsl::sender
+                        .eq(self.uuid)
+                        .and(frdsl::receiver.eq(user_uuid)))
+                    .or(frdsl::sender
+                        .eq(user_uuid)
+                        .and(frdsl::receiver.eq(self.uuid))),
+                )
+                .returning(FriendRequest::as_returning())
+                .get_results(conn)
                 .await,
         )?;
 
-        let mut friends = vec![];
-
-        for friend in friends1 {
-            friends
-                .push(User::fetch_one_with_friendship(conn, cache_pool, self, friend.uuid2).await?);
+        for friend_request in friend_requests {
+            FriendEvent::FriendRequestDelete {
+                entity: friend_request,
+            }
+            .publish(&app_state.cache_pool)
+            .await?;
         }
 
-        for friend in friends2 {
-            friends
-                .push(User::fetch_one_with_friendship(conn, cache_pool, self, friend.uuid1).await?);
+        reload_blocks(&app_state.cache_pool, self.uuid).await
+    }
+
+    pub async fn unblock_user(
+        &self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        user_uuid: Uuid,
+    ) -> Result<(), Error> {
+        use user_blocks::dsl;
+        let deleted = delete(user_blocks::table)
+            .filter(dsl::blocker.eq(self.uuid))
+            .filter(dsl::blocked.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        if deleted == 0 {
+            return Err(diesel::result::Error::NotFound.into());
         }
 
-        Ok(friends)
+        reload_blocks(cache_pool, self.uuid).await
     }
 
-    /* TODO
-    pub async fn get_friend_requests(&self, conn: &mut Conn) -> Result<Vec<FriendRequest>, Error> {
-        use friend_requests::dsl;
-
-        let friend_request: Vec<FriendRequest> = load_or_empty(
-            dsl::friend_requests
-                .filter(dsl::receiver.eq(self.uuid))
+    pub async fn get_blocks(
+        &self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+    ) -> Result<Vec<BlockedUser>, Error> {
+        use user_blocks::dsl;
+        let blocks: Vec<UserBlock> = load_or_empty(
+            dsl::user_blocks
+                .filter(dsl::blocker.eq(self.uuid))
+                .order(dsl::blocked_at.desc())
+                .select(UserBlock::as_select())
                 .load(conn)
-                .await
+                .await,
         )?;
 
-        Ok()
-    }
+        let user_uuids: Vec<Uuid> = blocks.iter().map(|block| block.blocked).collect();
 
-    pub async fn delete_friend_request(&self, conn: &mut Conn, user_uuid: Uuid) -> Result<Vec<FriendRequest>, Error> {
-        use friend_requests::dsl;
+        let users = User::fetch_many(conn, cache_pool, &user_uuids).await?;
 
-        let friend_request: Vec<FriendRequest> = load_or_empty(
-            dsl::friend_requests
-                .filter(dsl::sender.eq(user_uuid))
-                .filter(dsl::receiver.eq(self.uuid))
+        Ok(blocks
+            .into_iter()
+            .zip(users)
+            .map(|(block, user)| BlockedUser {
+                user,
+                blocked_at: block.blocked_at,
+            })
+            .collect())
+    }
+
+    pub async fn get_friends(
+        &self,
+        conn: &mut Conn,
+        cache_pool: &Cache,
+    ) -> Result<Vec<User>, Error> {
+        use friends::dsl;
+        let friendships: Vec<Friend> = load_or_empty(
+            dsl::friends
+                .filter(dsl::uuid1.eq(self.uuid).or(dsl::uuid2.eq(self.uuid)))
+                .select(Friend::as_select())
                 .load(conn)
-                .await
+                .await,
         )?;
 
-        Ok()
+        let friend_uuids: Vec<Uuid> = friendships
+            .iter()
+            .map(|friend| {
+                if friend.uuid1 == self.uuid {
+                    friend.uuid2
+                } else {
+                    friend.uuid1
+                }
+            })
+            .collect();
+
+        let mut friends = User::fetch_many(conn, cache_pool, &friend_uuids).await?;
+
+        for (user, friendship) in friends.iter_mut().zip(friendships) {
+            user.friends_since = Some(friendship.accepted_at);
+        }
+
+        Ok(friends)
     }
-    */
 }
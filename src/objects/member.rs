@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use diesel::{
     Associations, BoolExpressionMethods, ExpressionMethods, Identifiable, Insertable, JoinOnDsl,
     QueryDsl, Queryable, Selectable, SelectableHelper, define_sql_function, delete, insert_into,
@@ -9,6 +11,7 @@ use uuid::Uuid;
 
 use crate::{
     Conn,
+    cache::Cache,
     error::Error,
     objects::PaginationRequest,
     schema::{friends, guild_bans, guild_members, users},
@@ -21,7 +24,7 @@ use super::{
 
 define_sql_function! { fn coalesce(x: Nullable<VarChar>, y: Nullable<VarChar>, z: VarChar) -> Text; }
 
-#[derive(Serialize, Queryable, Identifiable, Selectable, Insertable, Associations)]
+#[derive(Clone, Serialize, Queryable, Identifiable, Selectable, Insertable, Associations)]
 #[diesel(table_name = guild_members)]
 #[diesel(belongs_to(UserBuilder, foreign_key = user_uuid))]
 #[diesel(belongs_to(Guild, foreign_key = guild_uuid))]
@@ -39,7 +42,7 @@ impl MemberBuilder {
     pub async fn build(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         me: Option<&Me>,
     ) -> Result<Member, Error> {
         let user;
@@ -66,7 +69,7 @@ impl MemberBuilder {
     async fn build_with_parts(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         user_builder: UserBuilder,
         friend: Option<Friend>,
     ) -> Result<Member, Error> {
@@ -92,7 +95,7 @@ impl MemberBuilder {
     pub async fn check_permission(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         permission: Permissions,
     ) -> Result<(), Error> {
         if !self.is_owner {
@@ -149,7 +152,7 @@ impl Member {
 
     pub async fn fetch_one(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         me: Option<&Me>,
         user_uuid: Uuid,
         guild_uuid: Uuid,
@@ -194,9 +197,71 @@ impl Member {
             .await
     }
 
+    /// Members of a guild for several users with one query for members and users and one for roles, keyed
+    /// by user uuid, users that aren't members are left out
+    pub async fn fetch_many(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        guild_uuid: Uuid,
+        user_uuids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Self>, Error> {
+        if user_uuids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        use guild_members::dsl;
+        let member_builders: Vec<(MemberBuilder, UserBuilder)> = load_or_empty(
+            dsl::guild_members
+                .filter(dsl::guild_uuid.eq(guild_uuid))
+                .filter(dsl::user_uuid.eq_any(user_uuids))
+                .inner_join(users::table)
+                .select((MemberBuilder::as_select(), UserBuilder::as_select()))
+                .load(conn)
+                .await,
+        )?;
+
+        let members: Vec<MemberBuilder> = member_builders
+            .iter()
+            .map(|(member, _)| member.clone())
+            .collect();
+
+        let mut roles = Role::fetch_from_members(conn, cache_pool, &members).await?;
+
+        Ok(member_builders
+            .into_iter()
+            .map(|(member, user)| {
+                (
+                    member.user_uuid,
+                    Member {
+                        uuid: member.uuid,
+                        nickname: member.nickname,
+                        user_uuid: member.user_uuid,
+                        guild_uuid: member.guild_uuid,
+                        is_owner: member.is_owner,
+                        user: user.build(),
+                        roles: roles.remove(&member.uuid).unwrap_or_default(),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Stands in for an author who has left the guild, with a nil uuid and no roles
+    pub fn departed(user: User, guild_uuid: Uuid) -> Self {
+        Self {
+            uuid: Uuid::nil(),
+            nickname: None,
+            user_uuid: user.uuid,
+            guild_uuid,
+            is_owner: false,
+            user,
+            roles: vec![],
+        }
+    }
+
     pub async fn fetch_one_with_uuid(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         me: Option<&Me>,
         uuid: Uuid,
     ) -> Result<Self, Error> {
@@ -240,7 +305,7 @@ impl Member {
 
     pub async fn fetch_page(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         me: &Me,
         guild_uuid: Uuid,
         pagination: PaginationRequest,
@@ -304,7 +369,7 @@ impl Member {
 
     pub async fn new(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         user_uuid: Uuid,
         guild_uuid: Uuid,
     ) -> Result<Self, Error> {
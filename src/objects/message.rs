@@ -1,17 +1,14 @@
-use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable};
-use diesel_async::RunQueryDsl;
+use std::collections::HashSet;
+
+use diesel::{Insertable, Queryable, Selectable};
 use serde::Serialize;
 use uuid::Uuid;
 
-use crate::{
-    Conn,
-    error::Error,
-    schema::{channels, guilds, messages},
-};
+use crate::{Conn, cache::Cache, error::Error, schema::messages};
 
-use super::Member;
+use super::{Member, User};
 
-#[derive(Clone, Queryable, Selectable, Insertable)]
+#[derive(Clone, Serialize, Queryable, Selectable, Insertable)]
 #[diesel(table_name = messages)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct MessageBuilder {
@@ -23,30 +20,68 @@ pub struct MessageBuilder {
 }
 
 impl MessageBuilder {
+    /// `guild_uuid` is the guild of the message's channel
     pub async fn build(
         &self,
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
+        guild_uuid: Uuid,
     ) -> Result<Message, Error> {
-        use channels::dsl;
+        let member = Member::fetch_one(conn, cache_pool, None, self.user_uuid, guild_uuid).await?;
+
+        Ok(self.clone().with_member(member))
+    }
 
-        let guild_uuid = dsl::channels
-            .filter(dsl::uuid.eq(self.channel_uuid))
-            .inner_join(guilds::table)
-            .select(guilds::uuid)
-            .get_result(conn)
-            .await?;
+    /// Builds messages of one guild, loading each distinct author once
+    ///
+    /// Authors who are no longer members get a placeholder from `Member::departed`
+    pub async fn build_many(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        guild_uuid: Uuid,
+        builders: Vec<Self>,
+    ) -> Result<Vec<Message>, Error> {
+        let author_uuids: Vec<Uuid> = builders
+            .iter()
+            .map(|builder| builder.user_uuid)
+            .collect::<HashSet<Uuid>>()
+            .into_iter()
+            .collect();
 
-        let member = Member::fetch_one(conn, cache_pool, None, self.user_uuid, guild_uuid).await?;
+        let mut members = Member::fetch_many(conn, cache_pool, guild_uuid, &author_uuids).await?;
 
-        Ok(Message {
+        let departed: Vec<Uuid> = author_uuids
+            .into_iter()
+            .filter(|uuid| !members.contains_key(uuid))
+            .collect();
+
+        for user in User::fetch_many(conn, cache_pool, &departed).await? {
+            members.insert(user.uuid, Member::departed(user, guild_uuid));
+        }
+
+        builders
+            .into_iter()
+            .map(|builder| {
+                let member = members
+                    .get(&builder.user_uuid)
+                    .cloned()
+                    .ok_or(diesel::result::Error::NotFound)?;
+
+                Ok(builder.with_member(member))
+            })
+            .collect()
+    }
+
+    fn with_member(self, member: Member) -> Message {
+        Message {
             uuid: self.uuid,
             channel_uuid: self.channel_uuid,
             user_uuid: self.user_uuid,
-            message: self.message.clone(),
+            message: self.message,
             reply_to: self.reply_to,
             member,
-        })
+            author_blocked: false,
+        }
     }
 }
 
@@ -54,8 +89,10 @@ impl MessageBuilder {
 pub struct Message {
     uuid: Uuid,
     channel_uuid: Uuid,
-    user_uuid: Uuid,
+    pub user_uuid: Uuid,
     message: String,
     reply_to: Option<Uuid>,
     member: Member,
+    /// True when the user the message is returned to has blocked its author, sockets set it for each subscriber
+    pub author_blocked: bool,
 }
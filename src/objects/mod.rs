@@ -1,41 +1,87 @@
+use std::path::PathBuf;
+
 use lettre::{
-    AsyncSmtpTransport, AsyncTransport, Message as Email, Tokio1Executor,
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message as Email, Tokio1Executor,
     message::{Mailbox, MessageBuilder as EmailBuilder},
     transport::smtp::authentication::Credentials,
 };
-use log::debug;
+use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod account_deletion;
+mod admin_action;
+mod admin_user;
 mod bans;
+mod blocks;
 mod channel;
+mod data_export;
+mod email_queue;
+mod email_template;
 mod email_token;
 mod friends;
 mod guild;
+mod instance_permissions;
+mod instance_settings;
 mod invite;
+mod login_attempts;
+mod login_challenge;
 mod me;
 mod member;
 pub mod message;
+mod passkey;
 mod password_reset_token;
+mod registration_block;
+mod report;
 mod role;
+mod session;
+mod suspension;
+mod totp;
 mod user;
 
+pub use account_deletion::AccountDeletion;
+pub use admin_action::AdminAction;
+pub use admin_user::AdminUser;
 pub use bans::GuildBan;
+pub use blocks::BlockedUser;
+pub use blocks::UserBlock;
 pub use channel::Channel;
+pub use data_export::DataExport;
+pub use email_queue::QueuedEmail;
+pub use email_template::EmailTemplate;
 pub use email_token::EmailToken;
 pub use friends::Friend;
+pub use friends::FriendEvent;
 pub use friends::FriendRequest;
+pub use friends::FriendRequests;
+pub use friends::PendingFriendRequest;
 pub use guild::Guild;
+pub use instance_permissions::InstancePermissions;
+pub use instance_settings::InstanceSettings;
 pub use invite::Invite;
+pub use login_attempts::AttemptSubject;
+pub use login_attempts::LoginAttempts;
+pub use login_challenge::LoginChallenge;
 pub use me::Me;
 pub use member::Member;
 pub use message::Message;
+pub use passkey::Passkey;
+pub use passkey::PasskeyOptions;
 pub use password_reset_token::PasswordResetToken;
+pub use registration_block::RegistrationBlock;
+pub use report::GuildReport;
+pub use report::Report;
+pub use report::ReportQuery;
+pub use report::ReportRequest;
+pub use report::ReportResolution;
 pub use role::Permissions;
 pub use role::Role;
+pub use session::Session;
+pub use suspension::Suspension;
+pub use totp::Totp;
 pub use user::User;
 
-use crate::error::Error;
+use crate::{config::Mail, error::Error};
 
 pub trait HasUuid {
     fn uuid(&self) -> &Uuid;
@@ -115,26 +161,55 @@ impl From<String> for MailTls {
     }
 }
 
+#[derive(Clone)]
+enum MailTransport {
+    Smtp {
+        creds: Credentials,
+        server: String,
+        tls: MailTls,
+    },
+    /// Writes every email to a `.eml` file in the directory
+    File(PathBuf),
+    /// Prints every email to the log
+    Log,
+}
+
 #[derive(Clone)]
 pub struct MailClient {
-    creds: Credentials,
-    smtp_server: String,
     mbox: Mailbox,
-    tls: MailTls,
+    transport: MailTransport,
 }
 
 impl MailClient {
-    pub fn new<T: Into<MailTls>>(
-        creds: Credentials,
-        smtp_server: String,
-        mbox: String,
-        tls: T,
-    ) -> Result<Self, Error> {
+    pub fn new(mail: &Mail) -> Result<Self, Error> {
+        let transport = match &*mail.transport.to_lowercase() {
+            "smtp" => {
+                let smtp = mail.smtp.as_ref().ok_or(Error::InternalServerError(
+                    "mail.smtp is required when using the smtp transport".to_string(),
+                ))?;
+
+                MailTransport::Smtp {
+                    creds: smtp.credentials(),
+                    server: smtp.server.clone(),
+                    tls: mail.tls.clone().into(),
+                }
+            }
+            "file" => {
+                std::fs::create_dir_all(&mail.file_directory)?;
+
+                MailTransport::File(mail.file_directory.clone())
+            }
+            "log" => MailTransport::Log,
+            transport => {
+                return Err(Error::InternalServerError(format!(
+                    "unknown mail transport: {transport}"
+                )));
+            }
+        };
+
         Ok(Self {
-            creds,
-            smtp_server,
-            mbox: mbox.parse()?,
-            tls: tls.into(),
+            mbox: mail.address.parse()?,
+            transport,
         })
     }
 
@@ -142,21 +217,72 @@ impl MailClient {
         Email::builder().from(self.mbox.clone())
     }
 
-    pub async fn send_mail(&self, email: Email) -> Result<(), Error> {
-        let mailer: AsyncSmtpTransport<Tokio1Executor> = match self.tls {
-            MailTls::StartTls => {
-                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.smtp_server)?
-                    .credentials(self.creds.clone())
-                    .build()
-            }
-            MailTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.smtp_server)?
-                .credentials(self.creds.clone())
+    fn smtp_transport(
+        creds: &Credentials,
+        server: &str,
+        tls: &MailTls,
+    ) -> Result<AsyncSmtpTransport<Tokio1Executor>, Error> {
+        Ok(match tls {
+            MailTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(server)?
+                .credentials(creds.clone())
                 .build(),
-        };
+            MailTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(server)?
+                .credentials(creds.clone())
+                .build(),
+        })
+    }
+
+    /// Checks that mail can be delivered, connects to the SMTP server or checks the mail directory is writable
+    pub async fn check(&self) -> Result<(), Error> {
+        match &self.transport {
+            MailTransport::Smtp { creds, server, tls } => {
+                let mailer = Self::smtp_transport(creds, server, tls)?;
 
-        let response = mailer.send(email).await?;
+                if !mailer.test_connection().await? {
+                    return Err(Error::InternalServerError(format!(
+                        "SMTP server {server} refused the connection"
+                    )));
+                }
+            }
+            MailTransport::File(directory) => {
+                let metadata = tokio::fs::metadata(directory).await?;
+
+                if !metadata.is_dir() || metadata.permissions().readonly() {
+                    return Err(Error::InternalServerError(format!(
+                        "{} is not a writable directory",
+                        directory.display()
+                    )));
+                }
+            }
+            MailTransport::Log => {}
+        }
+
+        Ok(())
+    }
 
-        debug!("mail sending response: {response:?}");
+    pub async fn send_mail(&self, email: Email) -> Result<(), Error> {
+        match &self.transport {
+            MailTransport::Smtp { creds, server, tls } => {
+                let mailer = Self::smtp_transport(creds, server, tls)?;
+
+                let response = mailer.send(email).await?;
+
+                debug!("mail sending response: {response:?}");
+            }
+            MailTransport::File(directory) => {
+                let id = AsyncFileTransport::<Tokio1Executor>::new(directory)
+                    .send(email)
+                    .await?;
+
+                info!(
+                    "wrote mail to {}",
+                    directory.join(format!("{id}.eml")).display()
+                );
+            }
+            MailTransport::Log => {
+                info!("mail:\n{}", String::from_utf8_lossy(&email.formatted()));
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,336 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{
+    ExpressionMethods, OptionalExtension, QueryDsl, Queryable, Selectable, SelectableHelper,
+    delete, insert_into, update,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, DiscoverableAuthentication, DiscoverableKey, Passkey as Credential,
+    PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use crate::{
+    AppState, Conn, cache::CacheKey, error::Error, schema::passkeys, utils::generate_token,
+};
+
+use super::{LoginChallenge, Me, load_or_empty};
+
+/// State of an authentication ceremony, kept in the cache until the client responds
+#[derive(Serialize, Deserialize)]
+enum AuthenticationState {
+    /// Second factor after a password login, stored under the login challenge token
+    SecondFactor {
+        user_uuid: Uuid,
+        state: PasskeyAuthentication,
+    },
+    /// Passwordless login, the authenticator tells us who the user is
+    Passwordless { state: DiscoverableAuthentication },
+}
+
+#[derive(Serialize)]
+pub struct PasskeyOptions {
+    pub challenge_token: String,
+    pub public_key: RequestChallengeResponse,
+}
+
+#[derive(Serialize, Queryable, Selectable)]
+#[diesel(table_name = passkeys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Passkey {
+    pub uuid: Uuid,
+    #[serde(skip)]
+    user_uuid: Uuid,
+    pub name: String,
+    #[serde(skip)]
+    credential: String,
+    pub created_at: i64,
+    pub last_used: Option<i64>,
+}
+
+impl Passkey {
+    pub async fn fetch_all(conn: &mut Conn, user_uuid: Uuid) -> Result<Vec<Self>, Error> {
+        use passkeys::dsl;
+        let passkeys: Vec<Passkey> = load_or_empty(
+            dsl::passkeys
+                .filter(dsl::user_uuid.eq(user_uuid))
+                .select(Passkey::as_select())
+                .order(dsl::created_at.asc())
+                .load(conn)
+                .await,
+        )?;
+
+        Ok(passkeys)
+    }
+
+    pub async fn has_any(conn: &mut Conn, user_uuid: Uuid) -> Result<bool, Error> {
+        use passkeys::dsl;
+        let count: i64 = dsl::passkeys
+            .filter(dsl::user_uuid.eq(user_uuid))
+            .count()
+            .get_result(conn)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    fn credential(&self) -> Result<Credential, Error> {
+        Ok(serde_json::from_str(&self.credential)?)
+    }
+
+    /// Starts registering a new passkey, the returned options are passed to `navigator.credentials.create()`
+    pub async fn start_registration(
+        conn: &mut Conn,
+        app_state: &AppState,
+        me: &Me,
+    ) -> Result<CreationChallengeResponse, Error> {
+        let exclude_credentials = Self::fetch_all(conn, me.uuid)
+            .await?
+            .iter()
+            .map(|passkey| passkey.credential().map(|c| c.cred_id().clone()))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let (options, state) = app_state.webauthn.start_passkey_registration(
+            me.uuid,
+            &me.username,
+            me.display_name.as_ref().unwrap_or(&me.username),
+            Some(exclude_credentials),
+        )?;
+
+        app_state
+            .cache_pool
+            .set(&CacheKey::PasskeyRegistration(me.uuid), &state, 300)
+            .await?;
+
+        Ok(options)
+    }
+
+    pub async fn finish_registration(
+        conn: &mut Conn,
+        app_state: &AppState,
+        me: &Me,
+        name: String,
+        response: &RegisterPublicKeyCredential,
+    ) -> Result<Self, Error> {
+        if name.trim().is_empty() || name.len() > 32 {
+            return Err(Error::BadRequest(
+                "Passkey name must be between 1 and 32 characters".to_string(),
+            ));
+        }
+
+        let key = CacheKey::PasskeyRegistration(me.uuid);
+
+        let state: PasskeyRegistration =
+            app_state
+                .cache_pool
+                .get(&key)
+                .await?
+                .ok_or(Error::BadRequest(
+                    "No pending passkey registration".to_string(),
+                ))?;
+
+        app_state.cache_pool.del(&[key]).await?;
+
+        let credential = app_state
+            .webauthn
+            .finish_passkey_registration(response, &state)
+            .map_err(|e| Error::BadRequest(e.to_string()))?;
+
+        let credential_id = hex::encode(credential.cred_id());
+
+        use passkeys::dsl;
+        let existing: Option<Uuid> = dsl::passkeys
+            .filter(dsl::credential_id.eq(&credential_id))
+            .select(dsl::uuid)
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        if existing.is_some() {
+            return Err(Error::BadRequest(
+                "Passkey is already registered".to_string(),
+            ));
+        }
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let passkey = Passkey {
+            uuid: Uuid::now_v7(),
+            user_uuid: me.uuid,
+            name: name.trim().to_string(),
+            credential: serde_json::to_string(&credential)?,
+            created_at: current_time,
+            last_used: None,
+        };
+
+        insert_into(passkeys::table)
+            .values((
+                dsl::uuid.eq(passkey.uuid),
+                dsl::user_uuid.eq(passkey.user_uuid),
+                dsl::credential_id.eq(credential_id),
+                dsl::name.eq(&passkey.name),
+                dsl::credential.eq(&passkey.credential),
+                dsl::created_at.eq(passkey.created_at),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(passkey)
+    }
+
+    pub async fn delete(conn: &mut Conn, user_uuid: Uuid, uuid: Uuid) -> Result<(), Error> {
+        use passkeys::dsl;
+        let deleted = delete(passkeys::table)
+            .filter(dsl::uuid.eq(uuid))
+            .filter(dsl::user_uuid.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        if deleted == 0 {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+
+        Ok(())
+    }
+
+    /// Starts a passkey login, the returned options are passed to `navigator.credentials.get()`
+    ///
+    /// With a login challenge the passkey is used as a second factor, otherwise any discoverable passkey can log in without a password
+    pub async fn start_authentication(
+        conn: &mut Conn,
+        app_state: &AppState,
+        login_challenge: Option<LoginChallenge>,
+    ) -> Result<PasskeyOptions, Error> {
+        let (challenge_token, public_key, state) = match login_challenge {
+            Some(login_challenge) => {
+                let credentials = Self::fetch_all(conn, login_challenge.user_uuid)
+                    .await?
+                    .iter()
+                    .map(|passkey| passkey.credential())
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                if credentials.is_empty() {
+                    return Err(Error::BadRequest("No passkeys registered".to_string()));
+                }
+
+                let (public_key, state) = app_state
+                    .webauthn
+                    .start_passkey_authentication(&credentials)?;
+
+                (
+                    login_challenge.token,
+                    public_key,
+                    AuthenticationState::SecondFactor {
+                        user_uuid: login_challenge.user_uuid,
+                        state,
+                    },
+                )
+            }
+            None => {
+                let (public_key, state) = app_state.webauthn.start_discoverable_authentication()?;
+
+                (
+                    generate_token::<32>()?,
+                    public_key,
+                    AuthenticationState::Passwordless { state },
+                )
+            }
+        };
+
+        app_state
+            .cache_pool
+            .set(
+                &CacheKey::PasskeyAuthentication(challenge_token.clone()),
+                &state,
+                300,
+            )
+            .await?;
+
+        Ok(PasskeyOptions {
+            challenge_token,
+            public_key,
+        })
+    }
+
+    /// Finishes a passkey login, returns the uuid of the authenticated user
+    pub async fn finish_authentication(
+        conn: &mut Conn,
+        app_state: &AppState,
+        challenge_token: &str,
+        response: &PublicKeyCredential,
+    ) -> Result<Uuid, Error> {
+        let key = CacheKey::PasskeyAuthentication(challenge_token.to_string());
+
+        let state: AuthenticationState =
+            app_state
+                .cache_pool
+                .get(&key)
+                .await?
+                .ok_or(Error::Unauthorized(
+                    "Invalid or expired login challenge".to_string(),
+                ))?;
+
+        // Challenges are single use, even when verification fails
+        app_state.cache_pool.del(&[key]).await?;
+
+        let credential_id = hex::encode(response.get_credential_id());
+
+        use passkeys::dsl;
+        let passkey: Passkey = dsl::passkeys
+            .filter(dsl::credential_id.eq(&credential_id))
+            .select(Passkey::as_select())
+            .get_result(conn)
+            .await
+            .optional()?
+            .ok_or(Error::Unauthorized("Unknown passkey".to_string()))?;
+
+        let mut credential = passkey.credential()?;
+
+        let result = match state {
+            AuthenticationState::SecondFactor { user_uuid, state } => {
+                if passkey.user_uuid != user_uuid {
+                    return Err(Error::Unauthorized("Unknown passkey".to_string()));
+                }
+
+                app_state
+                    .webauthn
+                    .finish_passkey_authentication(response, &state)
+            }
+            AuthenticationState::Passwordless { state } => {
+                let (user_uuid, _) = app_state
+                    .webauthn
+                    .identify_discoverable_authentication(response)
+                    .map_err(|e| Error::Unauthorized(e.to_string()))?;
+
+                if passkey.user_uuid != user_uuid {
+                    return Err(Error::Unauthorized("Unknown passkey".to_string()));
+                }
+
+                app_state.webauthn.finish_discoverable_authentication(
+                    response,
+                    state,
+                    &[DiscoverableKey::from(&credential)],
+                )
+            }
+        }
+        .map_err(|e| Error::Unauthorized(e.to_string()))?;
+
+        credential.update_credential(&result);
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        update(passkeys::table)
+            .filter(dsl::uuid.eq(passkey.uuid))
+            .set((
+                dsl::credential.eq(serde_json::to_string(&credential)?),
+                dsl::last_used.eq(current_time),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(passkey.user_uuid)
+    }
+}
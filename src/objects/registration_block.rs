@@ -0,0 +1,142 @@
+use std::{
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable,
+    Selectable, SelectableHelper, delete, insert_into,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{Conn, error::Error, schema::registration_blocks};
+
+use super::load_or_empty;
+
+/// Blocks registering with an email address, every address of a domain (`@example.com`) or from an IP address
+#[derive(Serialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = registration_blocks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RegistrationBlock {
+    uuid: Uuid,
+    kind: String,
+    value: String,
+    reason: Option<String>,
+    created_at: i64,
+}
+
+impl RegistrationBlock {
+    pub async fn fetch_all(conn: &mut Conn) -> Result<Vec<Self>, Error> {
+        use registration_blocks::dsl;
+        let registration_blocks: Vec<RegistrationBlock> = load_or_empty(
+            dsl::registration_blocks
+                .order(dsl::created_at.desc())
+                .select(RegistrationBlock::as_select())
+                .load(conn)
+                .await,
+        )?;
+
+        Ok(registration_blocks)
+    }
+
+    /// Returns the existing block unchanged when the value is already blocked
+    pub async fn new(
+        conn: &mut Conn,
+        kind: String,
+        value: String,
+        reason: Option<String>,
+    ) -> Result<Self, Error> {
+        let value = match kind.as_str() {
+            "email" => value.trim().to_lowercase(),
+            "ip" => value
+                .trim()
+                .parse::<IpAddr>()
+                .map_err(|_| Error::BadRequest("Invalid IP address".to_string()))?
+                .to_string(),
+            _ => {
+                return Err(Error::BadRequest("Kind has to be email or ip".to_string()));
+            }
+        };
+
+        if value.is_empty() || value.len() > 256 {
+            return Err(Error::BadRequest(
+                "Value must be between 1 and 256 characters".to_string(),
+            ));
+        }
+
+        let registration_block = RegistrationBlock {
+            uuid: Uuid::now_v7(),
+            kind,
+            value,
+            reason,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+        };
+
+        use registration_blocks::dsl;
+        let inserted: Option<RegistrationBlock> = insert_into(registration_blocks::table)
+            .values(&registration_block)
+            .on_conflict((dsl::kind, dsl::value))
+            .do_nothing()
+            .returning(RegistrationBlock::as_returning())
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        if let Some(inserted) = inserted {
+            return Ok(inserted);
+        }
+
+        let existing: RegistrationBlock = dsl::registration_blocks
+            .filter(dsl::kind.eq(&registration_block.kind))
+            .filter(dsl::value.eq(&registration_block.value))
+            .select(RegistrationBlock::as_select())
+            .get_result(conn)
+            .await?;
+
+        Ok(existing)
+    }
+
+    pub async fn delete(conn: &mut Conn, uuid: Uuid) -> Result<(), Error> {
+        let deleted = delete(registration_blocks::table)
+            .filter(registration_blocks::uuid.eq(uuid))
+            .execute(conn)
+            .await?;
+
+        if deleted == 0 {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns `Forbidden` if registering with this email address or from this IP address is blocked
+    pub async fn check(conn: &mut Conn, email: &str, ip: IpAddr) -> Result<(), Error> {
+        let email = email.trim().to_lowercase();
+
+        let domain = email
+            .rsplit_once('@')
+            .map(|(_, domain)| format!("@{domain}"))
+            .unwrap_or_default();
+
+        use registration_blocks::dsl;
+        let blocked: Option<Uuid> = dsl::registration_blocks
+            .filter(
+                dsl::kind
+                    .eq("email")
+                    .and(dsl::value.eq(&email).or(dsl::value.eq(&domain))),
+            )
+            .or_filter(dsl::kind.eq("ip").and(dsl::value.eq(ip.to_string())))
+            .select(dsl::uuid)
+            .first(conn)
+            .await
+            .optional()?;
+
+        if blocked.is_some() {
+            return Err(Error::Forbidden("Registration is not allowed".to_string()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper, insert_into,
+    result::{DatabaseErrorKind, Error as DieselError},
+    update,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    Conn,
+    cache::Cache,
+    error::Error,
+    schema::{messages, reports},
+};
+
+use super::{Channel, Guild, Member, User, load_or_empty, message::MessageBuilder};
+
+const CATEGORIES: &[&str] = &[
+    "spam",
+    "harassment",
+    "hate",
+    "violence",
+    "sexual",
+    "self_harm",
+    "illegal",
+    "other",
+];
+
+const STATUSES: &[&str] = &["open", "actioned", "dismissed"];
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    pub category: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportResolution {
+    pub status: String,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    pub status: Option<String>,
+    pub start: Option<i64>,
+    pub amount: Option<i64>,
+}
+
+/// Report of a message, user or guild, message reports go to the moderators of the guild and everything is
+/// visible to instance administrators
+#[derive(Serialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = reports)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Report {
+    pub uuid: Uuid,
+    reporter_uuid: Uuid,
+    /// `message`, `user` or `guild`
+    kind: String,
+    target_uuid: Uuid,
+    /// Guild whose moderators can see the report, only set for message reports
+    pub guild_uuid: Option<Uuid>,
+    category: String,
+    reason: Option<String>,
+    /// Author and content of the message when it was reported, kept in case it is edited or deleted
+    message_author_uuid: Option<Uuid>,
+    message_content: Option<String>,
+    /// `open`, `actioned` or `dismissed`
+    status: String,
+    resolver_uuid: Option<Uuid>,
+    resolution_note: Option<String>,
+    created_at: i64,
+    resolved_at: Option<i64>,
+}
+
+impl Report {
+    pub async fn fetch_one(conn: &mut Conn, report_uuid: Uuid) -> Result<Self, Error> {
+        use reports::dsl;
+        let report: Report = dsl::reports
+            .filter(dsl::uuid.eq(report_uuid))
+            .select(Report::as_select())
+            .get_result(conn)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Lists reports newest first, `guild_uuid` limits it to the queue of a guild
+    pub async fn fetch_amount(
+        conn: &mut Conn,
+        guild_uuid: Option<Uuid>,
+        status: Option<String>,
+        offset: i64,
+        amount: i64,
+    ) -> Result<Vec<Self>, Error> {
+        use reports::dsl;
+        let mut select = dsl::reports
+            .select(Report::as_select())
+            .order(dsl::uuid.desc())
+            .limit(amount)
+            .offset(offset)
+            .into_boxed();
+
+        if let Some(guild_uuid) = guild_uuid {
+            select = select.filter(dsl::guild_uuid.eq(guild_uuid));
+        }
+
+        if let Some(status) = status {
+            select = select.filter(dsl::status.eq(status));
+        }
+
+        let reports: Vec<Report> = load_or_empty(select.load(conn).await)?;
+
+        Ok(reports)
+    }
+
+    pub async fn report_message(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        reporter_uuid: Uuid,
+        channel_uuid: Uuid,
+        message_uuid: Uuid,
+        request: ReportRequest,
+    ) -> Result<Self, Error> {
+        let channel = Channel::fetch_one(conn, cache_pool, channel_uuid).await?;
+
+        Member::check_membership(conn, reporter_uuid, channel.guild_uuid).await?;
+
+        use messages::dsl;
+        let message: MessageBuilder = dsl::messages
+            .filter(dsl::uuid.eq(message_uuid))
+            .filter(dsl::channel_uuid.eq(channel_uuid))
+            .select(MessageBuilder::as_select())
+            .get_result(conn)
+            .await?;
+
+        if message.user_uuid == reporter_uuid {
+            return Err(Error::BadRequest(
+                "Can't report your own message".to_string(),
+            ));
+        }
+
+        Self::new(
+            conn,
+            reporter_uuid,
+            "message",
+            message_uuid,
+            Some(channel.guild_uuid),
+            request,
+            Some((message.user_uuid, message.message)),
+        )
+        .await
+    }
+
+    pub async fn report_user(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        reporter_uuid: Uuid,
+        user_uuid: Uuid,
+        request: ReportRequest,
+    ) -> Result<Self, Error> {
+        if user_uuid == reporter_uuid {
+            return Err(Error::BadRequest("Can't report yourself".to_string()));
+        }
+
+        User::fetch_one(conn, cache_pool, user_uuid).await?;
+
+        Self::new(conn, reporter_uuid, "user", user_uuid, None, request, None).await
+    }
+
+    pub async fn report_guild(
+        conn: &mut Conn,
+        reporter_uuid: Uuid,
+        guild_uuid: Uuid,
+        request: ReportRequest,
+    ) -> Result<Self, Error> {
+        Guild::fetch_one(conn, guild_uuid).await?;
+
+        Self::new(
+            conn,
+            reporter_uuid,
+            "guild",
+            guild_uuid,
+            None,
+            request,
+            None,
+        )
+        .await
+    }
+
+    async fn new(
+        conn: &mut Conn,
+        reporter_uuid: Uuid,
+        kind: &str,
+        target_uuid: Uuid,
+        guild_uuid: Option<Uuid>,
+        request: ReportRequest,
+        message: Option<(Uuid, String)>,
+    ) -> Result<Self, Error> {
+        if !CATEGORIES.contains(&request.category.as_str()) {
+            return Err(Error::BadRequest(format!(
+                "Category has to be one of: {}",
+                CATEGORIES.join(", ")
+            )));
+        }
+
+        let reason = request
+            .reason
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty());
+
+        if reason.as_ref().is_some_and(|reason| reason.len() > 1000) {
+            return Err(Error::BadRequest(
+                "Reason can't be longer than 1000 characters".to_string(),
+            ));
+        }
+
+        let (message_author_uuid, message_content) = message.unzip();
+
+        let report = Report {
+            uuid: Uuid::now_v7(),
+            reporter_uuid,
+            kind: kind.to_string(),
+            target_uuid,
+            guild_uuid,
+            category: request.category,
+            reason,
+            message_author_uuid,
+            message_content,
+            status: "open".to_string(),
+            resolver_uuid: None,
+            resolution_note: None,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+            resolved_at: None,
+        };
+
+        // Only one open report per reporter and target
+        let report: Option<Report> = insert_into(reports::table)
+            .values(&report)
+            .on_conflict_do_nothing()
+            .returning(Report::as_returning())
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        report.ok_or(Error::BadRequest(
+            "You already have an open report about this".to_string(),
+        ))
+    }
+
+    /// Moves the report to another status, setting it back to `open` clears the resolution
+    pub async fn resolve(
+        &mut self,
+        conn: &mut Conn,
+        resolver_uuid: Uuid,
+        resolution: ReportResolution,
+    ) -> Result<(), Error> {
+        if !STATUSES.contains(&resolution.status.as_str()) {
+            return Err(Error::BadRequest(format!(
+                "Status has to be one of: {}",
+                STATUSES.join(", ")
+            )));
+        }
+
+        if resolution.status == self.status {
+            return Err(Error::BadRequest(format!(
+                "Report is already {}",
+                self.status
+            )));
+        }
+
+        let note = resolution
+            .note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty());
+
+        if note.as_ref().is_some_and(|note| note.len() > 1000) {
+            return Err(Error::BadRequest(
+                "Note can't be longer than 1000 characters".to_string(),
+            ));
+        }
+
+        let (resolver_uuid, resolved_at) = if resolution.status == "open" {
+            (None, None)
+        } else {
+            (
+                Some(resolver_uuid),
+                Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+            )
+        };
+
+        use reports::dsl;
+        update(reports::table)
+            .filter(dsl::uuid.eq(self.uuid))
+            .set((
+                dsl::status.eq(&resolution.status),
+                dsl::resolver_uuid.eq(resolver_uuid),
+                dsl::resolution_note.eq(&note),
+                dsl::resolved_at.eq(resolved_at),
+            ))
+            .execute(conn)
+            .await
+            .map_err(|error| match error {
+                // Reopening while the reporter has made a newer report about the same target
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    Error::BadRequest(
+                        "The reporter already has an open report about this".to_string(),
+                    )
+                }
+                error => error.into(),
+            })?;
+
+        self.status = resolution.status;
+        self.resolver_uuid = resolver_uuid;
+        self.resolution_note = note;
+        self.resolved_at = resolved_at;
+
+        Ok(())
+    }
+
+    /// Leaves out who made the report, guild moderators could otherwise retaliate against the reporter
+    pub fn for_guild_moderators(self) -> GuildReport {
+        GuildReport {
+            uuid: self.uuid,
+            kind: self.kind,
+            target_uuid: self.target_uuid,
+            guild_uuid: self.guild_uuid,
+            category: self.category,
+            reason: self.reason,
+            message_author_uuid: self.message_author_uuid,
+            message_content: self.message_content,
+            status: self.status,
+            resolver_uuid: self.resolver_uuid,
+            resolution_note: self.resolution_note,
+            created_at: self.created_at,
+            resolved_at: self.resolved_at,
+        }
+    }
+}
+
+/// Report in the queue of a guild, only instance administrators see the reporter
+#[derive(Serialize)]
+pub struct GuildReport {
+    uuid: Uuid,
+    kind: String,
+    target_uuid: Uuid,
+    guild_uuid: Option<Uuid>,
+    category: String,
+    reason: Option<String>,
+    message_author_uuid: Option<Uuid>,
+    message_content: Option<String>,
+    status: String,
+    resolver_uuid: Option<Uuid>,
+    resolution_note: Option<String>,
+    created_at: i64,
+    resolved_at: Option<i64>,
+}
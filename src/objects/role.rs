@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use diesel::query_dsl::BelongingToDsl;
 use diesel::{
     Associations, ExpressionMethods, Identifiable, Insertable, QueryDsl, Queryable, Selectable,
@@ -9,9 +11,10 @@ use uuid::Uuid;
 
 use crate::{
     Conn,
+    cache::{Cache, CacheKey},
     error::Error,
     schema::{role_members, roles},
-    utils::{CacheFns, order_by_is_above},
+    utils::order_by_is_above,
 };
 
 use super::{HasIsAbove, HasUuid, load_or_empty, member::MemberBuilder};
@@ -22,7 +25,7 @@ use super::{HasIsAbove, HasUuid, load_or_empty, member::MemberBuilder};
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Role {
     uuid: Uuid,
-    guild_uuid: Uuid,
+    pub guild_uuid: Uuid,
     name: String,
     color: i32,
     is_above: Option<Uuid>,
@@ -66,15 +69,26 @@ impl Role {
         Ok(roles)
     }
 
+    /// Roles of several guilds at once
+    pub async fn fetch_all_in(conn: &mut Conn, guild_uuids: &[Uuid]) -> Result<Vec<Self>, Error> {
+        use roles::dsl;
+        let roles: Vec<Role> = load_or_empty(
+            dsl::roles
+                .filter(dsl::guild_uuid.eq_any(guild_uuids))
+                .select(Role::as_select())
+                .load(conn)
+                .await,
+        )?;
+
+        Ok(roles)
+    }
+
     pub async fn fetch_from_member(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         member: &MemberBuilder,
     ) -> Result<Vec<Self>, Error> {
-        if let Ok(roles) = cache_pool
-            .get_cache_key(format!("{}_roles", member.uuid))
-            .await
-        {
+        if let Ok(Some(roles)) = cache_pool.get(&CacheKey::MemberRoles(member.uuid)).await {
             return Ok(roles);
         }
 
@@ -87,12 +101,77 @@ impl Role {
         )?;
 
         cache_pool
-            .set_cache_key(format!("{}_roles", member.uuid), roles.clone(), 300)
+            .set(&CacheKey::MemberRoles(member.uuid), &roles, 300)
             .await?;
 
         Ok(roles)
     }
 
+    /// Roles of several members with one cache round-trip and at most one query, keyed by member uuid
+    pub async fn fetch_from_members(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        members: &[MemberBuilder],
+    ) -> Result<HashMap<Uuid, Vec<Self>>, Error> {
+        let cached: Vec<Option<Vec<Role>>> = cache_pool
+            .get_many(
+                &members
+                    .iter()
+                    .map(|member| CacheKey::MemberRoles(member.uuid))
+                    .collect::<Vec<CacheKey>>(),
+            )
+            .await?;
+
+        let mut roles: HashMap<Uuid, Vec<Role>> = HashMap::new();
+        let mut missing = vec![];
+
+        for (member, cached_roles) in members.iter().zip(cached) {
+            match cached_roles {
+                Some(cached_roles) => {
+                    roles.insert(member.uuid, cached_roles);
+                }
+                None => missing.push(member.uuid),
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(roles);
+        }
+
+        use role_members::dsl;
+        let member_roles: Vec<(Uuid, Role)> = load_or_empty(
+            dsl::role_members
+                .filter(dsl::member_uuid.eq_any(&missing))
+                .inner_join(roles::table)
+                .select((dsl::member_uuid, Role::as_select()))
+                .load(conn)
+                .await,
+        )?;
+
+        let mut fetched: HashMap<Uuid, Vec<Role>> = missing
+            .into_iter()
+            .map(|member_uuid| (member_uuid, vec![]))
+            .collect();
+
+        for (member_uuid, role) in member_roles {
+            fetched.entry(member_uuid).or_default().push(role);
+        }
+
+        cache_pool
+            .set_many(
+                &fetched
+                    .iter()
+                    .map(|(member_uuid, roles)| (CacheKey::MemberRoles(*member_uuid), roles))
+                    .collect::<Vec<(CacheKey, &Vec<Role>)>>(),
+                300,
+            )
+            .await?;
+
+        roles.extend(fetched);
+
+        Ok(roles)
+    }
+
     pub async fn fetch_one(conn: &mut Conn, role_uuid: Uuid) -> Result<Self, Error> {
         use roles::dsl;
         let role: Role = dsl::roles
@@ -108,7 +187,12 @@ impl Role {
         Permissions::fetch_permissions(self.permissions)
     }
 
-    pub async fn new(conn: &mut Conn, guild_uuid: Uuid, name: String) -> Result<Self, Error> {
+    pub async fn new(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        guild_uuid: Uuid,
+        name: String,
+    ) -> Result<Self, Error> {
         let role_uuid = Uuid::now_v7();
 
         let roles = Self::fetch_all(conn, guild_uuid).await?;
@@ -140,6 +224,14 @@ impl Role {
                 .await?;
         }
 
+        let mut stale = vec![CacheKey::GuildRoles(guild_uuid)];
+
+        if let Some(old_last_role) = last_role {
+            stale.push(CacheKey::Role(old_last_role.uuid));
+        }
+
+        cache_pool.del(&stale).await?;
+
         Ok(new_role)
     }
 }
@@ -164,6 +256,10 @@ pub enum Permissions {
     BanMember = 128,
     /// Lets users kick members
     KickMember = 256,
+    /// Lets users send messages without being limited by channel slowmode
+    BypassSlowmode = 512,
+    /// Lets users view and resolve reports made in the guild
+    ManageReports = 1024,
 }
 
 impl Permissions {
@@ -178,6 +274,8 @@ impl Permissions {
             Self::ManageMember,
             Self::BanMember,
             Self::KickMember,
+            Self::BypassSlowmode,
+            Self::ManageReports,
         ];
 
         all_perms
@@ -0,0 +1,316 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{
+    ExpressionMethods, OptionalExtension, QueryDsl, Queryable, Selectable, SelectableHelper,
+    delete, update,
+};
+use diesel_async::RunQueryDsl;
+use log::warn;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState, Conn,
+    cache::Cache,
+    error::Error,
+    schema::{access_tokens, refresh_tokens, used_refresh_tokens},
+    utils::{disconnect_session_sockets, hash_token},
+};
+
+use super::{EmailTemplate, QueuedEmail, load_or_empty};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = refresh_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct SessionBuilder {
+    session_uuid: Uuid,
+    device_name: String,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    created_at: i64,
+    last_used: i64,
+}
+
+impl SessionBuilder {
+    fn build(self, current_session: Option<Uuid>) -> Session {
+        Session {
+            current: current_session == Some(self.session_uuid),
+            uuid: self.session_uuid,
+            device_name: self.device_name,
+            user_agent: self.user_agent,
+            ip_address: self.ip_address,
+            created_at: self.created_at,
+            last_used: self.last_used,
+        }
+    }
+}
+
+/// A logged in device, identified by a uuid that stays the same when its refresh token is rotated
+#[derive(Serialize)]
+pub struct Session {
+    pub uuid: Uuid,
+    device_name: String,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    created_at: i64,
+    last_used: i64,
+    /// True for the session the request was made with
+    current: bool,
+}
+
+impl Session {
+    /// Returns the uuid of the session the access token belongs to
+    pub async fn current_uuid(
+        conn: &mut Conn,
+        app_state: &AppState,
+        access_token: &str,
+    ) -> Result<Uuid, Error> {
+        let session_uuid: Uuid = access_tokens::table
+            .inner_join(refresh_tokens::table)
+            .filter(access_tokens::token.eq(hash_token(&app_state.token_pepper, access_token)))
+            .select(refresh_tokens::session_uuid)
+            .get_result(conn)
+            .await?;
+
+        Ok(session_uuid)
+    }
+
+    pub async fn fetch_all(
+        conn: &mut Conn,
+        user_uuid: Uuid,
+        current_session: Option<Uuid>,
+    ) -> Result<Vec<Self>, Error> {
+        use refresh_tokens::dsl;
+        let sessions: Vec<SessionBuilder> = load_or_empty(
+            dsl::refresh_tokens
+                .filter(dsl::uuid.eq(user_uuid))
+                .order(dsl::last_used.desc())
+                .select(SessionBuilder::as_select())
+                .load(conn)
+                .await,
+        )?;
+
+        Ok(sessions
+            .into_iter()
+            .map(|session| session.build(current_session))
+            .collect())
+    }
+
+    pub async fn rename(
+        conn: &mut Conn,
+        user_uuid: Uuid,
+        session_uuid: Uuid,
+        device_name: String,
+    ) -> Result<(), Error> {
+        let device_name = device_name.trim();
+
+        if device_name.is_empty() || device_name.len() > 64 {
+            return Err(Error::BadRequest(
+                "Device name must be between 1 and 64 characters".to_string(),
+            ));
+        }
+
+        use refresh_tokens::dsl;
+        let updated = update(refresh_tokens::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .filter(dsl::session_uuid.eq(session_uuid))
+            .set(dsl::device_name.eq(device_name))
+            .execute(conn)
+            .await?;
+
+        if updated == 0 {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+
+        Ok(())
+    }
+
+    /// Logs the session out, its access token is removed with the refresh token and its sockets are closed
+    pub async fn revoke(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        user_uuid: Uuid,
+        session_uuid: Uuid,
+    ) -> Result<(), Error> {
+        use refresh_tokens::dsl;
+        let deleted = delete(refresh_tokens::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .filter(dsl::session_uuid.eq(session_uuid))
+            .execute(conn)
+            .await?;
+
+        if deleted == 0 {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+
+        disconnect_session_sockets(cache_pool, user_uuid, &[session_uuid]).await
+    }
+
+    /// Logs out every session of the user except the given one, returns how many were logged out
+    pub async fn revoke_others(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        user_uuid: Uuid,
+        current_session: Uuid,
+    ) -> Result<usize, Error> {
+        use refresh_tokens::dsl;
+        let revoked: Vec<Uuid> = load_or_empty(
+            delete(refresh_tokens::table)
+                .filter(dsl::uuid.eq(user_uuid))
+                .filter(dsl::session_uuid.ne(current_session))
+                .returning(dsl::session_uuid)
+                .get_results(conn)
+                .await,
+        )?;
+
+        disconnect_session_sockets(cache_pool, user_uuid, &revoked).await?;
+
+        Ok(revoked.len())
+    }
+
+    /// Logs out the least recently used sessions of the user until at most `max_sessions` remain
+    pub async fn enforce_limit(
+        conn: &mut Conn,
+        user_uuid: Uuid,
+        max_sessions: i64,
+    ) -> Result<(), Error> {
+        use refresh_tokens::dsl;
+        let excess: Vec<Uuid> = load_or_empty(
+            dsl::refresh_tokens
+                .filter(dsl::uuid.eq(user_uuid))
+                .order((dsl::last_used.desc(), dsl::session_uuid.desc()))
+                .offset(max_sessions)
+                .select(dsl::session_uuid)
+                .load(conn)
+                .await,
+        )?;
+
+        if !excess.is_empty() {
+            delete(refresh_tokens::table)
+                .filter(dsl::session_uuid.eq_any(excess))
+                .execute(conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes sessions whose refresh token has expired or that have been idle for longer than allowed
+    pub async fn prune(app_state: &AppState) -> Result<usize, Error> {
+        let mut conn = app_state.pool.get().await?;
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        use refresh_tokens::dsl;
+        let mut pruned = delete(refresh_tokens::table)
+            .filter(dsl::issued_at.lt(current_time - app_state.config.auth.refresh_token_lifetime))
+            .execute(&mut conn)
+            .await?;
+
+        // Rotated tokens past the refresh lifetime would be rejected as expired anyway
+        delete(used_refresh_tokens::table)
+            .filter(
+                used_refresh_tokens::rotated_at
+                    .lt(current_time - app_state.config.auth.refresh_token_lifetime),
+            )
+            .execute(&mut conn)
+            .await?;
+
+        if let Some(idle_session_expiry) = app_state.config.auth.idle_session_expiry {
+            pruned += delete(refresh_tokens::table)
+                .filter(dsl::last_used.lt(current_time - idle_session_expiry))
+                .execute(&mut conn)
+                .await?;
+        }
+
+        Ok(pruned)
+    }
+
+    /// Handles a refresh token that is no longer current, if it was rotated before the whole session is
+    /// logged out and the user is notified since either they or someone who stole the token used it again
+    ///
+    /// Returns true if the token was rotated within `refresh_token_reuse_grace`, the session is kept and the newer token stays valid
+    pub async fn revoke_reused(
+        conn: &mut Conn,
+        app_state: &'static AppState,
+        refresh_token_hash: &str,
+    ) -> Result<bool, Error> {
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let used: Option<(Uuid, i64)> = used_refresh_tokens::table
+            .filter(used_refresh_tokens::token.eq(refresh_token_hash))
+            .select((
+                used_refresh_tokens::session_uuid,
+                used_refresh_tokens::rotated_at,
+            ))
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        let Some((session_uuid, rotated_at)) = used else {
+            return Ok(false);
+        };
+
+        if within_reuse_grace(
+            rotated_at,
+            current_time,
+            app_state.config.auth.refresh_token_reuse_grace,
+        ) {
+            return Ok(true);
+        }
+
+        use refresh_tokens::dsl;
+        let revoked: Option<(Uuid, String)> = delete(refresh_tokens::table)
+            .filter(dsl::session_uuid.eq(session_uuid))
+            .returning((dsl::uuid, dsl::device_name))
+            .get_result(conn)
+            .await
+            .optional()?;
+
+        let Some((user_uuid, device_name)) = revoked else {
+            return Ok(false);
+        };
+
+        warn!("refresh token reuse detected for session {session_uuid} of {user_uuid}");
+
+        disconnect_session_sockets(&app_state.cache_pool, user_uuid, &[session_uuid]).await?;
+
+        let reset_page = app_state.config.web.frontend_url.join("reset-password")?;
+
+        QueuedEmail::enqueue(
+            conn,
+            app_state,
+            user_uuid,
+            EmailTemplate::SessionReuse,
+            vec![
+                ("device_name", device_name),
+                ("link", reset_page.to_string()),
+            ],
+        )
+        .await?;
+
+        Ok(false)
+    }
+}
+
+/// Concurrent refreshes from several tabs present the old token right after it was rotated, that isn't treated as reuse
+fn within_reuse_grace(rotated_at: i64, current_time: i64, grace: i64) -> bool {
+    current_time - rotated_at <= grace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse_within_grace_is_allowed() {
+        assert!(within_reuse_grace(1000, 1000, 10));
+        assert!(within_reuse_grace(1000, 1010, 10));
+    }
+
+    #[test]
+    fn reuse_after_grace_is_detected() {
+        assert!(!within_reuse_grace(1000, 1011, 10));
+        assert!(!within_reuse_grace(1000, 1001, 0));
+    }
+}
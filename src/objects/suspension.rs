@@ -0,0 +1,140 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::{ExpressionMethods, QueryDsl, delete, update};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    Conn,
+    cache::Cache,
+    error::Error,
+    schema::{refresh_tokens, users},
+    utils::disconnect_sockets,
+};
+
+/// Instance wide suspension of an account, suspended users can't log in or use existing sessions
+#[derive(Serialize, Clone)]
+pub struct Suspension {
+    pub suspended_at: i64,
+    /// Unix timestamp the suspension ends at, `None` if it doesn't end
+    pub suspended_until: Option<i64>,
+    pub reason: Option<String>,
+}
+
+impl Suspension {
+    /// Builds the suspension from the `users` columns, returns `None` if the user isn't suspended or it has expired
+    pub fn from_columns(
+        suspended_at: Option<i64>,
+        suspended_until: Option<i64>,
+        reason: Option<String>,
+    ) -> Result<Option<Self>, Error> {
+        let Some(suspended_at) = suspended_at else {
+            return Ok(None);
+        };
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        if suspended_until.is_some_and(|until| until <= current_time) {
+            return Ok(None);
+        }
+
+        Ok(Some(Suspension {
+            suspended_at,
+            suspended_until,
+            reason,
+        }))
+    }
+
+    pub async fn fetch(conn: &mut Conn, user_uuid: Uuid) -> Result<Option<Self>, Error> {
+        use users::dsl;
+        let (suspended_at, suspended_until, reason): (Option<i64>, Option<i64>, Option<String>) =
+            dsl::users
+                .filter(dsl::uuid.eq(user_uuid))
+                .select((
+                    dsl::suspended_at,
+                    dsl::suspended_until,
+                    dsl::suspension_reason,
+                ))
+                .get_result(conn)
+                .await?;
+
+        Self::from_columns(suspended_at, suspended_until, reason)
+    }
+
+    pub fn to_error(&self) -> Error {
+        let mut message = "Account is suspended".to_string();
+
+        if let Some(until) = self.suspended_until {
+            message.push_str(&format!(" until {until}"));
+        }
+
+        if let Some(reason) = &self.reason {
+            message.push_str(&format!(": {reason}"));
+        }
+
+        Error::Forbidden(message)
+    }
+
+    /// Suspends the user, revokes all their tokens and disconnects their sockets
+    pub async fn suspend(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        user_uuid: Uuid,
+        reason: Option<String>,
+        suspended_until: Option<i64>,
+    ) -> Result<Self, Error> {
+        if reason.as_ref().is_some_and(|reason| reason.len() > 512) {
+            return Err(Error::BadRequest(
+                "Reason can't be longer than 512 characters".to_string(),
+            ));
+        }
+
+        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        if suspended_until.is_some_and(|until| until <= current_time) {
+            return Err(Error::BadRequest(
+                "Suspension has to end in the future".to_string(),
+            ));
+        }
+
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .set((
+                dsl::suspended_at.eq(current_time),
+                dsl::suspended_until.eq(suspended_until),
+                dsl::suspension_reason.eq(&reason),
+            ))
+            .execute(conn)
+            .await?;
+
+        delete(refresh_tokens::table)
+            .filter(refresh_tokens::uuid.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        disconnect_sockets(cache_pool, user_uuid).await?;
+
+        Ok(Suspension {
+            suspended_at: current_time,
+            suspended_until,
+            reason,
+        })
+    }
+
+    pub async fn lift(conn: &mut Conn, user_uuid: Uuid) -> Result<(), Error> {
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .set((
+                dsl::suspended_at.eq(None::<i64>),
+                dsl::suspended_until.eq(None::<i64>),
+                dsl::suspension_reason.eq(None::<String>),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use argon2::{
+    PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use diesel::{ExpressionMethods, QueryDsl, delete, insert_into, update};
+use diesel_async::RunQueryDsl;
+use getrandom::fill;
+use serde::Serialize;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::{
+    AppState, Conn,
+    cache::CacheKey,
+    error::Error,
+    schema::{recovery_codes, users},
+    utils::generate_token,
+};
+
+use super::{Me, load_or_empty};
+
+const RECOVERY_CODE_AMOUNT: usize = 10;
+
+#[derive(Serialize)]
+pub struct TotpEnrolment {
+    /// Base32 encoded secret for manual entry
+    secret: String,
+    /// `otpauth://` provisioning URI, usually displayed as a QR code
+    uri: String,
+}
+
+pub struct Totp;
+
+impl Totp {
+    fn build(app_state: &AppState, secret: Vec<u8>, username: String) -> Result<TOTP, Error> {
+        // ':' is used as a separator in provisioning URIs
+        let issuer = app_state.config.instance.name.replace(':', "");
+
+        TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, Some(issuer), username)
+            .map_err(|e| Error::TotpError(e.to_string()))
+    }
+
+    fn decode(secret: String) -> Result<Vec<u8>, Error> {
+        Secret::Encoded(secret)
+            .to_bytes()
+            .map_err(|e| Error::TotpError(e.to_string()))
+    }
+
+    pub async fn is_enabled(conn: &mut Conn, user_uuid: Uuid) -> Result<bool, Error> {
+        use users::dsl;
+        let secret: Option<String> = dsl::users
+            .filter(dsl::uuid.eq(user_uuid))
+            .select(dsl::totp_secret)
+            .get_result(conn)
+            .await?;
+
+        Ok(secret.is_some())
+    }
+
+    /// Generates a new secret, it is kept in the cache until confirmed with a valid code
+    pub async fn start_enrolment(app_state: &AppState, me: &Me) -> Result<TotpEnrolment, Error> {
+        let mut buf = [0u8; 20];
+        fill(&mut buf)?;
+
+        let totp = Self::build(app_state, buf.to_vec(), me.username.clone())?;
+
+        let secret = totp.get_secret_base32();
+
+        app_state
+            .cache_pool
+            .set(&CacheKey::TotpEnrolment(me.uuid), &secret, 600)
+            .await?;
+
+        Ok(TotpEnrolment {
+            uri: totp.get_url(),
+            secret,
+        })
+    }
+
+    /// Enables two-factor authentication if `code` is valid for the pending secret, returns new recovery codes
+    pub async fn confirm_enrolment(
+        conn: &mut Conn,
+        app_state: &AppState,
+        me: &Me,
+        code: &str,
+    ) -> Result<Vec<String>, Error> {
+        let secret: String = app_state
+            .cache_pool
+            .get(&CacheKey::TotpEnrolment(me.uuid))
+            .await?
+            .ok_or(Error::BadRequest(
+                "No pending two-factor enrolment".to_string(),
+            ))?;
+
+        let totp = Self::build(
+            app_state,
+            Self::decode(secret.clone())?,
+            me.username.clone(),
+        )?;
+
+        if !totp.check_current(code.trim())? {
+            return Err(Error::Unauthorized("Invalid code".to_string()));
+        }
+
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(me.uuid))
+            .set(dsl::totp_secret.eq(secret))
+            .execute(conn)
+            .await?;
+
+        app_state
+            .cache_pool
+            .del(&[CacheKey::TotpEnrolment(me.uuid)])
+            .await?;
+
+        Self::generate_recovery_codes(conn, app_state, me.uuid).await
+    }
+
+    pub async fn disable(conn: &mut Conn, user_uuid: Uuid) -> Result<(), Error> {
+        use users::dsl;
+        update(users::table)
+            .filter(dsl::uuid.eq(user_uuid))
+            .set(dsl::totp_secret.eq(None::<String>))
+            .execute(conn)
+            .await?;
+
+        delete(recovery_codes::table)
+            .filter(recovery_codes::user_uuid.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Checks a TOTP or recovery code, recovery codes can only be used once
+    pub async fn verify(
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+        code: &str,
+    ) -> Result<(), Error> {
+        use users::dsl;
+        let (username, secret): (String, Option<String>) = dsl::users
+            .filter(dsl::uuid.eq(user_uuid))
+            .select((dsl::username, dsl::totp_secret))
+            .get_result(conn)
+            .await?;
+
+        let secret = secret.ok_or(Error::BadRequest(
+            "Two-factor authentication is not enabled".to_string(),
+        ))?;
+
+        let code = code.trim().replace([' ', '-'], "").to_lowercase();
+
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+            let totp = Self::build(app_state, Self::decode(secret)?, username)?;
+
+            // Codes stay valid for multiple steps, remember used ones so they can't be replayed
+            let used_key = CacheKey::TotpUsed {
+                user_uuid,
+                code: code.clone(),
+            };
+
+            // Marking the code as used has to be what accepts it, otherwise concurrent requests could both pass
+            if !totp.check_current(&code)?
+                || !app_state.cache_pool.set_nx(&used_key, &true, 90).await?
+            {
+                return Err(Error::Unauthorized("Invalid code".to_string()));
+            }
+
+            return Ok(());
+        }
+
+        use recovery_codes::dsl as rdsl;
+        let recovery_codes: Vec<(Uuid, String)> = load_or_empty(
+            rdsl::recovery_codes
+                .filter(rdsl::user_uuid.eq(user_uuid))
+                .select((rdsl::uuid, rdsl::code_hash))
+                .load(conn)
+                .await,
+        )?;
+
+        for (uuid, code_hash) in recovery_codes {
+            let parsed_hash = PasswordHash::new(&code_hash)
+                .map_err(|e| Error::PasswordHashError(e.to_string()))?;
+
+            if app_state
+                .argon2
+                .verify_password(code.as_bytes(), &parsed_hash)
+                .is_ok()
+            {
+                let deleted = delete(recovery_codes::table)
+                    .filter(rdsl::uuid.eq(uuid))
+                    .execute(conn)
+                    .await?;
+
+                // Another request used the same code in the meantime
+                if deleted == 0 {
+                    return Err(Error::Unauthorized("Invalid code".to_string()));
+                }
+
+                return Ok(());
+            }
+        }
+
+        Err(Error::Unauthorized("Invalid code".to_string()))
+    }
+
+    /// Used by sensitive actions, requires a valid code if the user has two-factor authentication enabled
+    pub async fn require(
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+        code: Option<&String>,
+    ) -> Result<(), Error> {
+        if !Self::is_enabled(conn, user_uuid).await? {
+            return Ok(());
+        }
+
+        let code = code.ok_or(Error::Unauthorized(
+            "Two-factor authentication code required".to_string(),
+        ))?;
+
+        Self::verify(conn, app_state, user_uuid, code).await
+    }
+
+    /// Replaces all recovery codes of the user, the plaintext codes are only returned here
+    pub async fn generate_recovery_codes(
+        conn: &mut Conn,
+        app_state: &AppState,
+        user_uuid: Uuid,
+    ) -> Result<Vec<String>, Error> {
+        use recovery_codes::dsl;
+        delete(recovery_codes::table)
+            .filter(dsl::user_uuid.eq(user_uuid))
+            .execute(conn)
+            .await?;
+
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_AMOUNT);
+
+        for _ in 0..RECOVERY_CODE_AMOUNT {
+            let code = generate_token::<5>()?;
+
+            let salt = SaltString::generate(&mut OsRng);
+
+            let code_hash = app_state
+                .argon2
+                .hash_password(code.as_bytes(), &salt)
+                .map_err(|e| Error::PasswordHashError(e.to_string()))?;
+
+            insert_into(recovery_codes::table)
+                .values((
+                    dsl::uuid.eq(Uuid::now_v7()),
+                    dsl::user_uuid.eq(user_uuid),
+                    dsl::code_hash.eq(code_hash.to_string()),
+                ))
+                .execute(conn)
+                .await?;
+
+            codes.push(format!("{}-{}", &code[..5], &code[5..]));
+        }
+
+        Ok(codes)
+    }
+
+    pub async fn remaining_recovery_codes(conn: &mut Conn, user_uuid: Uuid) -> Result<i64, Error> {
+        use recovery_codes::dsl;
+        let count: i64 = dsl::recovery_codes
+            .filter(dsl::user_uuid.eq(user_uuid))
+            .count()
+            .get_result(conn)
+            .await?;
+
+        Ok(count)
+    }
+}
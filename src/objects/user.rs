@@ -1,10 +1,18 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use diesel::{ExpressionMethods, QueryDsl, Queryable, Selectable, SelectableHelper};
 use diesel_async::RunQueryDsl;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{Conn, error::Error, objects::Me, schema::users, utils::CacheFns};
+use crate::{
+    Conn,
+    cache::{Cache, CacheKey},
+    error::Error,
+    objects::Me,
+    schema::users,
+};
 
 use super::load_or_empty;
 
@@ -19,10 +27,24 @@ pub struct UserBuilder {
     pronouns: Option<String>,
     about: Option<String>,
     online_status: i16,
+    is_deleted: bool,
 }
 
 impl UserBuilder {
     pub fn build(self) -> User {
+        if self.is_deleted {
+            return User {
+                uuid: self.uuid,
+                username: "deleted_user".to_string(),
+                display_name: Some("Deleted User".to_string()),
+                avatar: None,
+                pronouns: None,
+                about: None,
+                online_status: 0,
+                friends_since: None,
+            };
+        }
+
         User {
             uuid: self.uuid,
             username: self.username,
@@ -51,10 +73,10 @@ pub struct User {
 impl User {
     pub async fn fetch_one(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         user_uuid: Uuid,
     ) -> Result<Self, Error> {
-        if let Ok(cache_hit) = cache_pool.get_cache_key(user_uuid.to_string()).await {
+        if let Ok(Some(cache_hit)) = cache_pool.get(&CacheKey::User(user_uuid)).await {
             return Ok(cache_hit);
         }
 
@@ -68,15 +90,78 @@ impl User {
         let user = user_builder.build();
 
         cache_pool
-            .set_cache_key(user_uuid.to_string(), user.clone(), 1800)
+            .set(&CacheKey::User(user_uuid), &user, 1800)
             .await?;
 
         Ok(user)
     }
 
+    /// Fetches several users with one cache round-trip and at most one query, in the order of `user_uuids`
+    pub async fn fetch_many(
+        conn: &mut Conn,
+        cache_pool: &Cache,
+        user_uuids: &[Uuid],
+    ) -> Result<Vec<Self>, Error> {
+        let cached: Vec<Option<User>> = cache_pool
+            .get_many(
+                &user_uuids
+                    .iter()
+                    .map(|uuid| CacheKey::User(*uuid))
+                    .collect::<Vec<CacheKey>>(),
+            )
+            .await?;
+
+        let missing: Vec<Uuid> = user_uuids
+            .iter()
+            .zip(&cached)
+            .filter(|(_, user)| user.is_none())
+            .map(|(uuid, _)| *uuid)
+            .collect();
+
+        let mut fetched: HashMap<Uuid, User> = HashMap::new();
+
+        if !missing.is_empty() {
+            use users::dsl;
+            let user_builders: Vec<UserBuilder> = load_or_empty(
+                dsl::users
+                    .filter(dsl::uuid.eq_any(&missing))
+                    .select(UserBuilder::as_select())
+                    .load(conn)
+                    .await,
+            )?;
+
+            fetched = user_builders
+                .into_iter()
+                .map(|builder| {
+                    let user = builder.build();
+                    (user.uuid, user)
+                })
+                .collect();
+
+            cache_pool
+                .set_many(
+                    &fetched
+                        .values()
+                        .map(|user| (CacheKey::User(user.uuid), user))
+                        .collect::<Vec<(CacheKey, &User)>>(),
+                    1800,
+                )
+                .await?;
+        }
+
+        user_uuids
+            .iter()
+            .zip(cached)
+            .map(|(uuid, user)| {
+                user.or_else(|| fetched.get(uuid).cloned())
+                    .ok_or(diesel::result::Error::NotFound.into())
+            })
+            .collect()
+    }
+
     pub async fn fetch_one_with_friendship(
         conn: &mut Conn,
-        cache_pool: &redis::Client,
+        cache_pool: &Cache,
         me: &Me,
         user_uuid: Uuid,
     ) -> Result<Self, Error> {
@@ -2,7 +2,7 @@
 
 diesel::table! {
     access_tokens (token) {
-        #[max_length = 32]
+        #[max_length = 64]
         token -> Varchar,
         #[max_length = 64]
         refresh_token -> Varchar,
@@ -11,6 +11,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    admin_actions (uuid) {
+        uuid -> Uuid,
+        admin_uuid -> Nullable<Uuid>,
+        #[max_length = 64]
+        action -> Varchar,
+        target_uuid -> Nullable<Uuid>,
+        #[max_length = 1024]
+        details -> Nullable<Varchar>,
+        created_at -> Int8,
+    }
+}
+
 diesel::table! {
     channel_permissions (channel_uuid, role_uuid) {
         channel_uuid -> Uuid,
@@ -28,6 +41,25 @@ diesel::table! {
         #[max_length = 500]
         description -> Nullable<Varchar>,
         is_above -> Nullable<Uuid>,
+        slowmode -> Int4,
+    }
+}
+
+diesel::table! {
+    email_queue (uuid) {
+        uuid -> Uuid,
+        #[max_length = 100]
+        recipient -> Varchar,
+        #[max_length = 256]
+        subject -> Varchar,
+        text_body -> Text,
+        html_body -> Text,
+        attempts -> Int4,
+        next_attempt_at -> Int8,
+        #[max_length = 1024]
+        last_error -> Nullable<Varchar>,
+        failed -> Bool,
+        created_at -> Int8,
     }
 }
 
@@ -87,6 +119,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    instance_settings (key) {
+        #[max_length = 64]
+        key -> Varchar,
+        value -> Bool,
+    }
+}
+
 diesel::table! {
     invites (id) {
         #[max_length = 32]
@@ -107,6 +147,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    passkeys (uuid) {
+        uuid -> Uuid,
+        user_uuid -> Uuid,
+        #[max_length = 1024]
+        credential_id -> Varchar,
+        #[max_length = 32]
+        name -> Varchar,
+        credential -> Text,
+        created_at -> Int8,
+        last_used -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (uuid) {
+        uuid -> Uuid,
+        user_uuid -> Uuid,
+        #[max_length = 512]
+        code_hash -> Varchar,
+    }
+}
+
 diesel::table! {
     refresh_tokens (token) {
         #[max_length = 64]
@@ -115,6 +178,51 @@ diesel::table! {
         created_at -> Int8,
         #[max_length = 64]
         device_name -> Varchar,
+        session_uuid -> Uuid,
+        #[max_length = 256]
+        user_agent -> Nullable<Varchar>,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        last_used -> Int8,
+        issued_at -> Int8,
+    }
+}
+
+diesel::table! {
+    registration_blocks (uuid) {
+        uuid -> Uuid,
+        #[max_length = 16]
+        kind -> Varchar,
+        #[max_length = 256]
+        value -> Varchar,
+        #[max_length = 512]
+        reason -> Nullable<Varchar>,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    reports (uuid) {
+        uuid -> Uuid,
+        reporter_uuid -> Uuid,
+        #[max_length = 16]
+        kind -> Varchar,
+        target_uuid -> Uuid,
+        guild_uuid -> Nullable<Uuid>,
+        #[max_length = 32]
+        category -> Varchar,
+        #[max_length = 1000]
+        reason -> Nullable<Varchar>,
+        message_author_uuid -> Nullable<Uuid>,
+        #[max_length = 4000]
+        message_content -> Nullable<Varchar>,
+        #[max_length = 16]
+        status -> Varchar,
+        resolver_uuid -> Nullable<Uuid>,
+        #[max_length = 1000]
+        resolution_note -> Nullable<Varchar>,
+        created_at -> Int8,
+        resolved_at -> Nullable<Int8>,
     }
 }
 
@@ -137,6 +245,23 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    used_refresh_tokens (token) {
+        #[max_length = 64]
+        token -> Varchar,
+        session_uuid -> Uuid,
+        rotated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    user_blocks (blocker, blocked) {
+        blocker -> Uuid,
+        blocked -> Uuid,
+        blocked_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     users (uuid) {
         uuid -> Uuid,
@@ -158,11 +283,21 @@ diesel::table! {
         #[max_length = 200]
         about -> Nullable<Varchar>,
         online_status -> Int2,
+        #[max_length = 64]
+        totp_secret -> Nullable<Varchar>,
+        disabled -> Bool,
+        suspended_at -> Nullable<Int8>,
+        suspended_until -> Nullable<Int8>,
+        #[max_length = 512]
+        suspension_reason -> Nullable<Varchar>,
+        #[max_length = 16]
+        locale -> Nullable<Varchar>,
     }
 }
 
 diesel::joinable!(access_tokens -> refresh_tokens (refresh_token));
 diesel::joinable!(access_tokens -> users (uuid));
+diesel::joinable!(admin_actions -> users (admin_uuid));
 diesel::joinable!(channel_permissions -> channels (channel_uuid));
 diesel::joinable!(channel_permissions -> roles (role_uuid));
 diesel::joinable!(channels -> guilds (guild_uuid));
@@ -175,25 +310,37 @@ diesel::joinable!(invites -> guilds (guild_uuid));
 diesel::joinable!(invites -> users (user_uuid));
 diesel::joinable!(messages -> channels (channel_uuid));
 diesel::joinable!(messages -> users (user_uuid));
+diesel::joinable!(passkeys -> users (user_uuid));
+diesel::joinable!(recovery_codes -> users (user_uuid));
 diesel::joinable!(refresh_tokens -> users (uuid));
+diesel::joinable!(reports -> guilds (guild_uuid));
 diesel::joinable!(role_members -> guild_members (member_uuid));
 diesel::joinable!(role_members -> roles (role_uuid));
 diesel::joinable!(roles -> guilds (guild_uuid));
 
 diesel::allow_tables_to_appear_in_same_query!(
     access_tokens,
+    admin_actions,
     channel_permissions,
     channels,
+    email_queue,
     friend_requests,
     friends,
     guild_bans,
     guild_members,
     guilds,
     instance_permissions,
+    instance_settings,
     invites,
     messages,
+    passkeys,
+    recovery_codes,
     refresh_tokens,
+    registration_blocks,
+    reports,
     role_members,
     roles,
+    used_refresh_tokens,
+    user_blocks,
     users,
 );
@@ -0,0 +1,151 @@
+//! Prometheus metrics, recorded with the `metrics` macros and rendered by `GET /metrics`.
+//!
+//! Nothing is recorded unless metrics are enabled in the config, the macros are no-ops without a recorder.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use log::warn;
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+use crate::{AppState, error::Error, schema::email_queue};
+
+/// Upper bounds in seconds of the latency histograms
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Installs the global recorder, has to be called once before anything is recorded
+pub fn install() -> Result<PrometheusHandle, Error> {
+    Ok(PrometheusBuilder::new()
+        .set_buckets(LATENCY_BUCKETS)?
+        .install_recorder()?)
+}
+
+/// Counts requests and their latency per route, only matched routes are recorded to keep the label set bounded
+pub async fn track_requests(
+    matched_path: Option<MatchedPath>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+
+    let method = req.method().to_string();
+    let route = matched_path
+        .map(|path| path.as_str().to_string())
+        .unwrap_or("unmatched".to_string());
+
+    let response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+
+    counter!(
+        "http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    )
+    .increment(1);
+
+    histogram!(
+        "http_request_duration_seconds",
+        "method" => method,
+        "route" => route
+    )
+    .record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Records the latency of a cache database command
+pub fn record_cache_command(command: &'static str, start: Instant) {
+    histogram!("cache_command_duration_seconds", "command" => command)
+        .record(start.elapsed().as_secs_f64());
+}
+
+/// Counted when an error is turned into a response
+pub fn record_error(status: StatusCode) {
+    counter!("http_errors_total", "status" => status.as_u16().to_string()).increment(1);
+}
+
+pub fn record_message_sent() {
+    counter!("messages_sent_total").increment(1);
+}
+
+/// Counts an open socket until it is dropped
+pub struct SocketConnection;
+
+impl SocketConnection {
+    pub fn open() -> Self {
+        gauge!("websocket_connections").increment(1);
+
+        SocketConnection
+    }
+}
+
+impl Drop for SocketConnection {
+    fn drop(&mut self) {
+        gauge!("websocket_connections").decrement(1);
+    }
+}
+
+/// `GET /metrics` Prometheus metrics of this replica
+pub async fn metrics(
+    State(app_state): State<&'static AppState>,
+) -> Result<impl IntoResponse, Error> {
+    let handle = app_state
+        .metrics
+        .as_ref()
+        .ok_or(Error::InternalServerError(
+            "metrics recorder is not installed".to_string(),
+        ))?;
+
+    let status = app_state.pool.status();
+
+    gauge!("db_pool_max_size").set(status.max_size as f64);
+    gauge!("db_pool_size").set(status.size as f64);
+    gauge!("db_pool_available").set(status.available as f64);
+    gauge!("db_pool_waiting").set(status.waiting as f64);
+
+    // Gauges keep their last value if the database can't be reached, the pool gauges show why
+    match email_queue_depth(app_state).await {
+        Ok((pending, failed)) => {
+            gauge!("email_queue_pending").set(pending as f64);
+            gauge!("email_queue_failed").set(failed as f64);
+        }
+        Err(error) => warn!("failed to count queued emails: {error}"),
+    }
+
+    Ok((
+        StatusCode::OK,
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    ))
+}
+
+async fn email_queue_depth(app_state: &AppState) -> Result<(i64, i64), Error> {
+    let mut conn = app_state.pool.get().await?;
+
+    use email_queue::dsl;
+    let pending: i64 = dsl::email_queue
+        .filter(dsl::failed.eq(false))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+
+    let failed: i64 = dsl::email_queue
+        .filter(dsl::failed.eq(true))
+        .count()
+        .get_result(&mut conn)
+        .await?;
+
+    Ok((pending, failed))
+}
@@ -1,23 +1,35 @@
 use rand::seq::IndexedRandom;
-use std::sync::LazyLock;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+};
 
-use axum::body::Bytes;
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts},
+};
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use bindet::FileType;
 use diesel::{ExpressionMethods, QueryDsl};
 use diesel_async::RunQueryDsl;
 use getrandom::fill;
 use hex::encode;
+use hmac::{Hmac, Mac};
+use log::{error, warn};
 use regex::Regex;
-use serde::{Serialize, de::DeserializeOwned};
+use sha2::Sha256;
 use time::Duration;
+use tokio::{fs, io::AsyncWriteExt};
 use uuid::Uuid;
 
 use crate::{
-    Conn,
-    config::Config,
+    AppState, Conn,
+    cache::Cache,
+    config::{Auth, Config},
     error::Error,
-    objects::{HasIsAbove, HasUuid},
+    objects::{HasIsAbove, HasUuid, InstanceSettings, Suspension},
     schema::users,
     wordlist::{ADJECTIVES, ANIMALS},
 };
@@ -32,6 +44,9 @@ pub static USERNAME_REGEX: LazyLock<Regex> =
 pub static CHANNEL_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[a-z0-9_.-]+$").unwrap());
 
+pub static LOCALE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z]{2,3}(-[A-Za-z0-9]{2,8})?$").unwrap());
+
 pub static PASSWORD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[0-9a-f]{96}").unwrap());
 
 pub fn new_refresh_token_cookie(config: &Config, refresh_token: String) -> Cookie<'_> {
@@ -40,16 +55,135 @@ pub fn new_refresh_token_cookie(config: &Config, refresh_token: String) -> Cooki
         .secure(true)
         .same_site(SameSite::None)
         .path(config.web.backend_url.path().to_string())
-        .max_age(Duration::days(30))
+        .max_age(Duration::seconds(config.auth.cookie_max_age))
         .build()
 }
 
+/// IP address of the client making the request
+///
+/// Uses the header configured in `web.real_ip_header` when running behind a reverse proxy,
+/// entries left of the ones appended by `web.trusted_proxies` are set by the client and ignored
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts<&'static AppState> for ClientIp {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &&'static AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let web = &app_state.config.web;
+
+        if let Some(header) = &web.real_ip_header
+            && parts.headers.contains_key(header)
+        {
+            // Proxies may add another header line instead of appending to the existing one
+            let values = parts
+                .headers
+                .get_all(header)
+                .iter()
+                .map(|value| value.to_str())
+                .collect::<Result<Vec<_>, _>>()?
+                .join(",");
+
+            let ip = forwarded_ip(&values, web.trusted_proxies)
+                .ok_or(Error::BadRequest(format!("Invalid {header} header")))?;
+
+            return Ok(ClientIp(ip));
+        }
+
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| ClientIp(address.ip()))
+            .ok_or(Error::InternalServerError(
+                "Unable to determine client address".to_string(),
+            ))
+    }
+}
+
+/// Address added by the outermost trusted proxy, `None` if there are fewer entries than trusted proxies
+fn forwarded_ip(value: &str, trusted_proxies: usize) -> Option<IpAddr> {
+    value
+        .split(',')
+        .nth_back(trusted_proxies.checked_sub(1)?)
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+/// IP address and user agent of the client, recorded on sessions
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub ip: IpAddr,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequestParts<&'static AppState> for ClientInfo {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &&'static AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, app_state).await?;
+
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|user_agent| user_agent.chars().take(256).collect());
+
+        Ok(ClientInfo { ip, user_agent })
+    }
+}
+
 pub fn generate_token<const N: usize>() -> Result<String, getrandom::Error> {
     let mut buf = [0u8; N];
     fill(&mut buf)?;
     Ok(encode(buf))
 }
 
+/// Keyed hash of a token, only hashes are stored so leaked rows can't be used as sessions
+pub fn hash_token(pepper: &[u8], token: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(pepper).expect("HMAC accepts keys of any size");
+    mac.update(token.as_bytes());
+
+    encode(mac.finalize().into_bytes())
+}
+
+/// Returns the configured token pepper, reading it from `auth.token_pepper_file` or generating it there on first start
+///
+/// A generated pepper is only known to this replica, deployments with several replicas have to configure or share it
+pub async fn load_token_pepper(auth: &Auth) -> Result<Vec<u8>, Error> {
+    if let Some(pepper) = &auth.token_pepper {
+        return Ok(pepper.as_bytes().to_vec());
+    }
+
+    match fs::read_to_string(&auth.token_pepper_file).await {
+        Ok(pepper) => Ok(pepper.trim().as_bytes().to_vec()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            let pepper = generate_token::<32>()?;
+
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&auth.token_pepper_file)
+                .await?;
+
+            file.write_all(pepper.as_bytes()).await?;
+
+            warn!(
+                "generated a new token pepper at {}, when running several replicas set auth.token_pepper or share this file between them",
+                auth.token_pepper_file.display()
+            );
+
+            Ok(pepper.into_bytes())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
 pub fn image_check(icon: Bytes) -> Result<String, Error> {
     let buf = std::io::Cursor::new(icon);
 
@@ -76,6 +210,7 @@ pub async fn user_uuid_from_identifier(
         use users::dsl;
         let user_uuid = dsl::users
             .filter(dsl::email.eq(identifier))
+            .filter(dsl::is_deleted.eq(false))
             .select(dsl::uuid)
             .get_result(conn)
             .await?;
@@ -85,6 +220,7 @@ pub async fn user_uuid_from_identifier(
         use users::dsl;
         let user_uuid = dsl::users
             .filter(dsl::username.eq(identifier))
+            .filter(dsl::is_deleted.eq(false))
             .select(dsl::uuid)
             .get_result(conn)
             .await?;
@@ -102,6 +238,7 @@ pub async fn user_uuid_from_username(conn: &mut Conn, username: &String) -> Resu
         use users::dsl;
         let user_uuid = dsl::users
             .filter(dsl::username.eq(username))
+            .filter(dsl::is_deleted.eq(false))
             .select(dsl::uuid)
             .get_result(conn)
             .await?;
@@ -114,8 +251,19 @@ pub async fn user_uuid_from_username(conn: &mut Conn, username: &String) -> Resu
     }
 }
 
-pub async fn global_checks(conn: &mut Conn, config: &Config, user_uuid: Uuid) -> Result<(), Error> {
-    if config.instance.require_email_verification {
+pub async fn global_checks(
+    conn: &mut Conn,
+    app_state: &AppState,
+    user_uuid: Uuid,
+) -> Result<(), Error> {
+    let instance_settings =
+        InstanceSettings::get(conn, &app_state.cache_pool, &app_state.config).await?;
+
+    if let Some(suspension) = Suspension::fetch(conn, user_uuid).await? {
+        return Err(suspension.to_error());
+    }
+
+    if instance_settings.require_email_verification {
         use users::dsl;
         let email_verified: bool = dsl::users
             .filter(dsl::uuid.eq(user_uuid))
@@ -133,6 +281,49 @@ pub async fn global_checks(conn: &mut Conn, config: &Config, user_uuid: Uuid) ->
     Ok(())
 }
 
+/// Closes every socket the user has open, used when all of their sessions are revoked
+pub async fn disconnect_sockets(cache_pool: &Cache, user_uuid: Uuid) -> Result<(), Error> {
+    cache_pool
+        .publish(format!("{user_uuid}_disconnect"), String::new())
+        .await
+}
+
+/// Closes the sockets opened by the given sessions of the user, used when only some of them are logged out
+pub async fn disconnect_session_sockets(
+    cache_pool: &Cache,
+    user_uuid: Uuid,
+    session_uuids: &[Uuid],
+) -> Result<(), Error> {
+    if session_uuids.is_empty() {
+        return Ok(());
+    }
+
+    cache_pool
+        .publish(
+            format!("{user_uuid}_disconnect"),
+            serde_json::to_string(session_uuids)?,
+        )
+        .await
+}
+
+/// Tells every socket the user has open to load their block list again, used when they block or unblock someone
+pub async fn reload_blocks(cache_pool: &Cache, user_uuid: Uuid) -> Result<(), Error> {
+    cache_pool
+        .publish(format!("{user_uuid}_blocks"), String::new())
+        .await
+}
+
+/// Sends an event to every socket the user has open, used for events that aren't tied to a channel
+pub async fn publish_user_event(
+    cache_pool: &Cache,
+    user_uuid: Uuid,
+    payload: String,
+) -> Result<(), Error> {
+    cache_pool
+        .publish(format!("{user_uuid}_events"), payload)
+        .await
+}
+
 pub async fn order_by_is_above<T>(mut items: Vec<T>) -> Result<Vec<T>, Error>
 where
     T: HasUuid + HasIsAbove,
@@ -158,71 +349,36 @@ where
     Ok(ordered)
 }
 
-#[allow(async_fn_in_trait)]
-pub trait CacheFns {
-    async fn set_cache_key(
-        &self,
-        key: String,
-        value: impl Serialize,
-        expire: u32,
-    ) -> Result<(), Error>;
-    async fn get_cache_key<T>(&self, key: String) -> Result<T, Error>
-    where
-        T: DeserializeOwned;
-    async fn del_cache_key(&self, key: String) -> Result<(), Error>;
-}
-
-impl CacheFns for redis::Client {
-    async fn set_cache_key(
-        &self,
-        key: String,
-        value: impl Serialize,
-        expire: u32,
-    ) -> Result<(), Error> {
-        let mut conn = self.get_multiplexed_tokio_connection().await?;
-
-        let key_encoded = encode(key);
-
-        let value_json = serde_json::to_string(&value)?;
-
-        redis::cmd("SET")
-            .arg(&[key_encoded.clone(), value_json])
-            .exec_async(&mut conn)
-            .await?;
-
-        redis::cmd("EXPIRE")
-            .arg(&[key_encoded, expire.to_string()])
-            .exec_async(&mut conn)
-            .await?;
-
-        Ok(())
-    }
-
-    async fn get_cache_key<T>(&self, key: String) -> Result<T, Error>
-    where
-        T: DeserializeOwned,
-    {
-        let mut conn = self.get_multiplexed_tokio_connection().await?;
-
-        let key_encoded = encode(key);
-
-        let res: String = redis::cmd("GET")
-            .arg(key_encoded)
-            .query_async(&mut conn)
-            .await?;
-
-        Ok(serde_json::from_str(&res)?)
-    }
-
-    async fn del_cache_key(&self, key: String) -> Result<(), Error> {
-        let mut conn = self.get_multiplexed_tokio_connection().await?;
+/// Resolves once the process receives SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            error!("failed to listen for SIGINT: {error}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(error) => {
+                error!("failed to listen for SIGTERM: {error}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
 
-        let key_encoded = encode(key);
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-        Ok(redis::cmd("DEL")
-            .arg(key_encoded)
-            .query_async(&mut conn)
-            .await?)
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
     }
 }
 
@@ -234,3 +390,37 @@ pub fn generate_device_name() -> String {
 
     [*adjective, *animal].join(" ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_ip_ignores_client_supplied_entries() {
+        let value = "203.0.113.7, 198.51.100.2";
+
+        assert_eq!(
+            forwarded_ip(value, 1),
+            Some("198.51.100.2".parse().unwrap())
+        );
+        assert_eq!(forwarded_ip(value, 2), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(forwarded_ip(value, 3), None);
+        assert_eq!(forwarded_ip(value, 0), None);
+    }
+
+    #[test]
+    fn forwarded_ip_rejects_garbage() {
+        assert_eq!(forwarded_ip("1.2.3.4, not-an-ip", 1), None);
+        assert_eq!(forwarded_ip("::1", 1), Some("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn hash_token_is_deterministic_and_keyed() {
+        let hash = hash_token(b"pepper", "token");
+
+        assert_eq!(hash, hash_token(b"pepper", "token"));
+        assert_ne!(hash, hash_token(b"pepper", "other token"));
+        assert_ne!(hash, hash_token(b"other pepper", "token"));
+        assert_ne!(hash, "token");
+    }
+}